@@ -0,0 +1,56 @@
+use std::env;
+
+use anchor_chain::models::bedrock_embedding::{BedrockEmbedding, CohereInputType};
+use anchor_chain::{
+    BedrockEmbeddingModel, ChainBuilder, Document, OpenSearchClientBuilder, OpenSearchIndexer,
+    OpenSearchRetriever,
+};
+
+#[tokio::main]
+async fn main() {
+    let aws_config = aws_config::load_from_env().await;
+    let url = env::var("OPENSEARCH_URL").expect("OPENSEARCH_URL not set");
+
+    let indexer = OpenSearchIndexer::new(
+        OpenSearchClientBuilder::new()
+            .with_aws_opensearch_serverless_connection(&url, aws_config.clone())
+            .build()
+            .await
+            .expect("Failed to create OpenSearch client"),
+        BedrockEmbeddingModel::new_with_config(BedrockEmbedding::CohereEnglishV3, &aws_config),
+        "test_index",
+        "embedding",
+    );
+
+    let docs = vec!["Hello, world!", "Goodbye, world!", "Hello, universe!"];
+    let docs: Vec<Document> = docs.into_iter().map(Document::from).collect();
+    let indexed = ChainBuilder::new()
+        .link(indexer)
+        .build()
+        .process(docs)
+        .await
+        .expect("Failed to index documents");
+    println!("Indexed: {:?}", indexed);
+
+    let retriever = OpenSearchRetriever::new(
+        OpenSearchClientBuilder::new()
+            .with_aws_opensearch_serverless_connection(&url, aws_config.clone())
+            .build()
+            .await
+            .expect("Failed to create OpenSearch client"),
+        BedrockEmbeddingModel::new_with_config(BedrockEmbedding::CohereEnglishV3, &aws_config)
+            .with_input_type(CohereInputType::SearchQuery),
+        &["test_index"],
+        "embedding",
+        2,
+    )
+    .await;
+
+    let retrieved = ChainBuilder::new()
+        .link(retriever)
+        .build()
+        .process("Hello")
+        .await
+        .expect("Failed to retrieve documents");
+    println!("Retrieved: {:?}", retrieved);
+}
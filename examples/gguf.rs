@@ -0,0 +1,29 @@
+use std::collections::HashMap;
+
+use anchor_chain::models::gguf::GgufModel;
+use anchor_chain::{ChainBuilder, Prompt};
+
+#[tokio::main]
+async fn main() {
+    let model = GgufModel::from_files(
+        "models/mistral-7b-instruct-v0.2.Q4_K_M.gguf",
+        "models/tokenizer.json",
+    )
+    .await
+    .expect("Error loading model")
+    .with_max_tokens(256);
+
+    let chain = ChainBuilder::new()
+        .link(Prompt::new("[INST] {{ input }} [/INST]"))
+        .link(model)
+        .build();
+
+    let output = chain
+        .process(HashMap::from([(
+            "input",
+            "Write a hello world program in Rust",
+        )]))
+        .await
+        .expect("Error processing chain");
+    println!("{}", output);
+}
@@ -0,0 +1,27 @@
+use anchor_chain::models::bedrock_converse::BedrockModel;
+use anchor_chain::models::multimodal::{DocumentInput, ImageInput};
+use anchor_chain::{BedrockConverse, ChainBuilder, MultimodalInput};
+
+#[tokio::main]
+async fn main() {
+    let llm = BedrockConverse::<String>::new(BedrockModel::Claude3_5)
+        .await
+        .with_input::<MultimodalInput>();
+    let chain = ChainBuilder::new().link(llm).build();
+
+    let input = MultimodalInput::new()
+        .with_image(
+            ImageInput::from_path("examples/data/chart.png")
+                .await
+                .expect("Error reading image"),
+        )
+        .with_document(
+            DocumentInput::from_path("examples/data/report.pdf")
+                .await
+                .expect("Error reading document"),
+        )
+        .with_text("Does the chart match the figures in the report?");
+
+    let output = chain.process(input).await.expect("Error processing chain");
+    println!("{}", output);
+}
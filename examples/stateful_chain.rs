@@ -1,13 +1,16 @@
 use std::collections::HashMap;
 
 use anchor_chain::node::Stateful;
-use anchor_chain::{AnchorChainError, StateManager};
+use anchor_chain::{AnchorChainError, ChainState, StateKey};
 use anchor_chain::{ChainBuilder, Node, Prompt};
 use async_trait::async_trait;
 
+const ORIGINAL: StateKey<String> = StateKey::new("original");
+
 #[derive(Debug, Default)]
 pub struct UpperCaseConverter {
-    state: Option<StateManager<String, String>>,
+    /// The state used when the node is processed outside of a chain.
+    state: ChainState,
 }
 
 impl UpperCaseConverter {
@@ -22,22 +25,26 @@ impl Node for UpperCaseConverter {
     type Output = String;
 
     async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
-        let state = self.state.as_ref().expect("Node state was not set");
-        state.insert("original".to_string(), input.clone()).await;
-        Ok(input.to_uppercase())
+        self.process_with_state(input, &self.state).await
     }
 }
 
 #[async_trait]
-impl Stateful<String, String> for UpperCaseConverter {
-    async fn set_state(&mut self, state: StateManager<String, String>) {
-        self.state = Some(state);
+impl Stateful for UpperCaseConverter {
+    async fn process_with_state(
+        &self,
+        input: Self::Input,
+        state: &ChainState,
+    ) -> Result<Self::Output, AnchorChainError> {
+        state.insert(&ORIGINAL, input.clone()).await?;
+        Ok(input.to_uppercase())
     }
 }
 
 #[derive(Debug, Default)]
 pub struct Reverser {
-    state: Option<StateManager<String, String>>,
+    /// The state used when the node is processed outside of a chain.
+    state: ChainState,
 }
 
 impl Reverser {
@@ -52,9 +59,19 @@ impl Node for Reverser {
     type Output = String;
 
     async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
-        let state = self.state.as_ref().expect("Node state was not set");
+        self.process_with_state(input, &self.state).await
+    }
+}
+
+#[async_trait]
+impl Stateful for Reverser {
+    async fn process_with_state(
+        &self,
+        input: Self::Input,
+        state: &ChainState,
+    ) -> Result<Self::Output, AnchorChainError> {
         let original = state
-            .get(&"original".to_string())
+            .get(&ORIGINAL)
             .await
             .expect("State value should exist");
         println!("Original input was: {:?}", original);
@@ -62,13 +79,6 @@ impl Node for Reverser {
     }
 }
 
-#[async_trait]
-impl Stateful<String, String> for Reverser {
-    async fn set_state(&mut self, state: StateManager<String, String>) {
-        self.state = Some(state);
-    }
-}
-
 #[tokio::main]
 async fn main() {
     let chain = ChainBuilder::new()
@@ -3,8 +3,7 @@ use aws_sdk_bedrockruntime::types::Message as BedrockMessage;
 use tokio::sync::RwLock;
 
 use crate::models::bedrock_converse::BedrockModel;
-use crate::node::Stateful;
-use crate::{AnchorChainError, BedrockConverse, Node, StateManager, ToolRegistry};
+use crate::{AnchorChainError, BedrockConverse, Node, ToolRegistry};
 
 #[derive(Debug)]
 enum AgentModel<'a> {
@@ -20,12 +19,11 @@ pub struct AgentExecutor<'a> {
 
 impl<'a> AgentExecutor<'a> {
     pub async fn new_claude_agent(tool_registry: &'a RwLock<ToolRegistry<'a>>) -> Self {
-        let mut llm = BedrockConverse::new_with_system_prompt(
+        let llm = BedrockConverse::new_with_system_prompt(
             BedrockModel::Claude3_5,
             "You are a helpful assistant",
         )
         .await;
-        llm.set_state(StateManager::new()).await;
         AgentExecutor {
             llm: AgentModel::Claude3_5(llm),
             max_iterations: 10,
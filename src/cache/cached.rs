@@ -0,0 +1,265 @@
+//! Node wrapper caching the outputs of another node.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+#[cfg(feature = "tracing")]
+use tracing::instrument;
+
+use crate::cache::CacheBackend;
+use crate::describe::NodeDescription;
+use crate::error::AnchorChainError;
+use crate::models::embedding_model::EmbeddingModel;
+use crate::node::{Node, Stateless};
+
+/// Cache hit and miss counts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The number of requests answered from the cache.
+    pub hits: u64,
+    /// The number of requests passed to the wrapped node.
+    pub misses: u64,
+}
+
+impl CacheStats {
+    /// Returns the fraction of requests answered from the cache.
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Metrics {
+    pub(crate) fn hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Node that caches the outputs of the wrapped node.
+///
+/// Entries are keyed by a SHA-256 hash of the node's identity and the JSON encoded
+/// input. The identity defaults to the `Debug` representation of the wrapped node,
+/// which for model nodes includes the model and system prompt, so changing the
+/// configuration doesn't return stale results. When the wrapped node is an
+/// `EmbeddingModel` its `embed` calls are cached as well.
+///
+/// # Example
+/// ```rust,no_run
+/// use anchor_chain::cache::{Cached, InMemoryCache};
+/// use anchor_chain::{ChainBuilder, OpenAIModel};
+/// use std::time::Duration;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let cache = InMemoryCache::new(1000).with_ttl(Duration::from_secs(3600));
+///     let llm = Cached::new(
+///         OpenAIModel::new_gpt3_5_turbo("You are a helpful assistant").await,
+///         cache,
+///     );
+///
+///     let chain = ChainBuilder::new().link(llm).build();
+///     let output = chain.process("Hello").await.expect("Error processing chain");
+///     println!("{}", output);
+/// }
+/// ```
+pub struct Cached<N> {
+    node: N,
+    backend: Arc<dyn CacheBackend>,
+    namespace: String,
+    metrics: Arc<Metrics>,
+}
+
+impl<N: fmt::Debug> Cached<N> {
+    /// Wraps the node, storing its outputs in the backend.
+    pub fn new(node: N, backend: impl CacheBackend + 'static) -> Self {
+        Self::with_shared_backend(node, Arc::new(backend))
+    }
+
+    /// Wraps the node, storing its outputs in a backend shared with other nodes.
+    pub fn with_shared_backend(node: N, backend: Arc<dyn CacheBackend>) -> Self {
+        let namespace = format!("{node:?}");
+        Cached {
+            node,
+            backend,
+            namespace,
+            metrics: Arc::new(Metrics::default()),
+        }
+    }
+}
+
+impl<N> Cached<N> {
+    /// Sets the identity used in cache keys instead of the node's `Debug` output.
+    ///
+    /// Use this when the `Debug` output doesn't capture the configuration that
+    /// affects the node's output or changes between runs.
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    /// Returns the number of cache hits and misses.
+    pub fn stats(&self) -> CacheStats {
+        self.metrics.stats()
+    }
+
+    /// Returns the cache key for a request.
+    fn key(&self, operation: &str, input: &impl Serialize) -> Result<String, AnchorChainError> {
+        let mut hasher = Sha256::new();
+        hasher.update(self.namespace.as_bytes());
+        hasher.update([0]);
+        hasher.update(operation.as_bytes());
+        hasher.update([0]);
+        hasher.update(serde_json::to_vec(input)?);
+        Ok(hex::encode(hasher.finalize()))
+    }
+
+    /// Returns the cached output for the key or stores the output of `call`.
+    async fn get_or_insert<T, F>(&self, key: String, call: F) -> Result<T, AnchorChainError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: std::future::Future<Output = Result<T, AnchorChainError>>,
+    {
+        if let Some(value) = self.backend.get(&key).await? {
+            if let Ok(output) = serde_json::from_str(&value) {
+                self.metrics.hit();
+                return Ok(output);
+            }
+        }
+        self.metrics.miss();
+        let output = call.await?;
+        self.backend
+            .set(&key, serde_json::to_string(&output)?)
+            .await?;
+        Ok(output)
+    }
+}
+
+#[async_trait]
+impl<N> Node for Cached<N>
+where
+    N: Node + Send + Sync,
+    N::Input: Serialize + Send,
+    N::Output: Serialize + DeserializeOwned + Send,
+{
+    type Input = N::Input;
+    type Output = N::Output;
+
+    /// Returns the cached output for the input or processes it with the wrapped node.
+    #[cfg_attr(feature = "tracing", instrument(skip(self, input)))]
+    async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+        let key = self.key("process", &input)?;
+        self.get_or_insert(key, self.node.process(input)).await
+    }
+
+    /// Describes the node as a wrapper around its inner node.
+    fn describe(&self) -> NodeDescription {
+        NodeDescription::of::<Self>().wrapping(self.node.describe())
+    }
+}
+
+impl<N> Stateless for Cached<N>
+where
+    N: Node + Stateless + Send + Sync,
+    N::Input: Serialize + Send,
+    N::Output: Serialize + DeserializeOwned + Send,
+{
+}
+
+#[async_trait]
+impl<N> EmbeddingModel for Cached<N>
+where
+    N: EmbeddingModel + Send + Sync,
+{
+    async fn embed(&self, input: String) -> Result<Vec<f32>, AnchorChainError> {
+        let key = self.key("embed", &input)?;
+        self.get_or_insert(key, self.node.embed(input)).await
+    }
+
+    fn dimensions(&self) -> usize {
+        self.node.dimensions()
+    }
+}
+
+impl<N: fmt::Debug> fmt::Debug for Cached<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cached")
+            .field("node", &self.node)
+            .field("backend", &self.backend)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{DiskCache, InMemoryCache};
+    use crate::testing::{MockModel, MockResponse};
+
+    #[tokio::test]
+    async fn test_cached_model_hits_and_misses() {
+        let model = MockModel::new("mock").otherwise(MockResponse::text("response"));
+        let cached = Cached::new(model.clone(), InMemoryCache::default());
+
+        for _ in 0..3 {
+            assert_eq!(
+                cached.process("hello".to_string()).await.unwrap(),
+                "response"
+            );
+        }
+        cached.process("goodbye".to_string()).await.unwrap();
+
+        assert_eq!(model.prompts(), vec!["hello", "goodbye"]);
+        assert_eq!(cached.stats(), CacheStats { hits: 2, misses: 2 });
+        assert_eq!(cached.stats().hit_rate(), 0.5);
+    }
+
+    #[tokio::test]
+    async fn test_disk_cache_persists_between_nodes() {
+        let dir = std::env::temp_dir().join(format!("anchor-chain-cache-{}", std::process::id()));
+        let backend = DiskCache::new(&dir).await.unwrap();
+
+        let first = Cached::new(MockModel::new("mock"), backend.clone()).with_namespace("model");
+        assert!(first.process("hello".to_string()).await.is_err());
+
+        let model = MockModel::new("mock").then(MockResponse::text("response"));
+        let second = Cached::new(model, backend.clone()).with_namespace("model");
+        second.process("hello".to_string()).await.unwrap();
+
+        let third = Cached::new(MockModel::new("mock"), backend.clone()).with_namespace("model");
+        assert_eq!(
+            third.process("hello".to_string()).await.unwrap(),
+            "response"
+        );
+
+        backend.clear().await.unwrap();
+        assert!(third.process("hello".to_string()).await.is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
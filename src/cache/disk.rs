@@ -0,0 +1,128 @@
+//! On-disk cache backend.
+
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::cache::CacheBackend;
+use crate::error::AnchorChainError;
+
+#[derive(Serialize, Deserialize)]
+struct DiskEntry {
+    /// Seconds since the Unix epoch when the entry was written.
+    created_at: u64,
+    value: String,
+}
+
+/// A cache storing each entry as a JSON file in a directory.
+///
+/// Entries persist across runs, making this useful for development and batch
+/// reprocessing. Entries older than the TTL, if set, are treated as missing and
+/// deleted when read. Unreadable entries are treated as missing.
+#[derive(Clone, Debug)]
+pub struct DiskCache {
+    dir: PathBuf,
+    ttl: Option<Duration>,
+}
+
+impl DiskCache {
+    /// Creates a cache storing entries in `dir`, which is created if it doesn't exist.
+    pub async fn new(dir: impl AsRef<Path>) -> Result<Self, AnchorChainError> {
+        let dir = dir.as_ref().to_path_buf();
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .map_err(|e| io_error(&dir, e))?;
+        Ok(DiskCache { dir, ttl: None })
+    }
+
+    /// Expires entries once they are older than `ttl`.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Returns the path of the file storing the key.
+    fn path(&self, key: &str) -> PathBuf {
+        let hash = Sha256::digest(key.as_bytes());
+        self.dir.join(format!("{}.json", hex::encode(hash)))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn io_error(path: &Path, error: std::io::Error) -> AnchorChainError {
+    AnchorChainError::CacheError(format!("{}: {error}", path.display()))
+}
+
+#[async_trait]
+impl CacheBackend for DiskCache {
+    async fn get(&self, key: &str) -> Result<Option<String>, AnchorChainError> {
+        let path = self.path(key);
+        let contents = match tokio::fs::read_to_string(&path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(io_error(&path, e)),
+        };
+        let Ok(entry) = serde_json::from_str::<DiskEntry>(&contents) else {
+            return Ok(None);
+        };
+        if self
+            .ttl
+            .is_some_and(|ttl| now().saturating_sub(entry.created_at) >= ttl.as_secs())
+        {
+            self.remove(key).await?;
+            return Ok(None);
+        }
+        Ok(Some(entry.value))
+    }
+
+    async fn set(&self, key: &str, value: String) -> Result<(), AnchorChainError> {
+        let path = self.path(key);
+        let entry = DiskEntry {
+            created_at: now(),
+            value,
+        };
+        tokio::fs::write(&path, serde_json::to_string(&entry)?)
+            .await
+            .map_err(|e| io_error(&path, e))
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), AnchorChainError> {
+        let path = self.path(key);
+        match tokio::fs::remove_file(&path).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(io_error(&path, e)),
+            _ => Ok(()),
+        }
+    }
+
+    async fn clear(&self) -> Result<(), AnchorChainError> {
+        let mut entries = tokio::fs::read_dir(&self.dir)
+            .await
+            .map_err(|e| io_error(&self.dir, e))?;
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| io_error(&self.dir, e))?
+        {
+            let path = entry.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                tokio::fs::remove_file(&path)
+                    .await
+                    .map_err(|e| io_error(&path, e))?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+//! In-memory LRU cache backend.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::time::Instant;
+
+use crate::cache::CacheBackend;
+use crate::error::AnchorChainError;
+
+#[derive(Debug)]
+struct Entry {
+    value: String,
+    inserted: Instant,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct Lru {
+    entries: HashMap<String, Entry>,
+    /// Keys ordered by when they were last used.
+    order: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl Lru {
+    fn touch(&mut self, key: &str) {
+        self.tick += 1;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.order.remove(&entry.last_used);
+            entry.last_used = self.tick;
+            self.order.insert(self.tick, key.to_string());
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.last_used);
+        }
+    }
+}
+
+/// An in-memory cache that evicts the least recently used entry once full.
+///
+/// Entries older than the TTL, if set, are treated as missing. Clones share the
+/// same entries.
+#[derive(Clone, Debug)]
+pub struct InMemoryCache {
+    capacity: usize,
+    ttl: Option<Duration>,
+    lru: Arc<Mutex<Lru>>,
+}
+
+impl InMemoryCache {
+    /// Creates a new cache holding at most `capacity` entries.
+    pub fn new(capacity: usize) -> Self {
+        InMemoryCache {
+            capacity,
+            ttl: None,
+            lru: Arc::new(Mutex::new(Lru::default())),
+        }
+    }
+
+    /// Expires entries once they are older than `ttl`.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Returns the number of entries in the cache, including expired entries that
+    /// haven't been evicted yet.
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Returns true if the cache has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Lru> {
+        self.lru.lock().expect("Cache lock poisoned")
+    }
+}
+
+impl Default for InMemoryCache {
+    /// Creates a cache holding at most 1000 entries without a TTL.
+    fn default() -> Self {
+        InMemoryCache::new(1000)
+    }
+}
+
+#[async_trait]
+impl CacheBackend for InMemoryCache {
+    async fn get(&self, key: &str) -> Result<Option<String>, AnchorChainError> {
+        let mut lru = self.lock();
+        let expired = match lru.entries.get(key) {
+            None => return Ok(None),
+            Some(entry) => self.ttl.is_some_and(|ttl| entry.inserted.elapsed() >= ttl),
+        };
+        if expired {
+            lru.remove(key);
+            return Ok(None);
+        }
+        lru.touch(key);
+        Ok(lru.entries.get(key).map(|entry| entry.value.clone()))
+    }
+
+    async fn set(&self, key: &str, value: String) -> Result<(), AnchorChainError> {
+        if self.capacity == 0 {
+            return Ok(());
+        }
+        let mut lru = self.lock();
+        lru.remove(key);
+        while lru.entries.len() >= self.capacity {
+            let Some((_, oldest)) = lru.order.pop_first() else {
+                break;
+            };
+            lru.entries.remove(&oldest);
+        }
+        lru.entries.insert(
+            key.to_string(),
+            Entry {
+                value,
+                inserted: Instant::now(),
+                last_used: 0,
+            },
+        );
+        lru.touch(key);
+        Ok(())
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), AnchorChainError> {
+        self.lock().remove(key);
+        Ok(())
+    }
+
+    async fn clear(&self) -> Result<(), AnchorChainError> {
+        *self.lock() = Lru::default();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_lru_eviction_and_ttl() {
+        let cache = InMemoryCache::new(2).with_ttl(Duration::from_secs(10));
+        cache.set("a", "1".to_string()).await.unwrap();
+        cache.set("b", "2".to_string()).await.unwrap();
+        assert_eq!(cache.get("a").await.unwrap(), Some("1".to_string()));
+
+        cache.set("c", "3".to_string()).await.unwrap();
+        assert_eq!(cache.get("b").await.unwrap(), None);
+        assert_eq!(cache.len(), 2);
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(cache.get("a").await.unwrap(), None);
+        assert_eq!(cache.len(), 1);
+    }
+}
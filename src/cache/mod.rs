@@ -0,0 +1,45 @@
+//! Response caching for nodes.
+//!
+//! `Cached` wraps any node and stores its outputs in a `CacheBackend` keyed by a hash
+//! of the node's identity and input, so repeated identical requests to models and
+//! embedding models are answered without calling the provider. `InMemoryCache`
+//! provides an LRU cache with an optional TTL and `DiskCache` persists entries
+//! across runs.
+//!
+//! `SemanticCache` instead matches requests by the similarity of their embeddings,
+//! returning a previous answer from a `VectorStore` for paraphrased prompts.
+
+use std::fmt;
+
+use async_trait::async_trait;
+
+use crate::error::AnchorChainError;
+
+pub mod cached;
+pub mod disk;
+pub mod memory;
+pub mod semantic;
+
+pub use cached::{CacheStats, Cached};
+pub use disk::DiskCache;
+pub use memory::InMemoryCache;
+pub use semantic::SemanticCache;
+
+/// Storage for cached node outputs.
+///
+/// Values are the JSON encoded outputs of the cached node. Implementations are
+/// responsible for expiring entries.
+#[async_trait]
+pub trait CacheBackend: fmt::Debug + Send + Sync {
+    /// Returns the value for the key if present and not expired.
+    async fn get(&self, key: &str) -> Result<Option<String>, AnchorChainError>;
+
+    /// Stores the value for the key, replacing any existing value.
+    async fn set(&self, key: &str, value: String) -> Result<(), AnchorChainError>;
+
+    /// Removes the value for the key.
+    async fn remove(&self, key: &str) -> Result<(), AnchorChainError>;
+
+    /// Removes all values.
+    async fn clear(&self) -> Result<(), AnchorChainError>;
+}
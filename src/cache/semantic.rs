@@ -0,0 +1,275 @@
+//! Node wrapper caching outputs by the semantic similarity of inputs.
+
+use std::fmt;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+#[cfg(feature = "tracing")]
+use tracing::instrument;
+
+use crate::cache::cached::{CacheStats, Metrics};
+use crate::describe::NodeDescription;
+use crate::error::AnchorChainError;
+use crate::models::embedding_model::EmbeddingModel;
+use crate::node::{Node, Stateless};
+use crate::vector::document::Document;
+use crate::vector::vector_store::{MetadataFilter, VectorStore};
+
+/// The default minimum cosine similarity for a cached answer to be returned.
+const DEFAULT_THRESHOLD: f32 = 0.95;
+
+/// Node that returns previous outputs of the wrapped node for similar inputs.
+///
+/// Each input is embedded with the embedding model and the nearest previous input
+/// is looked up in the vector store. If its cosine similarity is at least the
+/// threshold, the stored output is returned; otherwise the wrapped node processes
+/// the input and the result is added to the store. Entries are tagged with a
+/// namespace, defaulting to the `Debug` representation of the wrapped node, and
+/// searches are filtered by it, so a store can be shared by several caches.
+///
+/// A lower threshold returns more cached answers at the risk of answering a
+/// different question, so it should be tuned for the embedding model in use.
+///
+/// # Example
+/// ```rust,no_run
+/// use anchor_chain::cache::SemanticCache;
+/// use anchor_chain::{ChainBuilder, InMemoryVectorStore, OpenAIEmbeddingModel, OpenAIModel};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let llm = SemanticCache::new(
+///         OpenAIModel::new_gpt3_5_turbo("You are a helpful assistant").await,
+///         OpenAIEmbeddingModel::default(),
+///         InMemoryVectorStore::new(),
+///     )
+///     .with_threshold(0.9);
+///
+///     let chain = ChainBuilder::new().link(llm).build();
+///     let output = chain.process("Hello").await.expect("Error processing chain");
+///     println!("{}", output);
+/// }
+/// ```
+pub struct SemanticCache<N, M> {
+    node: N,
+    embedding_model: M,
+    store: Arc<dyn VectorStore>,
+    threshold: f32,
+    namespace: String,
+    metrics: Arc<Metrics>,
+}
+
+impl<N: fmt::Debug, M> SemanticCache<N, M> {
+    /// Wraps the node, storing its outputs in the vector store.
+    pub fn new(node: N, embedding_model: M, store: impl VectorStore + 'static) -> Self {
+        Self::with_shared_store(node, embedding_model, Arc::new(store))
+    }
+
+    /// Wraps the node, storing its outputs in a vector store shared with other nodes.
+    pub fn with_shared_store(node: N, embedding_model: M, store: Arc<dyn VectorStore>) -> Self {
+        let namespace = format!("{node:?}");
+        SemanticCache {
+            node,
+            embedding_model,
+            store,
+            threshold: DEFAULT_THRESHOLD,
+            namespace,
+            metrics: Arc::new(Metrics::default()),
+        }
+    }
+}
+
+impl<N, M> SemanticCache<N, M> {
+    /// Sets the minimum cosine similarity for a cached output to be returned.
+    ///
+    /// Defaults to 0.95.
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Sets the namespace used to tag entries instead of the node's `Debug` output.
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    /// Returns the number of cache hits and misses.
+    pub fn stats(&self) -> CacheStats {
+        self.metrics.stats()
+    }
+
+    /// Returns the ID of the entry storing the output for the input.
+    fn entry_id(&self, input: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.namespace.as_bytes());
+        hasher.update([0]);
+        hasher.update(input.as_bytes());
+        hex::encode(hasher.finalize())
+    }
+}
+
+#[async_trait]
+impl<N, M> Node for SemanticCache<N, M>
+where
+    N: Node + Send + Sync,
+    N::Input: AsRef<str> + Send,
+    N::Output: Serialize + DeserializeOwned + Send,
+    M: EmbeddingModel + Send + Sync,
+{
+    type Input = N::Input;
+    type Output = N::Output;
+
+    /// Returns the output stored for a similar input or processes it with the
+    /// wrapped node.
+    #[cfg_attr(feature = "tracing", instrument(skip(self, input)))]
+    async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+        let text = input.as_ref().to_string();
+        let embedding = self.embedding_model.embed(text.clone()).await?;
+
+        let filter = MetadataFilter::new().with_field("namespace", self.namespace.as_str());
+        let nearest = self
+            .store
+            .search(&embedding, 1, Some(&filter))
+            .await?
+            .into_iter()
+            .next();
+        if let Some((document, similarity)) = nearest {
+            if similarity >= self.threshold {
+                let response = document
+                    .metadata
+                    .and_then(|mut metadata| metadata.get_mut("response").map(|v| v.take()));
+                if let Some(Ok(output)) = response.map(serde_json::from_value) {
+                    self.metrics.hit();
+                    return Ok(output);
+                }
+            }
+        }
+
+        self.metrics.miss();
+        let output = self.node.process(input).await?;
+        let mut document = Document::new_with_id(self.entry_id(&text), text);
+        document.embedding = Some(embedding);
+        document.metadata = Some(json!({
+            "namespace": self.namespace,
+            "response": serde_json::to_value(&output)?,
+        }));
+        self.store.upsert(vec![document]).await?;
+        Ok(output)
+    }
+
+    /// Describes the node as a wrapper around its inner node.
+    fn describe(&self) -> NodeDescription {
+        NodeDescription::of::<Self>().wrapping(self.node.describe())
+    }
+}
+
+impl<N, M> Stateless for SemanticCache<N, M>
+where
+    N: Node + Stateless + Send + Sync,
+    N::Input: AsRef<str> + Send,
+    N::Output: Serialize + DeserializeOwned + Send,
+    M: EmbeddingModel + Send + Sync,
+{
+}
+
+impl<N: fmt::Debug, M> fmt::Debug for SemanticCache<N, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SemanticCache")
+            .field("node", &self.node)
+            .field("store", &self.store)
+            .field("threshold", &self.threshold)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MockModel, MockResponse};
+    use crate::vector::vector_store::InMemoryVectorStore;
+
+    /// Embeds text by the topics it mentions.
+    struct TopicEmbedding;
+
+    #[async_trait]
+    impl EmbeddingModel for TopicEmbedding {
+        async fn embed(&self, input: String) -> Result<Vec<f32>, AnchorChainError> {
+            let input = input.to_lowercase();
+            Ok(vec![
+                input.contains("france") as u8 as f32,
+                input.contains("everest") as u8 as f32,
+                input.len() as f32 / 1000.0,
+            ])
+        }
+
+        fn dimensions(&self) -> usize {
+            3
+        }
+    }
+
+    #[tokio::test]
+    async fn test_semantic_cache_returns_similar_answers() {
+        let model = MockModel::new("mock")
+            .when_contains("France", MockResponse::text("Paris"))
+            .when_contains("Everest", MockResponse::text("8849 m"));
+        let store = InMemoryVectorStore::new();
+        let cached = SemanticCache::new(model.clone(), TopicEmbedding, store.clone());
+
+        let questions = [
+            "What is the capital of France?",
+            "what's the capital city of france",
+            "How tall is Everest?",
+        ];
+        let mut answers = Vec::new();
+        for question in questions {
+            answers.push(cached.process(question.to_string()).await.unwrap());
+        }
+
+        assert_eq!(answers, vec!["Paris", "Paris", "8849 m"]);
+        assert_eq!(
+            model.prompts(),
+            vec!["What is the capital of France?", "How tall is Everest?"]
+        );
+        assert_eq!(cached.stats(), CacheStats { hits: 1, misses: 2 });
+        assert_eq!(store.len().await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_shared_store_is_filtered_by_namespace() {
+        let store = InMemoryVectorStore::new();
+        let cached = SemanticCache::new(
+            MockModel::new("mock").when_contains("France", MockResponse::text("Paris")),
+            TopicEmbedding,
+            store.clone(),
+        )
+        .with_namespace("mine")
+        .with_threshold(0.9);
+        cached
+            .process("What is the capital of France?".to_string())
+            .await
+            .unwrap();
+
+        // Entries of another cache that are closer to the question than this
+        // cache's own entry.
+        let question = "what's the capital city of france";
+        let embedding = TopicEmbedding.embed(question.to_string()).await.unwrap();
+        let others = (0..8)
+            .map(|i| {
+                let mut document = Document::new_with_id(format!("other-{i}"), question.into());
+                document.embedding = Some(embedding.clone());
+                document.metadata = Some(json!({"namespace": "other", "response": "Lyon"}));
+                document
+            })
+            .collect();
+        store.upsert(others).await.unwrap();
+
+        let answer = cached.process(question.to_string()).await.unwrap();
+        assert_eq!(answer, "Paris");
+        assert_eq!(cached.stats(), CacheStats { hits: 1, misses: 1 });
+    }
+}
@@ -6,13 +6,16 @@
 
 use async_trait::async_trait;
 use std::fmt;
-use std::hash::Hash;
 use std::marker::PhantomData;
 
+use crate::chain_state::ChainState;
+use crate::context::RunContext;
+use crate::describe::NodeDescription;
 use crate::error::AnchorChainError;
-use crate::link::StatefulLink;
+use crate::link::{StatefulLink, WithState};
 use crate::node::{Stateful, Stateless};
-use crate::state_manager::StateManager;
+use crate::state_manager::with_session;
+use crate::usage::UsageLedger;
 use crate::{link::Link, node::Node};
 
 /// Represents a chain of nodes that can asynchronously process data.
@@ -52,8 +55,75 @@ where
     /// serving as the input to the next. The final output of the chain is returned.
     /// If any node in the chain returns an error, the processing is halted and
     /// the error is returned.
+    ///
+    /// Processing stops with `AnchorChainError::Cancelled` or
+    /// `AnchorChainError::DeadlineExceeded` if the `RunContext` of the run is
+    /// cancelled or its deadline passes.
     pub async fn process(&self, input: I) -> Result<O, AnchorChainError> {
-        self.link.process(input).await
+        RunContext::current()
+            .interruptible(self.link.process(input))
+            .await
+    }
+
+    /// Processes the input through the chain, returning the output along with
+    /// the token usage and latency of every model call made during the run.
+    pub async fn process_with_usage(&self, input: I) -> Result<(O, UsageLedger), AnchorChainError> {
+        let ledger = UsageLedger::new();
+        let output = self.process_with_ledger(input, &ledger).await?;
+        Ok((output, ledger))
+    }
+
+    /// Processes the input through the chain, recording the usage of every model
+    /// call in the provided ledger.
+    ///
+    /// Passing the same ledger to multiple runs accumulates their usage.
+    pub async fn process_with_ledger(
+        &self,
+        input: I,
+        ledger: &UsageLedger,
+    ) -> Result<O, AnchorChainError> {
+        ledger.scope(self.process(input)).await
+    }
+
+    /// Processes the input through the chain using the state of the session.
+    ///
+    /// Stateful nodes read and write the state of `session_id` so a single chain
+    /// can serve multiple conversations without their state mixing. Runs started
+    /// with `process` use the default session.
+    pub async fn process_with_session(
+        &self,
+        session_id: impl Into<String>,
+        input: I,
+    ) -> Result<O, AnchorChainError> {
+        with_session(session_id, self.process(input)).await
+    }
+
+    /// Processes the input through the chain as the run `run_id`.
+    ///
+    /// If a run with the same ID failed, `Checkpointed` steps that succeeded return
+    /// their saved outputs, resuming the run after its last checkpointed step.
+    pub async fn process_with_run_id(
+        &self,
+        run_id: impl Into<String>,
+        input: I,
+    ) -> Result<O, AnchorChainError> {
+        RunContext::current()
+            .with_run_id(run_id)
+            .scope(self.process(input))
+            .await
+    }
+
+    /// Processes the input through the chain with the context available to every
+    /// node through `RunContext::current`.
+    ///
+    /// Use `RunContext::with_cancellation` and `RunContext::with_timeout` to abort
+    /// the run, such as when the user disconnects.
+    pub async fn process_with_context(
+        &self,
+        context: RunContext,
+        input: I,
+    ) -> Result<O, AnchorChainError> {
+        context.scope(self.process(input)).await
     }
 }
 
@@ -70,6 +140,13 @@ where
     async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
         self.process(input).await
     }
+
+    /// Describes the nodes of the chain as a sequence.
+    fn describe(&self) -> NodeDescription {
+        NodeDescription::of::<Self>()
+            .with_name("Chain")
+            .sequence([self.link.describe()])
+    }
 }
 
 /// A builder for constructing a `Chain` of nodes.
@@ -97,16 +174,17 @@ impl ChainBuilder {
         }
     }
 
-    pub fn link_with_state<I, N, K, V>(self, node: N) -> StatefulLinkedChainBuilder<I, N, K, V>
+    /// Adds a stateful first node to the chain. A new `ChainState` will also be
+    /// created that will be passed to all stateful nodes in the chain.
+    pub fn link_with_state<I, N>(self, node: N) -> StatefulLinkedChainBuilder<I, WithState<N>>
     where
-        N: Node<Input = I> + Stateful<K, V> + Send + Sync + fmt::Debug,
+        N: Node<Input = I> + Stateful + Send + Sync + fmt::Debug,
         I: Send,
-        K: Eq + Hash + Clone,
-        V: Clone,
     {
+        let state = ChainState::new();
         StatefulLinkedChainBuilder {
-            link: node,
-            state: StateManager::new(),
+            link: WithState::new(node, state.clone()),
+            state,
             _input: PhantomData,
         }
     }
@@ -147,20 +225,15 @@ where
     }
 
     /// Adds a new `StatefulNode` to the chain, linking it to the previous
-    /// node. A new `StateManager` will also be created that will be passed
+    /// node. A new `ChainState` will also be created that will be passed
     /// to all stateful nodes in the chain.
-    pub fn link_with_state<N, K, V>(
-        self,
-        next: N,
-    ) -> StatefulLinkedChainBuilder<I, StatefulLink<L, N, K, V>, K, V>
+    pub fn link_with_state<N>(self, next: N) -> StatefulLinkedChainBuilder<I, StatefulLink<L, N>>
     where
-        N: Node<Input = L::Output> + Stateful<K, V> + Send + Sync + fmt::Debug,
+        N: Node<Input = L::Output> + Stateful + Send + Sync + fmt::Debug,
         L::Output: Send,
         Link<L, N>: Node<Input = I>,
-        K: Eq + Hash + Clone + Send + fmt::Debug,
-        V: Clone + Send + fmt::Debug,
     {
-        let state = StateManager::default();
+        let state = ChainState::new();
         StatefulLinkedChainBuilder {
             link: StatefulLink::new(self.link, next, state.clone()),
             state,
@@ -187,21 +260,27 @@ where
 /// `StatefulLinkedChainBuilder` takes an initial node and allows for incremental
 /// construction of a stateful processing chain, adding nodes one at a time. New nodes
 /// are linked to the previous nodes using nested `Link` or `StatefulLink` instances.
-pub struct StatefulLinkedChainBuilder<I, L, K, V> {
+pub struct StatefulLinkedChainBuilder<I, L> {
     link: L,
-    state: StateManager<K, V>,
+    state: ChainState,
     _input: PhantomData<I>,
 }
 
-impl<I, L, K, V> StatefulLinkedChainBuilder<I, L, K, V>
+impl<I, L> StatefulLinkedChainBuilder<I, L>
 where
     L: Node<Input = I> + Send + Sync + fmt::Debug,
     I: Send,
-    K: Clone + Send + fmt::Debug,
-    V: Clone + Send + fmt::Debug,
 {
+    /// Returns the `ChainState` shared by the stateful nodes of the chain.
+    ///
+    /// Keep the returned handle to list, fork or delete sessions of the built
+    /// chain, or to access the `StateManager` of a state type.
+    pub fn state(&self) -> ChainState {
+        self.state.clone()
+    }
+
     /// Adds a new node to the chain, linking it to the previous node.
-    pub fn link<N>(self, next: N) -> StatefulLinkedChainBuilder<I, Link<L, N>, K, V>
+    pub fn link<N>(self, next: N) -> StatefulLinkedChainBuilder<I, Link<L, N>>
     where
         N: Node<Input = L::Output> + Stateless + Send + Sync + fmt::Debug,
         L::Output: Send,
@@ -215,15 +294,12 @@ where
     }
 
     /// Adds a new `StatefulNode` to the chain, linking it to the previous
-    /// node. Each stateful node will be passed an instance of the `StateManager`.
-    pub fn link_with_state<N>(
-        self,
-        next: N,
-    ) -> StatefulLinkedChainBuilder<I, StatefulLink<L, N, K, V>, K, V>
+    /// node. Each stateful node will be passed the chain's `ChainState`.
+    pub fn link_with_state<N>(self, next: N) -> StatefulLinkedChainBuilder<I, StatefulLink<L, N>>
     where
-        N: Node<Input = L::Output> + Stateful<K, V> + Send + Sync + fmt::Debug,
+        N: Node<Input = L::Output> + Stateful + Send + Sync + fmt::Debug,
         L::Output: Send,
-        StatefulLink<L, N, K, V>: Node<Input = I>,
+        StatefulLink<L, N>: Node<Input = I>,
     {
         StatefulLinkedChainBuilder {
             link: StatefulLink::new(self.link, next, self.state.clone()),
@@ -0,0 +1,296 @@
+//! Typed state shared by the stateful nodes of a chain.
+//!
+//! A `ChainState` holds one `StateManager` for each key and value type used by
+//! the chain, so stateful nodes with different state types can be linked into the
+//! same chain. Nodes either take the manager for their types with
+//! `ChainState::manager` or read and write single values using typed `StateKey`s.
+
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+
+use crate::error::AnchorChainError;
+use crate::state_manager::StateManager;
+
+/// A key identifying a value of type `T` in a `ChainState`.
+///
+/// Keys are scoped by their value type, so keys with the same name but different
+/// types refer to different values.
+///
+/// ```rust
+/// use anchor_chain::chain_state::{ChainState, StateKey};
+///
+/// const VISITS: StateKey<u32> = StateKey::new("visits");
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let state = ChainState::new();
+///     state.insert(&VISITS, 1).await.unwrap();
+///     assert_eq!(state.get(&VISITS).await, Some(1));
+/// }
+/// ```
+pub struct StateKey<T> {
+    name: &'static str,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T> StateKey<T> {
+    /// Creates a key with the given name.
+    pub const fn new(name: &'static str) -> Self {
+        StateKey {
+            name,
+            _value: PhantomData,
+        }
+    }
+
+    /// Returns the name of the key.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl<T> Clone for StateKey<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for StateKey<T> {}
+
+impl<T> fmt::Debug for StateKey<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "StateKey<{}>({:?})", type_name::<T>(), self.name)
+    }
+}
+
+/// Session operations on a `StateManager` of any key and value type.
+#[async_trait]
+trait Slot: Send + Sync {
+    fn as_any(&self) -> &(dyn Any + Send + Sync);
+
+    async fn sessions(&self) -> Vec<String>;
+
+    async fn fork_session(&self, from: &str, to: &str) -> Result<bool, AnchorChainError>;
+
+    async fn delete_session(&self, session_id: &str) -> Result<bool, AnchorChainError>;
+}
+
+#[async_trait]
+impl<K, V> Slot for StateManager<K, V>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    fn as_any(&self) -> &(dyn Any + Send + Sync) {
+        self
+    }
+
+    async fn sessions(&self) -> Vec<String> {
+        StateManager::sessions(self).await
+    }
+
+    async fn fork_session(&self, from: &str, to: &str) -> Result<bool, AnchorChainError> {
+        StateManager::fork_session(self, from, to).await
+    }
+
+    async fn delete_session(&self, session_id: &str) -> Result<bool, AnchorChainError> {
+        Ok(StateManager::delete_session(self, session_id)
+            .await?
+            .is_some())
+    }
+}
+
+struct Entry {
+    type_name: &'static str,
+    slot: Arc<dyn Slot>,
+}
+
+/// State shared by the stateful nodes of a chain, holding a `StateManager` for
+/// each key and value type.
+///
+/// Clones share the same state. Nodes using the same key and value types share a
+/// `StateManager`, so nodes that shouldn't see each other's state should use
+/// distinct keys. To persist state, call `StateManager::persist_to` on the manager
+/// of each type with a separate store. Values of a `StateKey<T>` are held by the
+/// manager for `String` keys and `T` values.
+#[derive(Clone, Default)]
+pub struct ChainState {
+    slots: Arc<Mutex<HashMap<TypeId, Entry>>>,
+}
+
+impl ChainState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the `StateManager` for keys of type `K` and values of type `V`,
+    /// creating it if it doesn't exist.
+    pub fn manager<K, V>(&self) -> StateManager<K, V>
+    where
+        K: Eq + Hash + Clone + Send + Sync + 'static,
+        V: Clone + Send + Sync + 'static,
+    {
+        let mut slots = self.slots.lock().expect("Chain state lock poisoned");
+        slots
+            .entry(TypeId::of::<StateManager<K, V>>())
+            .or_insert_with(|| Entry {
+                type_name: type_name::<StateManager<K, V>>(),
+                slot: Arc::new(StateManager::<K, V>::new()),
+            })
+            .slot
+            .as_any()
+            .downcast_ref::<StateManager<K, V>>()
+            .expect("State slot has the wrong type")
+            .clone()
+    }
+
+    /// Returns the value of the key in the current session.
+    pub async fn get<T>(&self, key: &StateKey<T>) -> Option<T>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        self.manager::<String, T>().get(&key.name.to_string()).await
+    }
+
+    /// Sets the value of the key in the current session.
+    pub async fn insert<T>(&self, key: &StateKey<T>, value: T) -> Result<(), AnchorChainError>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        self.manager::<String, T>()
+            .insert(key.name.to_string(), value)
+            .await
+    }
+
+    /// Removes the value of the key in the current session, returning it if it
+    /// existed.
+    pub async fn remove<T>(&self, key: &StateKey<T>) -> Result<Option<T>, AnchorChainError>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        self.manager::<String, T>()
+            .remove(&key.name.to_string())
+            .await
+    }
+
+    /// Returns every slot so they can be used without holding the lock.
+    fn all_slots(&self) -> Vec<Arc<dyn Slot>> {
+        self.slots
+            .lock()
+            .expect("Chain state lock poisoned")
+            .values()
+            .map(|entry| entry.slot.clone())
+            .collect()
+    }
+
+    /// Returns the IDs of all sessions with state of any type.
+    pub async fn sessions(&self) -> Vec<String> {
+        let mut sessions = Vec::new();
+        for slot in self.all_slots() {
+            sessions.extend(slot.sessions().await);
+        }
+        sessions.sort();
+        sessions.dedup();
+        sessions
+    }
+
+    /// Copies the state of every type from the `from` session into the `to`
+    /// session. Returns false if the `from` session doesn't exist.
+    pub async fn fork_session(
+        &self,
+        from: &str,
+        to: impl Into<String>,
+    ) -> Result<bool, AnchorChainError> {
+        let to = to.into();
+        let mut forked = false;
+        for slot in self.all_slots() {
+            forked |= slot.fork_session(from, &to).await?;
+        }
+        Ok(forked)
+    }
+
+    /// Deletes the state of every type of the session. Returns false if the
+    /// session doesn't exist.
+    pub async fn delete_session(&self, session_id: &str) -> Result<bool, AnchorChainError> {
+        let mut deleted = false;
+        for slot in self.all_slots() {
+            deleted |= slot.delete_session(session_id).await?;
+        }
+        Ok(deleted)
+    }
+}
+
+impl fmt::Debug for ChainState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let slots = self.slots.lock().expect("Chain state lock poisoned");
+        f.debug_struct("ChainState")
+            .field(
+                "slots",
+                &slots
+                    .values()
+                    .map(|entry| entry.type_name)
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state_manager::with_session;
+    use crate::state_store::JsonFileStore;
+
+    const NAME: StateKey<String> = StateKey::new("name");
+    const TURNS: StateKey<Vec<u32>> = StateKey::new("name");
+
+    #[tokio::test]
+    async fn test_typed_slots_are_independent() {
+        let state = ChainState::new();
+        state.insert(&NAME, "Ada".to_string()).await.unwrap();
+        state.insert(&TURNS, vec![1, 2]).await.unwrap();
+        state
+            .manager::<String, Vec<String>>()
+            .push("history".to_string(), "hello".to_string())
+            .await
+            .unwrap();
+
+        assert_eq!(state.get(&NAME).await, Some("Ada".to_string()));
+        assert_eq!(state.get(&TURNS).await, Some(vec![1, 2]));
+
+        assert!(state.fork_session("default", "copy").await.unwrap());
+        with_session("copy", async {
+            assert_eq!(state.get(&TURNS).await, Some(vec![1, 2]));
+        })
+        .await;
+        assert!(state.delete_session("default").await.unwrap());
+        assert_eq!(state.sessions().await, vec!["copy".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_state_key_values_persist() {
+        let dir =
+            std::env::temp_dir().join(format!("anchor-chain-chain-state-{}", std::process::id()));
+        let state = ChainState::new();
+        state
+            .manager::<String, String>()
+            .persist_to(JsonFileStore::new(&dir).await.unwrap())
+            .await
+            .unwrap();
+        state.insert(&NAME, "Ada".to_string()).await.unwrap();
+
+        let restored = ChainState::new();
+        restored
+            .manager::<String, String>()
+            .persist_to(JsonFileStore::new(&dir).await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(restored.get(&NAME).await, Some("Ada".to_string()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,336 @@
+//! Checkpointing of intermediate outputs so failed runs can be resumed.
+//!
+//! `Checkpointed` wraps a step of a chain and saves its output to a
+//! `CacheBackend` under the run ID of the current `RunContext`. When a run with
+//! the same ID is processed again, checkpointed steps that already succeeded
+//! return their saved output instead of processing their input, so a run that
+//! failed part way resumes after its last checkpointed step. Steps run without a
+//! run ID aren't checkpointed.
+//!
+//! Checkpoints are stored as JSON, so the input and output of checkpointed steps
+//! must be serializable. Use `DiskCache` to resume runs after a restart.
+//!
+//! `Checkpoints` creates the checkpointed steps of a chain sharing a backend and
+//! removes the checkpoints of a run once it's no longer needed, keeping the
+//! backend from growing with every run.
+//!
+//! # Example
+//! ```rust
+//! use anchor_chain::cache::InMemoryCache;
+//! use anchor_chain::checkpoint::Checkpoints;
+//! use anchor_chain::{ChainBuilder, NoOpNode};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let checkpoints = Checkpoints::new(InMemoryCache::default());
+//!     let chain = ChainBuilder::new()
+//!         .link(checkpoints.step(NoOpNode::<String>::new(), "fetch"))
+//!         .link(checkpoints.step(NoOpNode::<String>::new(), "summarize"))
+//!         .build();
+//!
+//!     let output = chain
+//!         .process_with_run_id("run-1", "hello".to_string())
+//!         .await
+//!         .unwrap();
+//!     assert_eq!(output, "hello");
+//!     checkpoints.clear_run("run-1").await.unwrap();
+//! }
+//! ```
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+#[cfg(feature = "tracing")]
+use tracing::instrument;
+
+use crate::cache::CacheBackend;
+use crate::context::RunContext;
+use crate::describe::NodeDescription;
+use crate::error::AnchorChainError;
+use crate::node::{Node, Stateless};
+
+/// The saved output of a step along with a hash of the input it was produced
+/// from.
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    input: String,
+    output: Value,
+}
+
+/// Node saving the output of the wrapped node for each run so it isn't processed
+/// again when the run is resumed.
+///
+/// The step name identifies the checkpoint within a run and must be unique among
+/// the checkpointed steps sharing a backend. A saved output is only reused if the
+/// step is given the same input, so changing the input of a run processes it
+/// again.
+pub struct Checkpointed<N> {
+    node: N,
+    backend: Arc<dyn CacheBackend>,
+    step: String,
+}
+
+impl<N> Checkpointed<N> {
+    /// Wraps the node, saving its outputs in the backend under the step name.
+    pub fn new(node: N, backend: impl CacheBackend + 'static, step: impl Into<String>) -> Self {
+        Self::with_shared_backend(node, Arc::new(backend), step)
+    }
+
+    /// Wraps the node, saving its outputs in a backend shared with other steps.
+    pub fn with_shared_backend(
+        node: N,
+        backend: Arc<dyn CacheBackend>,
+        step: impl Into<String>,
+    ) -> Self {
+        Checkpointed {
+            node,
+            backend,
+            step: step.into(),
+        }
+    }
+
+    /// Returns the name of the step.
+    pub fn step(&self) -> &str {
+        &self.step
+    }
+
+    /// Removes the checkpoint of the step for the run.
+    pub async fn remove(&self, run_id: &str) -> Result<(), AnchorChainError> {
+        self.backend.remove(&self.key(run_id)).await
+    }
+
+    fn key(&self, run_id: &str) -> String {
+        checkpoint_key(run_id, &self.step)
+    }
+}
+
+/// Returns the cache key of the step's checkpoint for the run.
+///
+/// The run ID and step name are hashed with the length of the run ID, so IDs and
+/// names containing any characters map to distinct keys.
+fn checkpoint_key(run_id: &str, step: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update((run_id.len() as u64).to_le_bytes());
+    hasher.update(run_id);
+    hasher.update(step);
+    format!("checkpoint:{}", hex::encode(hasher.finalize()))
+}
+
+/// The checkpointed steps sharing a backend, used to remove the checkpoints of
+/// whole runs.
+///
+/// Clones share the same backend and steps.
+#[derive(Clone)]
+pub struct Checkpoints {
+    backend: Arc<dyn CacheBackend>,
+    steps: Arc<Mutex<Vec<String>>>,
+}
+
+impl Checkpoints {
+    /// Creates checkpoints saved in the backend.
+    pub fn new(backend: impl CacheBackend + 'static) -> Self {
+        Self::with_shared_backend(Arc::new(backend))
+    }
+
+    /// Creates checkpoints saved in a backend shared with other users.
+    pub fn with_shared_backend(backend: Arc<dyn CacheBackend>) -> Self {
+        Checkpoints {
+            backend,
+            steps: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Wraps the node as a checkpointed step named `step`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a step with the same name was already created, as the steps
+    /// would overwrite each other's checkpoints.
+    pub fn step<N>(&self, node: N, step: impl Into<String>) -> Checkpointed<N> {
+        let step = step.into();
+        let added = {
+            let mut steps = self.steps.lock().expect("Checkpoint steps lock poisoned");
+            let added = !steps.contains(&step);
+            if added {
+                steps.push(step.clone());
+            }
+            added
+        };
+        assert!(added, "duplicate checkpointed step name: {step:?}");
+        Checkpointed::with_shared_backend(node, self.backend.clone(), step)
+    }
+
+    /// Returns the names of the steps.
+    pub fn steps(&self) -> Vec<String> {
+        self.steps
+            .lock()
+            .expect("Checkpoint steps lock poisoned")
+            .clone()
+    }
+
+    /// Removes the checkpoints of every step for the run.
+    ///
+    /// Call this once a run has finished, or failed and won't be resumed.
+    pub async fn clear_run(&self, run_id: &str) -> Result<(), AnchorChainError> {
+        for step in self.steps() {
+            self.backend.remove(&checkpoint_key(run_id, &step)).await?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Checkpoints {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Checkpoints")
+            .field("backend", &self.backend)
+            .field("steps", &self.steps())
+            .finish()
+    }
+}
+
+#[async_trait]
+impl<N> Node for Checkpointed<N>
+where
+    N: Node + Send + Sync,
+    N::Input: Serialize + Send,
+    N::Output: Serialize + DeserializeOwned + Send,
+{
+    type Input = N::Input;
+    type Output = N::Output;
+
+    /// Returns the saved output of the step for the current run, or processes the
+    /// input with the wrapped node and saves its output.
+    #[cfg_attr(feature = "tracing", instrument(skip(self, input), fields(step = %self.step)))]
+    async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+        let Some(run_id) =
+            RunContext::with_current(|context| context.run_id().map(String::from)).flatten()
+        else {
+            return self.node.process(input).await;
+        };
+
+        let key = self.key(&run_id);
+        let input_hash = hex::encode(Sha256::digest(serde_json::to_vec(&input)?));
+        if let Some(saved) = self.backend.get(&key).await? {
+            if let Ok(checkpoint) = serde_json::from_str::<Checkpoint>(&saved) {
+                if checkpoint.input == input_hash {
+                    if let Ok(output) = serde_json::from_value(checkpoint.output) {
+                        return Ok(output);
+                    }
+                }
+            }
+        }
+
+        let output = self.node.process(input).await?;
+        let checkpoint = Checkpoint {
+            input: input_hash,
+            output: serde_json::to_value(&output)?,
+        };
+        self.backend
+            .set(&key, serde_json::to_string(&checkpoint)?)
+            .await?;
+        Ok(output)
+    }
+
+    /// Describes the node as a wrapper around its inner node.
+    fn describe(&self) -> NodeDescription {
+        NodeDescription::of::<Self>().wrapping(self.node.describe())
+    }
+}
+
+impl<N> Stateless for Checkpointed<N>
+where
+    N: Node + Stateless + Send + Sync,
+    N::Input: Serialize + Send,
+    N::Output: Serialize + DeserializeOwned + Send,
+{
+}
+
+impl<N: fmt::Debug> fmt::Debug for Checkpointed<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Checkpointed")
+            .field("node", &self.node)
+            .field("backend", &self.backend)
+            .field("step", &self.step)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::cache::InMemoryCache;
+    use crate::chain::ChainBuilder;
+
+    /// Counts its calls and fails the first `failures` of them.
+    #[derive(Debug, Default)]
+    struct Step {
+        calls: Arc<AtomicUsize>,
+        failures: usize,
+    }
+
+    #[async_trait]
+    impl Node for Step {
+        type Input = u32;
+        type Output = u32;
+
+        async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                return Err(AnchorChainError::ModelError("step failed".to_string()));
+            }
+            Ok(input + 1)
+        }
+    }
+
+    impl Stateless for Step {}
+
+    #[tokio::test]
+    async fn test_failed_runs_resume_after_last_checkpoint() {
+        let checkpoints = Checkpoints::new(InMemoryCache::default());
+        let first = Arc::new(AtomicUsize::new(0));
+        let second = Arc::new(AtomicUsize::new(0));
+        let chain = ChainBuilder::new()
+            .link(checkpoints.step(
+                Step {
+                    calls: first.clone(),
+                    failures: 0,
+                },
+                "first",
+            ))
+            .link(checkpoints.step(
+                Step {
+                    calls: second.clone(),
+                    failures: 1,
+                },
+                "second",
+            ))
+            .build();
+
+        assert!(chain.process_with_run_id("run-1", 1).await.is_err());
+        assert_eq!(chain.process_with_run_id("run-1", 1).await.unwrap(), 3);
+        assert_eq!(chain.process_with_run_id("run-1", 1).await.unwrap(), 3);
+        assert_eq!(first.load(Ordering::SeqCst), 1);
+        assert_eq!(second.load(Ordering::SeqCst), 2);
+
+        assert_eq!(chain.process_with_run_id("run-1", 5).await.unwrap(), 7);
+        assert_eq!(chain.process(1).await.unwrap(), 3);
+        assert_eq!(first.load(Ordering::SeqCst), 3);
+
+        checkpoints.clear_run("run-1").await.unwrap();
+        assert_eq!(chain.process_with_run_id("run-1", 5).await.unwrap(), 7);
+        assert_eq!(first.load(Ordering::SeqCst), 4);
+        assert_ne!(checkpoint_key("a:b", "c"), checkpoint_key("a", "b:c"));
+
+        let duplicate = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            checkpoints.step(Step::default(), "first")
+        }));
+        assert!(duplicate.is_err());
+        assert_eq!(checkpoints.steps(), vec!["first", "second"]);
+    }
+}
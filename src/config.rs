@@ -0,0 +1,678 @@
+//! Declarative chain definitions loaded from JSON or YAML.
+//!
+//! A `ChainConfig` lists the nodes of a chain by type name along with their
+//! parameters. A `NodeRegistry` maps type names to factories creating the nodes,
+//! and `NodeRegistry::build` instantiates a `DynamicChain` after checking that
+//! adjacent nodes have matching types, so pipelines can be changed without
+//! recompiling.
+//!
+//! ```yaml
+//! nodes:
+//!   - type: prompt
+//!     template: "Summarize the following text:\n{{ input }}"
+//!     variable: input
+//!   - type: openai
+//!     model: gpt-4-turbo-preview
+//!   - type: logger
+//!     prefix: summary
+//! ```
+//!
+//! The built-in node types are:
+//!
+//! - `noop`: passes text through unchanged.
+//! - `prompt`: renders a Tera `template`. It takes a JSON object of variables, or
+//!   text bound to `variable` if one is set.
+//! - `logger`: prints text with a `prefix`.
+//! - `parallel`: runs text through each of `branches`, each a list of nodes, and
+//!   joins their outputs with `separator`.
+//! - `openai`: an OpenAI chat `model` with an optional `system_prompt`
+//!   (feature `openai`).
+//! - `bedrock`: a Bedrock `model` ID with an optional `system_prompt`
+//!   (feature `bedrock`).
+//! - `ollama`: an Ollama `model` with optional `host` and `port` (feature `ollama`).
+//! - `opensearch_retriever`: retrieves the `top_k` documents most similar to the
+//!   input text from the `indexes` of a local OpenSearch at `url` using OpenAI
+//!   embeddings, returning their text joined by blank lines (features `opensearch`
+//!   and `openai`).
+//!
+//! Custom nodes are added with `NodeRegistry::register_node`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::dynamic_chain::{boxed, DynamicChain, DynamicNode, PayloadType};
+use crate::error::AnchorChainError;
+use crate::node::{NoOpNode, Node};
+use crate::nodes::logger::Logger;
+use crate::nodes::prompt::Prompt;
+use crate::parallel_node::{to_boxed_future, ParallelNode};
+
+/// The definition of a chain as a list of nodes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChainConfig {
+    /// The nodes of the chain in processing order.
+    pub nodes: Vec<NodeConfig>,
+}
+
+impl ChainConfig {
+    /// Parses a chain definition from JSON.
+    pub fn from_json(json: &str) -> Result<Self, AnchorChainError> {
+        serde_json::from_str(json).map_err(|e| AnchorChainError::ConfigError(e.to_string()))
+    }
+
+    /// Parses a chain definition from YAML.
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml(yaml: &str) -> Result<Self, AnchorChainError> {
+        serde_yaml_ng::from_str(yaml).map_err(|e| AnchorChainError::ConfigError(e.to_string()))
+    }
+
+    /// Reads a chain definition from a `.json` file, or a `.yaml` or `.yml` file
+    /// with the `yaml` feature.
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self, AnchorChainError> {
+        let path = path.as_ref();
+        let contents = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| AnchorChainError::ConfigError(format!("{}: {e}", path.display())))?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Self::from_json(&contents),
+            #[cfg(feature = "yaml")]
+            Some("yaml" | "yml") => Self::from_yaml(&contents),
+            _ => Err(AnchorChainError::ConfigError(format!(
+                "unsupported chain definition format: {}",
+                path.display()
+            ))),
+        }
+    }
+}
+
+/// The definition of a node: its type name and parameters.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NodeConfig {
+    /// The name the node's factory is registered under.
+    #[serde(rename = "type")]
+    pub node_type: String,
+    /// The parameters passed to the factory.
+    #[serde(flatten)]
+    pub params: Map<String, Value>,
+}
+
+impl NodeConfig {
+    /// Creates a node definition without parameters.
+    pub fn new(node_type: impl Into<String>) -> Self {
+        NodeConfig {
+            node_type: node_type.into(),
+            params: Map::new(),
+        }
+    }
+
+    /// Adds a parameter to the definition.
+    pub fn with_param(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.params.insert(name.into(), value.into());
+        self
+    }
+
+    /// Deserializes the parameters into `T`.
+    pub fn params<T: DeserializeOwned>(&self) -> Result<T, AnchorChainError> {
+        serde_json::from_value(Value::Object(self.params.clone())).map_err(|e| {
+            AnchorChainError::ConfigError(format!("invalid {} parameters: {e}", self.node_type))
+        })
+    }
+}
+
+/// Creates nodes from their definitions.
+///
+/// Closures taking a `&NodeConfig` and returning a boxed node implement
+/// `NodeFactory`; implement it directly for factories that are async or build
+/// nested nodes with the registry.
+#[async_trait]
+pub trait NodeFactory: Send + Sync {
+    /// Creates the node described by the definition.
+    async fn create(
+        &self,
+        config: &NodeConfig,
+        registry: &NodeRegistry,
+    ) -> Result<Box<dyn DynamicNode>, AnchorChainError>;
+}
+
+#[async_trait]
+impl<F> NodeFactory for F
+where
+    F: Fn(&NodeConfig) -> Result<Box<dyn DynamicNode>, AnchorChainError> + Send + Sync,
+{
+    async fn create(
+        &self,
+        config: &NodeConfig,
+        _registry: &NodeRegistry,
+    ) -> Result<Box<dyn DynamicNode>, AnchorChainError> {
+        self(config)
+    }
+}
+
+/// A registry of node factories keyed by type name.
+///
+/// # Example
+/// ```rust
+/// use anchor_chain::config::{ChainConfig, NodeConfig, NodeRegistry};
+/// use anchor_chain::dynamic_chain::boxed;
+/// use anchor_chain::Logger;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut registry = NodeRegistry::default();
+///     registry.register_node("quiet_logger", |_: &NodeConfig| {
+///         Ok(boxed(Logger::<String>::new("quiet")))
+///     });
+///
+///     let config = ChainConfig::from_json(
+///         r#"{"nodes": [
+///             {"type": "prompt", "template": "Hello, {{ name }}!", "variable": "name"},
+///             {"type": "quiet_logger"}
+///         ]}"#,
+///     )
+///     .unwrap();
+///     let chain = registry.build(&config).await.unwrap();
+///     let output: String = chain.process("world".to_string()).await.unwrap();
+///     assert_eq!(output, "Hello, world!");
+/// }
+/// ```
+#[derive(Clone)]
+pub struct NodeRegistry {
+    factories: HashMap<String, Arc<dyn NodeFactory>>,
+}
+
+impl NodeRegistry {
+    /// Creates a registry without any node types.
+    pub fn new() -> Self {
+        NodeRegistry {
+            factories: HashMap::new(),
+        }
+    }
+
+    /// Creates a registry with the built-in node types.
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::new();
+        registry.register_node("noop", |_: &NodeConfig| {
+            Ok(boxed(NoOpNode::<String>::new()))
+        });
+        registry.register_node("logger", create_logger);
+        registry.register_node("prompt", create_prompt);
+        registry.register_node("parallel", ParallelFactory);
+        #[cfg(feature = "openai")]
+        registry.register_node("openai", OpenAIFactory);
+        #[cfg(feature = "bedrock")]
+        registry.register_node("bedrock", BedrockFactory);
+        #[cfg(feature = "ollama")]
+        registry.register_node("ollama", create_ollama);
+        #[cfg(all(feature = "opensearch", feature = "openai"))]
+        registry.register_node("opensearch_retriever", OpenSearchRetrieverFactory);
+        registry
+    }
+
+    /// Registers a factory for a node type, replacing any factory registered
+    /// under the same name.
+    pub fn register_node(
+        &mut self,
+        node_type: impl Into<String>,
+        factory: impl NodeFactory + 'static,
+    ) {
+        self.factories.insert(node_type.into(), Arc::new(factory));
+    }
+
+    /// Returns the registered node types in alphabetical order.
+    pub fn node_types(&self) -> Vec<&str> {
+        let mut types = self
+            .factories
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        types.sort_unstable();
+        types
+    }
+
+    /// Creates the node described by the definition.
+    pub async fn create(
+        &self,
+        config: &NodeConfig,
+    ) -> Result<Box<dyn DynamicNode>, AnchorChainError> {
+        let factory = self.factories.get(&config.node_type).ok_or_else(|| {
+            AnchorChainError::ConfigError(format!(
+                "unknown node type {:?}, expected one of: {}",
+                config.node_type,
+                self.node_types().join(", ")
+            ))
+        })?;
+        factory.create(config, self).await
+    }
+
+    /// Creates the nodes of the definition and links them into a chain.
+    ///
+    /// Returns an error if a node type is unknown, its parameters are invalid or
+    /// the output of a node doesn't match the input of the next node.
+    pub async fn build(&self, config: &ChainConfig) -> Result<DynamicChain, AnchorChainError> {
+        let mut builder = DynamicChain::builder();
+        let mut previous: Option<(&str, PayloadType)> = None;
+        for (index, node_config) in config.nodes.iter().enumerate() {
+            let node = self.create(node_config).await?;
+            if let Some((previous_type, output)) = previous {
+                if output != node.input_type() {
+                    return Err(AnchorChainError::ConfigError(format!(
+                        "node {index} ({}) takes {} but node {} ({previous_type}) produces {output}",
+                        node_config.node_type,
+                        node.input_type(),
+                        index - 1,
+                    )));
+                }
+            }
+            previous = Some((&node_config.node_type, node.output_type()));
+            builder = builder.link_boxed(node);
+        }
+        builder.build()
+    }
+}
+
+impl Default for NodeRegistry {
+    /// Creates a registry with the built-in node types.
+    fn default() -> Self {
+        Self::with_builtins()
+    }
+}
+
+impl fmt::Debug for NodeRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NodeRegistry")
+            .field("node_types", &self.node_types())
+            .finish()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LoggerParams {
+    #[serde(default = "default_logger_prefix")]
+    prefix: String,
+}
+
+fn default_logger_prefix() -> String {
+    "Logger".to_string()
+}
+
+fn create_logger(config: &NodeConfig) -> Result<Box<dyn DynamicNode>, AnchorChainError> {
+    let params = config.params::<LoggerParams>()?;
+    Ok(boxed(Logger::<String>::new(&params.prefix)))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PromptParams {
+    template: String,
+    variable: Option<String>,
+}
+
+/// A prompt taking its variables as a JSON object, or as text bound to a single
+/// variable.
+struct ConfigPrompt<I> {
+    prompt: Prompt<'static>,
+    variable: String,
+    _input: PhantomData<fn(I)>,
+}
+
+impl<I> fmt::Debug for ConfigPrompt<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConfigPrompt")
+            .field("prompt", &self.prompt)
+            .field("variable", &self.variable)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl Node for ConfigPrompt<Value> {
+    type Input = Value;
+    type Output = String;
+
+    async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+        self.prompt.render(&input)
+    }
+}
+
+#[async_trait]
+impl Node for ConfigPrompt<String> {
+    type Input = String;
+    type Output = String;
+
+    async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+        self.prompt
+            .render(&HashMap::from([(self.variable.as_str(), input)]))
+    }
+}
+
+fn create_prompt(config: &NodeConfig) -> Result<Box<dyn DynamicNode>, AnchorChainError> {
+    let params = config.params::<PromptParams>()?;
+    let prompt = Prompt::try_new(&params.template)?;
+    Ok(match params.variable {
+        Some(variable) => boxed(ConfigPrompt::<String> {
+            prompt,
+            variable,
+            _input: PhantomData,
+        }),
+        None => boxed(ConfigPrompt::<Value> {
+            prompt,
+            variable: String::new(),
+            _input: PhantomData,
+        }),
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ParallelParams {
+    branches: Vec<Vec<NodeConfig>>,
+    #[serde(default = "default_separator")]
+    separator: String,
+}
+
+fn default_separator() -> String {
+    "\n\n".to_string()
+}
+
+/// Creates a `ParallelNode` running text through each branch.
+struct ParallelFactory;
+
+#[async_trait]
+impl NodeFactory for ParallelFactory {
+    async fn create(
+        &self,
+        config: &NodeConfig,
+        registry: &NodeRegistry,
+    ) -> Result<Box<dyn DynamicNode>, AnchorChainError> {
+        let params = config.params::<ParallelParams>()?;
+        let mut branches: Vec<Box<dyn Node<Input = String, Output = String> + Send + Sync>> =
+            Vec::new();
+        for nodes in params.branches {
+            let branch = registry.build(&ChainConfig { nodes }).await?;
+            branches.push(Box::new(branch.typed::<String, String>()?));
+        }
+        let separator = params.separator;
+        Ok(boxed(ParallelNode::new(
+            branches,
+            to_boxed_future(move |outputs: Vec<String>| Ok(outputs.join(&separator))),
+        )))
+    }
+}
+
+#[cfg(feature = "openai")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OpenAIParams {
+    model: String,
+    #[serde(default = "default_system_prompt")]
+    system_prompt: String,
+}
+
+#[cfg(any(feature = "openai", feature = "bedrock"))]
+fn default_system_prompt() -> String {
+    "You are a helpful assistant".to_string()
+}
+
+/// Creates an `OpenAIChatModel` taking text.
+#[cfg(feature = "openai")]
+struct OpenAIFactory;
+
+#[cfg(feature = "openai")]
+#[async_trait]
+impl NodeFactory for OpenAIFactory {
+    async fn create(
+        &self,
+        config: &NodeConfig,
+        _registry: &NodeRegistry,
+    ) -> Result<Box<dyn DynamicNode>, AnchorChainError> {
+        let params = config.params::<OpenAIParams>()?;
+        let model = crate::models::openai::OpenAIChatModel::<String>::new(
+            params.system_prompt,
+            params.model,
+        )
+        .await;
+        Ok(boxed(model))
+    }
+}
+
+#[cfg(feature = "bedrock")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BedrockParams {
+    model: String,
+    system_prompt: Option<String>,
+}
+
+/// Creates a `BedrockConverse` taking and returning text.
+#[cfg(feature = "bedrock")]
+struct BedrockFactory;
+
+#[cfg(feature = "bedrock")]
+#[async_trait]
+impl NodeFactory for BedrockFactory {
+    async fn create(
+        &self,
+        config: &NodeConfig,
+        _registry: &NodeRegistry,
+    ) -> Result<Box<dyn DynamicNode>, AnchorChainError> {
+        use crate::models::bedrock_converse::{BedrockConverse, BedrockModel};
+
+        let params = config.params::<BedrockParams>()?;
+        let model = BedrockModel::from_id(&params.model).ok_or_else(|| {
+            AnchorChainError::ConfigError(format!("unknown Bedrock model {:?}", params.model))
+        })?;
+        let node = match params.system_prompt {
+            Some(system_prompt) => {
+                BedrockConverse::<String>::new_with_system_prompt(model, system_prompt).await
+            }
+            None => BedrockConverse::<String>::new(model).await,
+        };
+        Ok(boxed(node))
+    }
+}
+
+#[cfg(feature = "ollama")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OllamaParams {
+    model: String,
+    #[serde(default = "default_ollama_host")]
+    host: String,
+    #[serde(default = "default_ollama_port")]
+    port: String,
+}
+
+#[cfg(feature = "ollama")]
+fn default_ollama_host() -> String {
+    "localhost".to_string()
+}
+
+#[cfg(feature = "ollama")]
+fn default_ollama_port() -> String {
+    "11434".to_string()
+}
+
+#[cfg(feature = "ollama")]
+fn create_ollama(config: &NodeConfig) -> Result<Box<dyn DynamicNode>, AnchorChainError> {
+    let params = config.params::<OllamaParams>()?;
+    Ok(boxed(crate::models::ollama::Ollama::<String>::new(
+        &params.model,
+        &params.host,
+        &params.port,
+    )))
+}
+
+#[cfg(all(feature = "opensearch", feature = "openai"))]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OpenSearchRetrieverParams {
+    url: String,
+    username: String,
+    password: String,
+    indexes: Vec<String>,
+    vector_field: String,
+    #[serde(default = "default_top_k")]
+    top_k: usize,
+}
+
+#[cfg(all(feature = "opensearch", feature = "openai"))]
+fn default_top_k() -> usize {
+    5
+}
+
+/// Retrieves documents for text input, returning their text joined by blank lines.
+#[cfg(all(feature = "opensearch", feature = "openai"))]
+#[derive(Debug)]
+struct TextRetriever(
+    crate::vector::opensearch_retriever::OpenSearchRetriever<
+        'static,
+        crate::models::openai::OpenAIEmbeddingModel,
+    >,
+);
+
+#[cfg(all(feature = "opensearch", feature = "openai"))]
+#[async_trait]
+impl Node for TextRetriever {
+    type Input = String;
+    type Output = String;
+
+    async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+        let documents = self.0.retrieve(&input).await?;
+        Ok(documents
+            .into_iter()
+            .map(|document| document.text)
+            .collect::<Vec<_>>()
+            .join("\n\n"))
+    }
+}
+
+/// Creates an `OpenSearchRetriever` using OpenAI embeddings.
+#[cfg(all(feature = "opensearch", feature = "openai"))]
+struct OpenSearchRetrieverFactory;
+
+#[cfg(all(feature = "opensearch", feature = "openai"))]
+#[async_trait]
+impl NodeFactory for OpenSearchRetrieverFactory {
+    async fn create(
+        &self,
+        config: &NodeConfig,
+        _registry: &NodeRegistry,
+    ) -> Result<Box<dyn DynamicNode>, AnchorChainError> {
+        use crate::models::openai::OpenAIEmbeddingModel;
+        use crate::vector::opensearch_client_builder::OpenSearchClientBuilder;
+        use crate::vector::opensearch_retriever::OpenSearchRetriever;
+
+        let params = config.params::<OpenSearchRetrieverParams>()?;
+        let client = OpenSearchClientBuilder::new()
+            .with_local_connection(&params.url, &params.username, &params.password)
+            .build()
+            .await?;
+        let indexes = params
+            .indexes
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        let retriever = OpenSearchRetriever::new(
+            client,
+            OpenAIEmbeddingModel::default(),
+            &indexes,
+            &params.vector_field,
+            params.top_k,
+        )
+        .await;
+        Ok(boxed(TextRetriever(retriever)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Shout;
+
+    #[async_trait]
+    impl Node for Shout {
+        type Input = String;
+        type Output = String;
+
+        async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+            Ok(input.to_uppercase())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registry_builds_and_validates_chains() {
+        let mut registry = NodeRegistry::default();
+        registry.register_node("shout", |_: &NodeConfig| Ok(boxed(Shout)));
+
+        let config = ChainConfig::from_json(
+            r#"{"nodes": [
+                {"type": "prompt", "template": "{{ greeting }}, {{ name }}"},
+                {"type": "parallel", "separator": " | ", "branches": [
+                    [{"type": "shout"}],
+                    [{"type": "noop"}, {"type": "logger", "prefix": "branch"}]
+                ]}
+            ]}"#,
+        )
+        .unwrap();
+        let chain = registry.build(&config).await.unwrap();
+        let input = serde_json::json!({"greeting": "Hello", "name": "Ada"});
+        let output: String = chain.process(input).await.unwrap();
+        assert_eq!(output, "HELLO, ADA | Hello, Ada");
+
+        let unknown = ChainConfig {
+            nodes: vec![NodeConfig::new("missing")],
+        };
+        let error = registry.build(&unknown).await.unwrap_err().to_string();
+        assert!(error.contains("unknown node type \"missing\""));
+
+        let mismatched = ChainConfig {
+            nodes: vec![
+                NodeConfig::new("shout"),
+                NodeConfig::new("prompt").with_param("template", "{{ x }}"),
+            ],
+        };
+        let error = registry.build(&mismatched).await.unwrap_err().to_string();
+        assert!(error.contains("node 1 (prompt) takes serde_json::value::Value"));
+    }
+
+    #[cfg(feature = "yaml")]
+    #[tokio::test]
+    async fn test_yaml_definitions_are_loaded() {
+        let yaml = r#"
+nodes:
+  - type: prompt
+    template: "Summarize the following text:\n{{ input }}"
+    variable: input
+  - type: openai
+    model: gpt-4-turbo-preview
+  - type: logger
+    prefix: summary
+"#;
+        let config = ChainConfig::from_yaml(yaml).unwrap();
+        let types: Vec<_> = config.nodes.iter().map(|node| &node.node_type).collect();
+        assert_eq!(types, ["prompt", "openai", "logger"]);
+        assert_eq!(
+            config.nodes[0].params["template"],
+            "Summarize the following text:\n{{ input }}"
+        );
+        assert_eq!(config.nodes[1].params["model"], "gpt-4-turbo-preview");
+
+        let path =
+            std::env::temp_dir().join(format!("anchor-chain-config-{}.yml", std::process::id()));
+        tokio::fs::write(&path, yaml).await.unwrap();
+        let loaded = ChainConfig::load(&path).await;
+        tokio::fs::remove_file(&path).await.unwrap();
+        assert_eq!(loaded.unwrap(), config);
+    }
+}
@@ -0,0 +1,381 @@
+//! Request-scoped data available to every node of a run.
+//!
+//! A `RunContext` carries data about the current run, such as the session, user
+//! and trace IDs, feature flags and the `UsageLedger` recording model usage,
+//! without adding it to the input of each node. A context is attached to a run with
+//! `Chain::process_with_context` and any node, including nodes within links,
+//! parallel nodes and tools invoked by agents, can read it with
+//! `RunContext::current`.
+//!
+//! The context is scoped to the task running the chain. Nodes spawning tasks
+//! should run them within `RunContext::current().scope(...)` to keep the context.
+//!
+//! A context can also carry a cancellation token and a deadline. Chains stop
+//! processing with `AnchorChainError::Cancelled` or
+//! `AnchorChainError::DeadlineExceeded` as soon as either is reached, dropping any
+//! in-flight work, and long-running nodes check `RunContext::check_current` between
+//! steps.
+
+use std::any::{Any, TypeId};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::time::Instant;
+pub use tokio_util::sync::CancellationToken;
+
+use crate::error::AnchorChainError;
+use crate::state_manager::DEFAULT_SESSION;
+use crate::usage::UsageLedger;
+
+tokio::task_local! {
+    static CONTEXT: RunContext;
+}
+
+/// Data about the current run shared by every node of a chain.
+///
+/// # Example
+/// ```rust
+/// use anchor_chain::{ChainBuilder, NoOpNode, RunContext};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let chain = ChainBuilder::new().link(NoOpNode::<&str>::new()).build();
+///     let context = RunContext::new()
+///         .with_session("conversation-1")
+///         .with_user("user-1")
+///         .with_flag("beta");
+///     let output = chain.process_with_context(context, "hello").await.unwrap();
+///     assert_eq!(output, "hello");
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct RunContext {
+    session_id: Option<String>,
+    user_id: Option<String>,
+    trace_id: Option<String>,
+    run_id: Option<String>,
+    ledger: Option<UsageLedger>,
+    flags: BTreeSet<String>,
+    values: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+    cancellation: Option<CancellationToken>,
+    deadline: Option<Instant>,
+}
+
+impl RunContext {
+    /// Creates an empty context using the default session.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a copy of the context of the current run, or an empty context if
+    /// there is none.
+    pub fn current() -> Self {
+        Self::try_current().unwrap_or_default()
+    }
+
+    /// Returns a copy of the context of the current run if one is set.
+    pub fn try_current() -> Option<Self> {
+        CONTEXT.try_with(Clone::clone).ok()
+    }
+
+    /// Calls `f` with the context of the current run without copying it.
+    pub(crate) fn with_current<R>(f: impl FnOnce(&RunContext) -> R) -> Option<R> {
+        CONTEXT.try_with(f).ok()
+    }
+
+    /// Runs the future with this context as the context of the current run.
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        CONTEXT.scope(self, future).await
+    }
+
+    /// Sets the session whose state stateful nodes use.
+    pub fn with_session(mut self, session_id: impl Into<String>) -> Self {
+        self.session_id = Some(session_id.into());
+        self
+    }
+
+    /// Sets the ID of the user the run is for.
+    pub fn with_user(mut self, user_id: impl Into<String>) -> Self {
+        self.user_id = Some(user_id.into());
+        self
+    }
+
+    /// Sets the ID used to correlate the run with external traces.
+    pub fn with_trace_id(mut self, trace_id: impl Into<String>) -> Self {
+        self.trace_id = Some(trace_id.into());
+        self
+    }
+
+    /// Sets the ID identifying the run, used by `Checkpointed` steps to resume the
+    /// run after a failure.
+    pub fn with_run_id(mut self, run_id: impl Into<String>) -> Self {
+        self.run_id = Some(run_id.into());
+        self
+    }
+
+    /// Sets the ledger recording the usage of model calls made during the run.
+    pub fn with_ledger(mut self, ledger: UsageLedger) -> Self {
+        self.ledger = Some(ledger);
+        self
+    }
+
+    /// Enables a feature flag for the run.
+    pub fn with_flag(mut self, flag: impl Into<String>) -> Self {
+        self.flags.insert(flag.into());
+        self
+    }
+
+    /// Adds a value of any type to the context, replacing any value of the same type.
+    pub fn with_value<T: Any + Send + Sync>(mut self, value: T) -> Self {
+        self.values.insert(TypeId::of::<T>(), Arc::new(value));
+        self
+    }
+
+    /// Sets the token that cancels the run when cancelled.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Sets the time by which the run must finish.
+    ///
+    /// An earlier deadline already set on the context is kept.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(match self.deadline {
+            Some(existing) => existing.min(deadline),
+            None => deadline,
+        });
+        self
+    }
+
+    /// Sets the deadline of the run to `timeout` from now.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(Instant::now() + timeout)
+    }
+
+    /// Returns the session of the run, or `DEFAULT_SESSION` if none is set.
+    pub fn session_id(&self) -> &str {
+        self.session_id.as_deref().unwrap_or(DEFAULT_SESSION)
+    }
+
+    /// Returns the ID of the user the run is for.
+    pub fn user_id(&self) -> Option<&str> {
+        self.user_id.as_deref()
+    }
+
+    /// Returns the trace ID of the run.
+    pub fn trace_id(&self) -> Option<&str> {
+        self.trace_id.as_deref()
+    }
+
+    /// Returns the ID of the run.
+    pub fn run_id(&self) -> Option<&str> {
+        self.run_id.as_deref()
+    }
+
+    /// Returns the ledger recording the usage of the run.
+    pub fn ledger(&self) -> Option<&UsageLedger> {
+        self.ledger.as_ref()
+    }
+
+    /// Returns true if the feature flag is enabled for the run.
+    pub fn is_enabled(&self, flag: &str) -> bool {
+        self.flags.contains(flag)
+    }
+
+    /// Returns the value of type `T` added with `with_value`.
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.values
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    /// Returns the cancellation token of the run.
+    pub fn cancellation_token(&self) -> Option<&CancellationToken> {
+        self.cancellation.as_ref()
+    }
+
+    /// Returns the deadline of the run.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Returns an error if the run has been cancelled or its deadline has passed.
+    pub fn check(&self) -> Result<(), AnchorChainError> {
+        if self
+            .cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+        {
+            return Err(AnchorChainError::Cancelled);
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| deadline <= Instant::now())
+        {
+            return Err(AnchorChainError::DeadlineExceeded);
+        }
+        Ok(())
+    }
+
+    /// Returns an error if the current run has been cancelled or its deadline has
+    /// passed.
+    ///
+    /// Nodes doing work in several steps, such as agent loops, call this between
+    /// steps.
+    pub fn check_current() -> Result<(), AnchorChainError> {
+        Self::with_current(RunContext::check).unwrap_or(Ok(()))
+    }
+
+    /// Waits until the run is cancelled or its deadline passes, returning the
+    /// matching error.
+    async fn interrupted(&self) -> AnchorChainError {
+        let cancelled = async {
+            match &self.cancellation {
+                Some(token) => token.cancelled().await,
+                None => std::future::pending().await,
+            }
+        };
+        let deadline = async {
+            match self.deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            _ = cancelled => AnchorChainError::Cancelled,
+            _ = deadline => AnchorChainError::DeadlineExceeded,
+        }
+    }
+
+    /// Runs the future until it completes, the run is cancelled or its deadline
+    /// passes. The future is dropped if the run is interrupted.
+    pub async fn interruptible<T>(
+        &self,
+        future: impl Future<Output = Result<T, AnchorChainError>>,
+    ) -> Result<T, AnchorChainError> {
+        self.check()?;
+        if self.cancellation.is_none() && self.deadline.is_none() {
+            return future.await;
+        }
+        tokio::select! {
+            biased;
+            error = self.interrupted() => Err(error),
+            result = future => result,
+        }
+    }
+}
+
+impl fmt::Debug for RunContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RunContext")
+            .field("session_id", &self.session_id())
+            .field("user_id", &self.user_id)
+            .field("trace_id", &self.trace_id)
+            .field("run_id", &self.run_id)
+            .field("ledger", &self.ledger.is_some())
+            .field("flags", &self.flags)
+            .field("values", &self.values.len())
+            .field("cancellation", &self.cancellation)
+            .field("deadline", &self.deadline)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::chain::ChainBuilder;
+    use crate::error::AnchorChainError;
+    use crate::node::Node;
+    use crate::parallel_node::{to_boxed_future, ParallelNode};
+    use crate::usage::{record_usage, TokenUsage};
+
+    struct Tenant(&'static str);
+
+    #[derive(Debug, anchor_chain_macros::Stateless)]
+    struct Greeter;
+
+    #[async_trait]
+    impl Node for Greeter {
+        type Input = String;
+        type Output = String;
+
+        async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+            let context = RunContext::current();
+            record_usage("test", "greeter", TokenUsage::new(1, 1), Duration::ZERO);
+            Ok(format!(
+                "{input} {} {} {}",
+                context.user_id().unwrap_or("anonymous"),
+                context.get::<Tenant>().map_or("none", |tenant| tenant.0),
+                context.is_enabled("beta"),
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_context_reaches_every_node() {
+        let parallel = ParallelNode::new(
+            vec![Box::new(Greeter), Box::new(Greeter)],
+            to_boxed_future(|outputs: Vec<String>| Ok(outputs.join(","))),
+        );
+        let chain = ChainBuilder::new().link(Greeter).link(parallel).build();
+
+        let ledger = UsageLedger::new();
+        let context = RunContext::new()
+            .with_user("ada")
+            .with_flag("beta")
+            .with_value(Tenant("acme"))
+            .with_ledger(ledger.clone());
+        let output = chain
+            .process_with_context(context, "hi".to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            output,
+            "hi ada acme true ada acme true,hi ada acme true ada acme true"
+        );
+        assert_eq!(ledger.calls(), 3);
+
+        let output = chain.process("hi".to_string()).await.unwrap();
+        assert!(output.starts_with("hi anonymous none false"));
+    }
+
+    #[derive(Debug, anchor_chain_macros::Stateless)]
+    struct Sleeper;
+
+    #[async_trait]
+    impl Node for Sleeper {
+        type Input = u64;
+        type Output = u64;
+
+        async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+            tokio::time::sleep(Duration::from_secs(input)).await;
+            Ok(input)
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_runs_stop_when_cancelled_or_past_deadline() {
+        let chain = ChainBuilder::new().link(Sleeper).link(Sleeper).build();
+
+        let context = RunContext::new().with_timeout(Duration::from_secs(15));
+        let result = chain.process_with_context(context, 10).await;
+        assert!(matches!(result, Err(AnchorChainError::DeadlineExceeded)));
+
+        let token = CancellationToken::new();
+        let context = RunContext::new().with_cancellation(token.clone());
+        let run = tokio::spawn(async move { chain.process_with_context(context, 10).await });
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        token.cancel();
+        let result = run.await.unwrap();
+        assert!(matches!(result, Err(AnchorChainError::Cancelled)));
+    }
+}
@@ -0,0 +1,447 @@
+//! Structural descriptions of chains and their export as diagrams.
+//!
+//! `Node::describe` returns a `NodeDescription` with the name, kind and input and
+//! output types of a node, along with the nodes it contains. Links, chains,
+//! parallel nodes, dynamic chains, workflows and wrappers such as `Cached`
+//! describe their inner nodes, so describing a chain describes its whole
+//! structure. Descriptions can be rendered as Mermaid flowcharts or Graphviz DOT
+//! graphs for documentation and debugging.
+//!
+//! # Example
+//! ```rust
+//! use anchor_chain::{ChainBuilder, Logger, NoOpNode, Node};
+//!
+//! let chain = ChainBuilder::new()
+//!     .link(NoOpNode::<String>::new())
+//!     .link(Logger::<String>::new("output"))
+//!     .build();
+//!
+//! let mermaid = chain.describe().to_mermaid();
+//! assert!(mermaid.contains("n0 --> n1"));
+//! ```
+
+use std::any::type_name;
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::node::Node;
+
+/// The kind of a described node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeKind {
+    /// A node without inner nodes.
+    Node,
+    /// Inner nodes run one after another, each taking the previous output.
+    Sequence,
+    /// Inner nodes run concurrently on the same input and their outputs are
+    /// combined.
+    Parallel,
+    /// Inner nodes run in the order given by their dependencies.
+    Workflow,
+    /// A node adding behavior, such as caching, around its single inner node.
+    Wrapper,
+}
+
+/// A description of the structure of a node.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct NodeDescription {
+    /// The name of the node's type without module paths.
+    pub name: String,
+    /// The kind of the node.
+    pub kind: NodeKind,
+    /// The name of the node's input type.
+    pub input_type: String,
+    /// The name of the node's output type.
+    pub output_type: String,
+    /// The name of the node within its workflow.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The IDs of the workflow nodes this node depends on.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
+    /// The inner nodes of the node.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<NodeDescription>,
+}
+
+impl NodeDescription {
+    /// Creates a description without inner nodes.
+    pub fn new(
+        name: impl Into<String>,
+        kind: NodeKind,
+        input_type: impl Into<String>,
+        output_type: impl Into<String>,
+    ) -> Self {
+        NodeDescription {
+            name: name.into(),
+            kind,
+            input_type: input_type.into(),
+            output_type: output_type.into(),
+            id: None,
+            dependencies: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Creates a description of a node of type `N` from its type names.
+    pub fn of<N: Node + ?Sized>() -> Self {
+        Self::new(
+            short_type_name(type_name::<N>()),
+            NodeKind::Node,
+            short_type_name(type_name::<N::Input>()),
+            short_type_name(type_name::<N::Output>()),
+        )
+    }
+
+    /// Makes the description a wrapper around `inner`.
+    pub fn wrapping(mut self, inner: NodeDescription) -> Self {
+        self.kind = NodeKind::Wrapper;
+        self.children = vec![inner];
+        self
+    }
+
+    /// Makes the description a sequence of the descriptions in `parts`,
+    /// flattening nested sequences.
+    pub fn sequence(mut self, parts: impl IntoIterator<Item = NodeDescription>) -> Self {
+        self.kind = NodeKind::Sequence;
+        self.children.clear();
+        for part in parts {
+            if part.kind == NodeKind::Sequence && part.id.is_none() {
+                self.children.extend(part.children);
+            } else {
+                self.children.push(part);
+            }
+        }
+        self
+    }
+
+    /// Sets the name of the node.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Sets the kind of the node.
+    pub fn with_kind(mut self, kind: NodeKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Sets the inner nodes of the node.
+    pub fn with_children(mut self, children: Vec<NodeDescription>) -> Self {
+        self.children = children;
+        self
+    }
+
+    /// Sets the name of the node within its workflow.
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Sets the IDs of the workflow nodes the node depends on.
+    pub fn with_dependencies(mut self, dependencies: Vec<String>) -> Self {
+        self.dependencies = dependencies;
+        self
+    }
+
+    /// Renders the description as a Mermaid flowchart.
+    pub fn to_mermaid(&self) -> String {
+        Renderer::new(Format::Mermaid).finish(self)
+    }
+
+    /// Renders the description as a Graphviz DOT graph.
+    pub fn to_dot(&self) -> String {
+        Renderer::new(Format::Dot).finish(self)
+    }
+}
+
+/// Removes module paths from a type name, turning
+/// `anchor_chain::node::NoOpNode<alloc::string::String>` into `NoOpNode<String>`.
+pub(crate) fn short_type_name(name: &str) -> String {
+    let mut short = String::with_capacity(name.len());
+    let mut path = String::new();
+    for c in name.chars() {
+        if c.is_alphanumeric() || c == '_' || c == ':' {
+            path.push(c);
+        } else {
+            short.push_str(path.rsplit("::").next().unwrap_or_default());
+            path.clear();
+            short.push(c);
+        }
+    }
+    short.push_str(path.rsplit("::").next().unwrap_or_default());
+    short
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    Mermaid,
+    Dot,
+}
+
+/// Writes the nodes and clusters of a description, collecting edges to write at
+/// the end so they don't place nodes in the wrong cluster.
+struct Renderer {
+    format: Format,
+    lines: Vec<String>,
+    edges: Vec<(String, String)>,
+    nodes: usize,
+    clusters: usize,
+    depth: usize,
+}
+
+impl Renderer {
+    fn new(format: Format) -> Self {
+        Renderer {
+            format,
+            lines: Vec::new(),
+            edges: Vec::new(),
+            nodes: 0,
+            clusters: 0,
+            depth: 1,
+        }
+    }
+
+    fn finish(mut self, description: &NodeDescription) -> String {
+        self.render(description);
+        let mut output = match self.format {
+            Format::Mermaid => "flowchart LR\n".to_string(),
+            Format::Dot => "digraph {\n    rankdir=LR;\n    node [shape=box];\n".to_string(),
+        };
+        for line in &self.lines {
+            output.push_str(line);
+            output.push('\n');
+        }
+        for (from, to) in &self.edges {
+            match self.format {
+                Format::Mermaid => output.push_str(&format!("    {from} --> {to}\n")),
+                Format::Dot => output.push_str(&format!("    {from} -> {to};\n")),
+            }
+        }
+        if let Format::Dot = self.format {
+            output.push_str("}\n");
+        }
+        output
+    }
+
+    fn line(&mut self, line: String) {
+        self.lines
+            .push(format!("{}{line}", "    ".repeat(self.depth)));
+    }
+
+    fn escape(&self, text: &str) -> String {
+        match self.format {
+            Format::Mermaid => text
+                .replace('"', "#quot;")
+                .replace('<', "#lt;")
+                .replace('>', "#gt;"),
+            Format::Dot => text.replace('\\', "\\\\").replace('"', "\\\""),
+        }
+    }
+
+    fn title(&self, description: &NodeDescription) -> String {
+        let name = self.escape(&description.name);
+        match &description.id {
+            Some(id) => format!("{}: {name}", self.escape(id)),
+            None => name,
+        }
+    }
+
+    fn node(&mut self, title: String, input_type: &str, output_type: &str) -> String {
+        let id = format!("n{}", self.nodes);
+        self.nodes += 1;
+        let types = self.escape(&format!("{input_type} → {output_type}"));
+        let line = match self.format {
+            Format::Mermaid => format!("{id}[\"{title}<br/>{types}\"]"),
+            Format::Dot => format!("{id} [label=\"{title}\\n{types}\"];"),
+        };
+        self.line(line);
+        id
+    }
+
+    fn begin_cluster(&mut self, title: String) {
+        let id = self.clusters;
+        self.clusters += 1;
+        let line = match self.format {
+            Format::Mermaid => format!("subgraph c{id} [\"{title}\"]"),
+            Format::Dot => format!("subgraph cluster_{id} {{"),
+        };
+        self.line(line);
+        self.depth += 1;
+        if let Format::Dot = self.format {
+            self.line(format!("label=\"{title}\";"));
+        }
+    }
+
+    fn end_cluster(&mut self) {
+        self.depth -= 1;
+        let line = match self.format {
+            Format::Mermaid => "end".to_string(),
+            Format::Dot => "}".to_string(),
+        };
+        self.line(line);
+    }
+
+    fn connect(&mut self, from: &[String], to: &[String]) {
+        for source in from {
+            for target in to {
+                self.edges.push((source.clone(), target.clone()));
+            }
+        }
+    }
+
+    /// Renders the description, returning the IDs of the nodes taking its input
+    /// and of the nodes producing its output.
+    fn render(&mut self, description: &NodeDescription) -> (Vec<String>, Vec<String>) {
+        if description.children.is_empty() {
+            let title = self.title(description);
+            let id = self.node(title, &description.input_type, &description.output_type);
+            return (vec![id.clone()], vec![id]);
+        }
+        match description.kind {
+            NodeKind::Node | NodeKind::Sequence => {
+                if description.id.is_some() {
+                    let title = self.title(description);
+                    self.begin_cluster(title);
+                    let ends = self.render_sequence(&description.children);
+                    self.end_cluster();
+                    ends
+                } else {
+                    self.render_sequence(&description.children)
+                }
+            }
+            NodeKind::Wrapper => {
+                let title = self.title(description);
+                self.begin_cluster(title);
+                let ends = self.render_sequence(&description.children);
+                self.end_cluster();
+                ends
+            }
+            NodeKind::Parallel => {
+                let title = self.title(description);
+                self.begin_cluster(title);
+                let mut entries = Vec::new();
+                let mut exits = Vec::new();
+                for child in &description.children {
+                    let (child_entries, child_exits) = self.render(child);
+                    entries.extend(child_entries);
+                    exits.extend(child_exits);
+                }
+                let combine = self.node(
+                    "combine".to_string(),
+                    &format!("Vec<{}>", description.children[0].output_type),
+                    &description.output_type,
+                );
+                self.connect(&exits, std::slice::from_ref(&combine));
+                self.end_cluster();
+                (entries, vec![combine])
+            }
+            NodeKind::Workflow => {
+                let title = self.title(description);
+                self.begin_cluster(title);
+                let mut ends = HashMap::new();
+                for child in &description.children {
+                    let child_ends = self.render(child);
+                    ends.insert(child.id.clone().unwrap_or_default(), child_ends);
+                }
+                let mut entries = Vec::new();
+                let mut exits = Vec::new();
+                for child in &description.children {
+                    let id = child.id.clone().unwrap_or_default();
+                    let (child_entries, child_exits) = ends[&id].clone();
+                    if child.dependencies.is_empty() {
+                        entries.extend(child_entries.iter().cloned());
+                    }
+                    for dependency in &child.dependencies {
+                        if let Some((_, dependency_exits)) = ends.get(dependency) {
+                            let dependency_exits = dependency_exits.clone();
+                            self.connect(&dependency_exits, &child_entries);
+                        }
+                    }
+                    if !description
+                        .children
+                        .iter()
+                        .any(|other| other.dependencies.contains(&id))
+                    {
+                        exits.extend(child_exits);
+                    }
+                }
+                self.end_cluster();
+                (entries, exits)
+            }
+        }
+    }
+
+    fn render_sequence(&mut self, children: &[NodeDescription]) -> (Vec<String>, Vec<String>) {
+        let mut entries = None;
+        let mut exits: Vec<String> = Vec::new();
+        for child in children {
+            let (child_entries, child_exits) = self.render(child);
+            self.connect(&exits, &child_entries);
+            entries.get_or_insert(child_entries);
+            exits = child_exits;
+        }
+        (entries.unwrap_or_default(), exits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::ChainBuilder;
+    use crate::node::NoOpNode;
+    use crate::nodes::logger::Logger;
+    use crate::parallel_node::{to_boxed_future, ParallelNode};
+
+    #[test]
+    fn test_chains_describe_their_structure() {
+        let parallel = ParallelNode::new(
+            vec![
+                Box::new(NoOpNode::<String>::new()),
+                Box::new(Logger::<String>::new("branch")),
+            ],
+            to_boxed_future(|outputs: Vec<String>| Ok(outputs.concat())),
+        );
+        let chain = ChainBuilder::new()
+            .link(NoOpNode::<String>::new())
+            .link(parallel)
+            .link(Logger::<String>::new("output"))
+            .build();
+
+        let description = chain.describe();
+        assert_eq!(description.kind, NodeKind::Sequence);
+        assert_eq!(
+            description
+                .children
+                .iter()
+                .map(|child| (child.name.as_str(), child.kind))
+                .collect::<Vec<_>>(),
+            vec![
+                ("NoOpNode<String>", NodeKind::Node),
+                ("ParallelNode<String, String, String>", NodeKind::Parallel),
+                ("Logger<String>", NodeKind::Node),
+            ]
+        );
+        assert_eq!(description.children[1].children.len(), 2);
+
+        let mermaid = description.to_mermaid();
+        assert!(mermaid.starts_with("flowchart LR\n"));
+        assert!(mermaid.contains("n0[\"NoOpNode#lt;String#gt;<br/>String → String\"]"));
+        for edge in [
+            "n0 --> n1",
+            "n0 --> n2",
+            "n1 --> n3",
+            "n2 --> n3",
+            "n3 --> n4",
+        ] {
+            assert!(mermaid.contains(edge), "missing {edge} in {mermaid}");
+        }
+
+        let dot = description.to_dot();
+        assert!(dot.contains("subgraph cluster_0 {"));
+        assert!(dot.contains("n3 -> n4;"));
+    }
+}
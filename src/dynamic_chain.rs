@@ -0,0 +1,417 @@
+//! Chains assembled at runtime.
+//!
+//! `ChainBuilder` produces nested `Link` types fixed at compile time. A
+//! `DynamicChain` instead holds a list of boxed nodes passing type-erased
+//! `Payload`s, so chains can be assembled from configuration or user choices. The
+//! input and output types of adjacent nodes are checked when the chain is built,
+//! and a dynamic chain can be used as a typed node within a static chain with
+//! `DynamicChain::typed`.
+
+use std::any::{type_name, Any, TypeId};
+use std::fmt;
+use std::marker::PhantomData;
+
+use async_trait::async_trait;
+#[cfg(feature = "tracing")]
+use tracing::instrument;
+
+use crate::chain_state::ChainState;
+use crate::context::RunContext;
+use crate::describe::{short_type_name, NodeDescription, NodeKind};
+use crate::error::AnchorChainError;
+use crate::link::WithState;
+use crate::node::{Node, Stateful, Stateless};
+
+/// The type of a `Payload`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PayloadType {
+    id: TypeId,
+    name: &'static str,
+}
+
+impl PayloadType {
+    /// Returns the payload type of `T`.
+    pub fn of<T: Any>() -> Self {
+        PayloadType {
+            id: TypeId::of::<T>(),
+            name: type_name::<T>(),
+        }
+    }
+
+    /// Returns the name of the type.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl fmt::Display for PayloadType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+/// A value of any type passed between the nodes of a `DynamicChain`.
+pub struct Payload {
+    value: Box<dyn Any + Send>,
+    payload_type: PayloadType,
+}
+
+impl Payload {
+    /// Wraps the value in a payload.
+    pub fn new<T: Any + Send>(value: T) -> Self {
+        Payload {
+            value: Box::new(value),
+            payload_type: PayloadType::of::<T>(),
+        }
+    }
+
+    /// Returns the type of the value.
+    pub fn payload_type(&self) -> PayloadType {
+        self.payload_type
+    }
+
+    /// Returns the value if it has type `T`.
+    pub fn downcast<T: Any>(self) -> Result<T, AnchorChainError> {
+        let found = self.payload_type;
+        self.value
+            .downcast::<T>()
+            .map(|value| *value)
+            .map_err(|_| AnchorChainError::TypeMismatch {
+                expected: type_name::<T>().to_string(),
+                found: found.to_string(),
+            })
+    }
+}
+
+impl fmt::Debug for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Payload")
+            .field("type", &self.payload_type.name)
+            .finish()
+    }
+}
+
+/// A node with type-erased input and output that can be linked into a
+/// `DynamicChain`.
+///
+/// Any `Node` with `'static` input and output types can be boxed as a
+/// `DynamicNode` with `boxed`.
+#[async_trait]
+pub trait DynamicNode: fmt::Debug + Send + Sync {
+    /// Returns the type of input the node accepts.
+    fn input_type(&self) -> PayloadType;
+
+    /// Returns the type of output the node produces.
+    fn output_type(&self) -> PayloadType;
+
+    /// Processes the input, which must have the node's input type.
+    async fn process_payload(&self, input: Payload) -> Result<Payload, AnchorChainError>;
+
+    /// Returns a description of the node's structure, including any inner nodes.
+    fn describe(&self) -> NodeDescription {
+        NodeDescription::new(
+            short_type_name(type_name::<Self>()),
+            NodeKind::Node,
+            short_type_name(self.input_type().name()),
+            short_type_name(self.output_type().name()),
+        )
+    }
+}
+
+/// Adapts a typed node to a `DynamicNode`.
+#[derive(Debug)]
+struct Erased<N>(N);
+
+#[async_trait]
+impl<N> DynamicNode for Erased<N>
+where
+    N: Node + Send + Sync,
+    N::Input: Any + Send,
+    N::Output: Any + Send,
+{
+    fn input_type(&self) -> PayloadType {
+        PayloadType::of::<N::Input>()
+    }
+
+    fn output_type(&self) -> PayloadType {
+        PayloadType::of::<N::Output>()
+    }
+
+    async fn process_payload(&self, input: Payload) -> Result<Payload, AnchorChainError> {
+        let output = self.0.process(input.downcast()?).await?;
+        Ok(Payload::new(output))
+    }
+
+    fn describe(&self) -> NodeDescription {
+        self.0.describe()
+    }
+}
+
+/// Boxes a typed node as a `DynamicNode`.
+pub fn boxed<N>(node: N) -> Box<dyn DynamicNode>
+where
+    N: Node + Send + Sync + 'static,
+    N::Input: Any + Send,
+    N::Output: Any + Send,
+{
+    Box::new(Erased(node))
+}
+
+/// A chain of boxed nodes assembled at runtime.
+///
+/// # Example
+/// ```rust
+/// use anchor_chain::{DynamicChain, Logger, NoOpNode};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut builder = DynamicChain::builder().link(NoOpNode::<String>::new());
+///     if std::env::var("VERBOSE").is_ok() {
+///         builder = builder.link(Logger::<String>::new("output"));
+///     }
+///     let chain = builder.build().expect("Adjacent node types should match");
+///     let output: String = chain.process("hello".to_string()).await.unwrap();
+///     assert_eq!(output, "hello");
+/// }
+/// ```
+#[derive(Debug)]
+pub struct DynamicChain {
+    nodes: Vec<Box<dyn DynamicNode>>,
+}
+
+impl DynamicChain {
+    /// Returns a builder for a dynamic chain.
+    pub fn builder() -> DynamicChainBuilder {
+        DynamicChainBuilder::new()
+    }
+
+    /// Processes the input through the chain, returning an error if the input or
+    /// output types don't match the chain.
+    pub async fn process<I, O>(&self, input: I) -> Result<O, AnchorChainError>
+    where
+        I: Any + Send,
+        O: Any,
+    {
+        let output = self.process_payload(Payload::new(input)).await?;
+        output.downcast()
+    }
+
+    /// Returns a typed node running the chain, or an error if the chain's input
+    /// and output types aren't `I` and `O`.
+    pub fn typed<I, O>(self) -> Result<TypedDynamicChain<I, O>, AnchorChainError>
+    where
+        I: Any,
+        O: Any,
+    {
+        check_types(PayloadType::of::<I>(), self.input_type())?;
+        check_types(self.output_type(), PayloadType::of::<O>())?;
+        Ok(TypedDynamicChain {
+            chain: self,
+            _types: PhantomData,
+        })
+    }
+}
+
+/// Returns an error if a value of type `found` can't be passed where `expected`
+/// is accepted.
+fn check_types(found: PayloadType, expected: PayloadType) -> Result<(), AnchorChainError> {
+    if found != expected {
+        return Err(AnchorChainError::TypeMismatch {
+            expected: expected.to_string(),
+            found: found.to_string(),
+        });
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl DynamicNode for DynamicChain {
+    fn input_type(&self) -> PayloadType {
+        self.nodes[0].input_type()
+    }
+
+    fn output_type(&self) -> PayloadType {
+        self.nodes[self.nodes.len() - 1].output_type()
+    }
+
+    /// Processes the input through each node in turn.
+    ///
+    /// Processing stops if the `RunContext` of the run is cancelled or its
+    /// deadline passes.
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    async fn process_payload(&self, input: Payload) -> Result<Payload, AnchorChainError> {
+        let context = RunContext::current();
+        context
+            .interruptible(async {
+                let mut payload = input;
+                for node in &self.nodes {
+                    context.check()?;
+                    payload = node.process_payload(payload).await?;
+                }
+                Ok(payload)
+            })
+            .await
+    }
+
+    /// Describes the nodes of the chain as a sequence.
+    fn describe(&self) -> NodeDescription {
+        NodeDescription::new(
+            "DynamicChain",
+            NodeKind::Sequence,
+            short_type_name(self.input_type().name()),
+            short_type_name(self.output_type().name()),
+        )
+        .sequence(self.nodes.iter().map(|node| node.describe()))
+    }
+}
+
+/// A builder for constructing a `DynamicChain`.
+///
+/// Types of adjacent nodes are checked by `build`, so nodes can be added in any
+/// order at runtime.
+#[derive(Debug, Default)]
+pub struct DynamicChainBuilder {
+    nodes: Vec<Box<dyn DynamicNode>>,
+    state: ChainState,
+}
+
+impl DynamicChainBuilder {
+    /// Creates a new empty `DynamicChainBuilder`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a stateless node to the end of the chain.
+    pub fn link<N>(self, node: N) -> Self
+    where
+        N: Node + Stateless + Send + Sync + 'static,
+        N::Input: Any + Send,
+        N::Output: Any + Send,
+    {
+        self.link_boxed(boxed(node))
+    }
+
+    /// Adds a stateful node to the end of the chain, which is passed the
+    /// `ChainState` shared by the stateful nodes of the chain.
+    pub fn link_with_state<N>(self, node: N) -> Self
+    where
+        N: Node + Stateful + Send + Sync + 'static,
+        N::Input: Any + Send,
+        N::Output: Any + Send,
+    {
+        let node = WithState::new(node, self.state.clone());
+        self.link_boxed(boxed(node))
+    }
+
+    /// Adds a boxed node, such as another `DynamicChain`, to the end of the chain.
+    pub fn link_boxed(mut self, node: Box<dyn DynamicNode>) -> Self {
+        self.nodes.push(node);
+        self
+    }
+
+    /// Returns the `ChainState` shared by the stateful nodes of the chain.
+    pub fn state(&self) -> ChainState {
+        self.state.clone()
+    }
+
+    /// Builds the chain, returning an error if the chain is empty or the output
+    /// type of a node doesn't match the input type of the next node.
+    pub fn build(self) -> Result<DynamicChain, AnchorChainError> {
+        if self.nodes.is_empty() {
+            return Err(AnchorChainError::InvalidInputError(
+                "dynamic chain has no nodes".to_string(),
+            ));
+        }
+        for pair in self.nodes.windows(2) {
+            check_types(pair[0].output_type(), pair[1].input_type())?;
+        }
+        Ok(DynamicChain { nodes: self.nodes })
+    }
+}
+
+/// A `DynamicChain` with known input and output types that can be linked into a
+/// statically typed chain.
+pub struct TypedDynamicChain<I, O> {
+    chain: DynamicChain,
+    _types: PhantomData<fn(I) -> O>,
+}
+
+impl<I, O> fmt::Debug for TypedDynamicChain<I, O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.chain.fmt(f)
+    }
+}
+
+#[async_trait]
+impl<I, O> Node for TypedDynamicChain<I, O>
+where
+    I: Any + Send,
+    O: Any + Send,
+{
+    type Input = I;
+    type Output = O;
+
+    async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+        self.chain.process(input).await
+    }
+
+    fn describe(&self) -> NodeDescription {
+        DynamicNode::describe(&self.chain)
+    }
+}
+
+impl<I, O> Stateless for TypedDynamicChain<I, O>
+where
+    I: Any + Send,
+    O: Any + Send,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::ChainBuilder;
+    use crate::node::NoOpNode;
+
+    #[derive(Debug, anchor_chain_macros::Stateless)]
+    struct Length;
+
+    #[async_trait]
+    impl Node for Length {
+        type Input = String;
+        type Output = usize;
+
+        async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+            Ok(input.len())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dynamic_chain_checks_adjacent_types() {
+        let chain = DynamicChain::builder()
+            .link(NoOpNode::<String>::new())
+            .link(Length)
+            .build()
+            .unwrap();
+        assert_eq!(
+            chain
+                .process::<_, usize>("hello".to_string())
+                .await
+                .unwrap(),
+            5
+        );
+        assert!(matches!(
+            chain.process::<_, usize>(5_usize).await,
+            Err(AnchorChainError::TypeMismatch { .. })
+        ));
+
+        let result = DynamicChain::builder().link(Length).link(Length).build();
+        assert!(matches!(result, Err(AnchorChainError::TypeMismatch { .. })));
+
+        let static_chain = ChainBuilder::new()
+            .link(chain.typed::<String, usize>().unwrap())
+            .link(NoOpNode::new())
+            .build();
+        assert_eq!(static_chain.process("hi".to_string()).await.unwrap(), 2);
+    }
+}
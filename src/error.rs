@@ -21,11 +21,11 @@ pub enum AnchorChainError {
     /// Occurs when failing to construct or invoke a model in Bedrock.
     #[cfg(feature = "bedrock")]
     #[error("Bedrock error: {0}")]
-    BedrockError(#[from] SdkError<InvokeModelError>),
+    BedrockError(Box<SdkError<InvokeModelError>>),
 
     #[cfg(feature = "bedrock")]
     #[error("Bedrock Converse Error: {0}")]
-    BedrockConverse(#[from] SdkError<ConverseError>),
+    BedrockConverse(Box<SdkError<ConverseError>>),
 
     /// Error constructing or rendering Tera templates.
     #[error("error constructing or rendering Tera template: {0}")]
@@ -55,12 +55,81 @@ pub enum AnchorChainError {
     #[error("invalid input: {0}")]
     InvalidInputError(String),
 
+    /// Error when a request uses a feature the model doesn't support.
+    #[error("unsupported model capability: {0}")]
+    UnsupportedCapability(String),
+
+    /// Error when a reviewer rejects an output awaiting approval.
+    #[error("approval rejected: {0}")]
+    ApprovalRejected(String),
+
+    /// Error when an approval request can't be delivered or answered.
+    #[error("approval channel error: {0}")]
+    ApprovalChannelError(String),
+
+    /// Error when a workflow is invalid or a node reads an output it wasn't passed.
+    #[error("workflow error: {0}")]
+    WorkflowError(String),
+
+    /// Error when a chain definition is invalid.
+    #[error("invalid chain configuration: {0}")]
+    ConfigError(String),
+
+    /// Error when the output of a node doesn't match the input of the next node in
+    /// a dynamic chain.
+    #[error("type mismatch: expected {expected}, found {found}")]
+    TypeMismatch { expected: String, found: String },
+
+    /// Error when a run is cancelled through its cancellation token.
+    #[error("run cancelled")]
+    Cancelled,
+
+    /// Error when a run doesn't finish before its deadline.
+    #[error("deadline exceeded")]
+    DeadlineExceeded,
+
+    /// Error reading or writing persisted state.
+    #[error("state store error: {0}")]
+    StateStoreError(String),
+
+    /// Error reading or writing a cache backend.
+    #[error("cache error: {0}")]
+    CacheError(String),
+
+    /// Error when a spending budget has been used up.
+    #[error("budget exceeded: spent ${spent:.4} of ${limit:.4}")]
+    BudgetExceeded { spent: f64, limit: f64 },
+
+    /// Error reading, writing or replaying a test cassette.
+    #[cfg(any(test, feature = "testing"))]
+    #[error("cassette error: {0}")]
+    CassetteError(String),
+
     /// Generic error calling a model.
     #[error("error processing model response: {0}")]
     ModelError(String),
 
+    /// Error loading or running a local model.
+    #[cfg(feature = "gguf")]
+    #[error("candle error: {0}")]
+    CandleError(#[from] candle_core::Error),
+
     // Reqwest error
     #[cfg(feature = "ollama")]
     #[error("reqwest error: {0}")]
     ReqwestError(#[from] reqwest::Error),
 }
+
+#[cfg(feature = "bedrock")]
+impl From<SdkError<InvokeModelError>> for AnchorChainError {
+    fn from(error: SdkError<InvokeModelError>) -> Self {
+        AnchorChainError::BedrockError(Box::new(error))
+    }
+}
+
+#[cfg(feature = "bedrock")]
+impl From<SdkError<ConverseError>> for AnchorChainError {
+    fn from(error: SdkError<ConverseError>) -> Self {
+        AnchorChainError::BedrockConverse(Box::new(error))
+    }
+}
@@ -96,34 +96,64 @@
 struct _README;
 
 pub mod agents;
+pub mod cache;
 pub mod chain;
+pub mod chain_state;
+pub mod checkpoint;
+pub mod config;
+pub mod context;
+pub mod describe;
+pub mod dynamic_chain;
 mod error;
+pub mod limits;
 mod link;
-mod state_manager;
+pub mod memory;
+pub mod state_manager;
+pub mod state_store;
 // TODO: Add impls for Ollama
 pub mod models;
 pub mod node;
 pub mod nodes;
 pub mod parallel_node;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod tokenizer;
+pub mod usage;
 pub mod vector;
+pub mod workflow;
 
 #[cfg(feature = "bedrock")]
 pub use agents::agent_executor::AgentExecutor;
 pub use agents::tool_registry::ToolRegistry;
 pub use chain::ChainBuilder;
+pub use chain_state::{ChainState, StateKey};
+pub use checkpoint::{Checkpointed, Checkpoints};
+pub use config::{ChainConfig, NodeRegistry};
+pub use context::RunContext;
+pub use describe::{NodeDescription, NodeKind};
+pub use dynamic_chain::{DynamicChain, DynamicNode};
 pub use error::AnchorChainError;
 pub use link::Link;
+pub use models::multimodal::MultimodalInput;
 pub use node::NoOpNode;
 pub use node::Node;
 pub use node::Stateless;
+pub use nodes::approval::ApprovalNode;
+pub use nodes::context_trimmer::{ContextItem, ContextTrimmer};
 pub use nodes::logger::Logger;
 pub use nodes::prompt::Prompt;
 pub use parallel_node::to_boxed_future;
 pub use parallel_node::ParallelNode;
 pub use state_manager::StateManager;
+pub use usage::UsageLedger;
+pub use workflow::{Workflow, WorkflowBuilder};
 
 #[cfg(feature = "bedrock")]
 pub use models::bedrock_converse::BedrockConverse;
+#[cfg(feature = "bedrock")]
+pub use models::bedrock_embedding::BedrockEmbeddingModel;
+#[cfg(feature = "gguf")]
+pub use models::gguf::GgufModel;
 #[cfg(feature = "ollama")]
 pub use models::ollama::Ollama;
 #[cfg(feature = "openai")]
@@ -142,5 +172,8 @@ pub use vector::opensearch_client_builder::OpenSearchClientBuilder;
 pub use vector::opensearch_indexer::OpenSearchIndexer;
 #[cfg(feature = "opensearch")]
 pub use vector::opensearch_retriever::OpenSearchRetriever;
+#[cfg(feature = "opensearch")]
+pub use vector::opensearch_vector_store::OpenSearchVectorStore;
+pub use vector::vector_store::{InMemoryVectorStore, MetadataFilter, VectorStore};
 
 pub use ctor;
@@ -0,0 +1,500 @@
+//! Rate limits and spending budgets for model calls.
+//!
+//! `Limited` wraps any `Node` and enforces a shared `RateLimiter` and `Budget`
+//! before each call. Limiters and budgets are cheap to clone and clones share the
+//! same quota, so wrapping every model node in a chain, including nodes running in
+//! parallel branches, with clones of the same limiter enforces a single quota
+//! across all of them. Token usage is taken from the `UsageLedger` records made by
+//! the wrapped node.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::num::{NonZeroU32, NonZeroU64};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::time::Instant;
+#[cfg(feature = "tracing")]
+use tracing::instrument;
+
+use crate::describe::NodeDescription;
+use crate::error::AnchorChainError;
+use crate::node::{Node, Stateless};
+use crate::usage::{PriceTable, UsageLedger, UsageRecord};
+
+/// Request and token limits for a provider over a time window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    /// The maximum number of requests started within the window.
+    pub requests: Option<NonZeroU32>,
+    /// The maximum number of tokens used within the window.
+    pub tokens: Option<NonZeroU64>,
+    /// The length of the sliding window.
+    pub window: Duration,
+}
+
+impl RateLimit {
+    /// Creates an unrestricted limit over a one minute window.
+    pub fn per_minute() -> Self {
+        RateLimit {
+            requests: None,
+            tokens: None,
+            window: Duration::from_secs(60),
+        }
+    }
+
+    /// Sets the maximum number of requests per window.
+    ///
+    /// Returns an error if `requests` is 0.
+    pub fn with_requests(mut self, requests: u32) -> Result<Self, AnchorChainError> {
+        self.requests = Some(NonZeroU32::new(requests).ok_or_else(|| {
+            AnchorChainError::InvalidInputError("request limit must be at least 1".to_string())
+        })?);
+        Ok(self)
+    }
+
+    /// Sets the maximum number of tokens per window.
+    ///
+    /// Returns an error if `tokens` is 0.
+    pub fn with_tokens(mut self, tokens: u64) -> Result<Self, AnchorChainError> {
+        self.tokens = Some(NonZeroU64::new(tokens).ok_or_else(|| {
+            AnchorChainError::InvalidInputError("token limit must be at least 1".to_string())
+        })?);
+        Ok(self)
+    }
+}
+
+/// Requests and token usage recorded for a provider within the current window.
+#[derive(Debug, Default)]
+struct Window {
+    requests: VecDeque<Instant>,
+    tokens: VecDeque<(Instant, u64)>,
+}
+
+impl Window {
+    fn prune(&mut self, now: Instant, window: Duration) {
+        while matches!(self.requests.front(), Some(start) if now.duration_since(*start) >= window) {
+            self.requests.pop_front();
+        }
+        while matches!(self.tokens.front(), Some((start, _)) if now.duration_since(*start) >= window)
+        {
+            self.tokens.pop_front();
+        }
+    }
+
+    /// Returns how long to wait before another request is allowed under `limit`.
+    fn wait_time(&self, now: Instant, limit: &RateLimit) -> Option<Duration> {
+        let request_wait = match (limit.requests, self.requests.front()) {
+            (Some(max), Some(oldest)) if self.requests.len() >= max.get() as usize => {
+                Some(limit.window.saturating_sub(now.duration_since(*oldest)))
+            }
+            _ => None,
+        };
+        let used = self.tokens.iter().map(|(_, tokens)| tokens).sum::<u64>();
+        let token_wait = match (limit.tokens, self.tokens.front()) {
+            (Some(max), Some((oldest, _))) if used >= max.get() => {
+                Some(limit.window.saturating_sub(now.duration_since(*oldest)))
+            }
+            _ => None,
+        };
+        request_wait.max(token_wait)
+    }
+}
+
+/// Enforces requests-per-window and tokens-per-window limits per provider.
+///
+/// Providers without a configured limit are not restricted. Clones share the
+/// same quota.
+///
+/// # Example
+/// ```rust
+/// use anchor_chain::limits::{RateLimit, RateLimiter};
+///
+/// # fn main() -> Result<(), anchor_chain::AnchorChainError> {
+/// let limiter = RateLimiter::new()
+///     .with_limit("openai", RateLimit::per_minute().with_requests(500)?.with_tokens(30_000)?)
+///     .with_limit("bedrock", RateLimit::per_minute().with_requests(50)?);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    limits: HashMap<String, RateLimit>,
+    windows: Arc<Mutex<HashMap<String, Window>>>,
+}
+
+impl RateLimiter {
+    /// Creates a new `RateLimiter` without any limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the limit for a provider.
+    pub fn with_limit(mut self, provider: impl Into<String>, limit: RateLimit) -> Self {
+        self.limits.insert(provider.into(), limit);
+        self
+    }
+
+    /// Waits until the provider has quota available and reserves a request.
+    pub async fn acquire(&self, provider: &str) {
+        let Some(limit) = self.limits.get(provider) else {
+            return;
+        };
+        loop {
+            let wait = {
+                let mut windows = self.windows.lock().expect("Rate limiter lock poisoned");
+                let window = windows.entry(provider.to_string()).or_default();
+                let now = Instant::now();
+                window.prune(now, limit.window);
+                match window.wait_time(now, limit) {
+                    Some(wait) => wait,
+                    None => {
+                        window.requests.push_back(now);
+                        return;
+                    }
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Records the tokens used by a request against the provider's quota.
+    pub fn record(&self, provider: &str, tokens: u64) {
+        if !self.limits.contains_key(provider) {
+            return;
+        }
+        self.windows
+            .lock()
+            .expect("Rate limiter lock poisoned")
+            .entry(provider.to_string())
+            .or_default()
+            .tokens
+            .push_back((Instant::now(), tokens));
+    }
+}
+
+impl fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimiter")
+            .field("limits", &self.limits)
+            .finish()
+    }
+}
+
+/// A spending limit in US dollars.
+///
+/// Create a `Budget` per run to limit the cost of a single run or keep one per
+/// tenant and share it across runs. Clones share the same spending. Models missing
+/// from the price table are not charged.
+///
+/// The cost of a call is only known once it finishes, so calls running at the
+/// same time can together overshoot the limit. Set a reservation with
+/// `with_reservation` to only start calls while their reserved cost fits in the
+/// remaining budget.
+#[derive(Clone, Debug)]
+pub struct Budget {
+    limit: f64,
+    prices: PriceTable,
+    reservation: f64,
+    spending: Arc<Mutex<Spending>>,
+}
+
+/// The amount spent and the amount reserved by running calls.
+#[derive(Debug, Default)]
+struct Spending {
+    spent: f64,
+    reserved: f64,
+}
+
+/// A reservation of a running call, released when dropped.
+struct Reservation<'a> {
+    budget: &'a Budget,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        self.budget.spending().reserved -= self.budget.reservation;
+    }
+}
+
+impl Budget {
+    /// Creates a new `Budget` with the given limit and model prices.
+    pub fn new(limit: f64, prices: PriceTable) -> Self {
+        Budget {
+            limit,
+            prices,
+            reservation: 0.0,
+            spending: Arc::new(Mutex::new(Spending::default())),
+        }
+    }
+
+    /// Reserves `cost` from the budget for each call while it runs.
+    pub fn with_reservation(mut self, cost: f64) -> Self {
+        self.reservation = cost;
+        self
+    }
+
+    fn spending(&self) -> std::sync::MutexGuard<'_, Spending> {
+        self.spending.lock().expect("Budget lock poisoned")
+    }
+
+    /// Returns the limit of the budget.
+    pub fn limit(&self) -> f64 {
+        self.limit
+    }
+
+    /// Returns the amount spent so far.
+    pub fn spent(&self) -> f64 {
+        self.spending().spent
+    }
+
+    /// Returns the amount left before the budget is exceeded.
+    pub fn remaining(&self) -> f64 {
+        (self.limit - self.spent()).max(0.0)
+    }
+
+    /// Returns `AnchorChainError::BudgetExceeded` if the budget has been used up or
+    /// can't fit another reservation.
+    pub fn check(&self) -> Result<(), AnchorChainError> {
+        self.admit(&self.spending())
+    }
+
+    /// Returns an error unless the spending leaves room for another call.
+    fn admit(&self, spending: &Spending) -> Result<(), AnchorChainError> {
+        let exceeded = if self.reservation > 0.0 {
+            spending.spent + spending.reserved + self.reservation > self.limit
+        } else {
+            spending.spent >= self.limit
+        };
+        if exceeded {
+            Err(AnchorChainError::BudgetExceeded {
+                spent: spending.spent,
+                limit: self.limit,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks the budget and reserves the cost of a call in one step.
+    fn reserve(&self) -> Result<Reservation<'_>, AnchorChainError> {
+        let mut spending = self.spending();
+        self.admit(&spending)?;
+        spending.reserved += self.reservation;
+        Ok(Reservation { budget: self })
+    }
+
+    /// Adds the cost of a model call to the amount spent.
+    pub fn charge(&self, record: &UsageRecord) {
+        if let Some(cost) = self.prices.cost(record) {
+            self.spending().spent += cost;
+        }
+    }
+}
+
+/// Node that enforces a rate limit and budget on calls to the wrapped node.
+///
+/// Before each call the budget is checked and a request is reserved from the rate
+/// limiter. The usage recorded by the wrapped node is then charged against both,
+/// with tokens counted against the limiter's provider, and passed on to the ledger
+/// of the current run.
+///
+/// # Example
+/// ```rust,no_run
+/// use anchor_chain::limits::{Budget, Limited, RateLimit, RateLimiter};
+/// use anchor_chain::usage::{ModelPrice, PriceTable};
+/// use anchor_chain::{ChainBuilder, OpenAIModel};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let limiter = RateLimiter::new()
+///         .with_limit("openai", RateLimit::per_minute().with_requests(500).unwrap());
+///     let prices = PriceTable::new()
+///         .with_price("openai", "gpt-3.5-turbo", ModelPrice::new(0.5, 1.5));
+///
+///     let chain = ChainBuilder::new()
+///         .link(
+///             Limited::new(OpenAIModel::new_gpt3_5_turbo("You are a helpful assistant").await)
+///                 .with_rate_limiter(limiter, "openai")
+///                 .with_budget(Budget::new(1.0, prices)),
+///         )
+///         .build();
+///
+///     let output = chain.process("Hello").await.expect("Error processing chain");
+///     println!("{}", output);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Limited<N> {
+    node: N,
+    rate_limiter: Option<(RateLimiter, String)>,
+    budget: Option<Budget>,
+}
+
+impl<N> Limited<N> {
+    /// Wraps the node without any limits.
+    pub fn new(node: N) -> Self {
+        Limited {
+            node,
+            rate_limiter: None,
+            budget: None,
+        }
+    }
+
+    /// Reserves a request from the limiter for `provider` before each call.
+    pub fn with_rate_limiter(mut self, limiter: RateLimiter, provider: impl Into<String>) -> Self {
+        self.rate_limiter = Some((limiter, provider.into()));
+        self
+    }
+
+    /// Fails calls with `AnchorChainError::BudgetExceeded` once the budget is used up.
+    pub fn with_budget(mut self, budget: Budget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+}
+
+#[async_trait]
+impl<N> Node for Limited<N>
+where
+    N: Node + Send + Sync,
+    N::Input: Send,
+    N::Output: Send,
+{
+    type Input = N::Input;
+    type Output = N::Output;
+
+    /// Processes the input with the wrapped node once quota is available.
+    #[cfg_attr(feature = "tracing", instrument(skip(self, input)))]
+    async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+        let _reservation = match &self.budget {
+            Some(budget) => Some(budget.reserve()?),
+            None => None,
+        };
+        if let Some((limiter, provider)) = &self.rate_limiter {
+            limiter.acquire(provider).await;
+        }
+
+        let ledger = UsageLedger::new();
+        let output = ledger.scope(self.node.process(input)).await;
+
+        let parent = UsageLedger::current();
+        for record in ledger.records() {
+            if let Some((limiter, provider)) = &self.rate_limiter {
+                limiter.record(provider, record.usage.total_tokens());
+            }
+            if let Some(budget) = &self.budget {
+                budget.charge(&record);
+            }
+            if let Some(parent) = &parent {
+                parent.record(record);
+            }
+        }
+        output
+    }
+
+    /// Describes the node as a wrapper around its inner node.
+    fn describe(&self) -> NodeDescription {
+        NodeDescription::of::<Self>().wrapping(self.node.describe())
+    }
+}
+
+impl<N> Stateless for Limited<N>
+where
+    N: Node + Stateless + Send + Sync,
+    N::Input: Send,
+    N::Output: Send,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usage::{record_usage, ModelPrice, TokenUsage};
+
+    #[derive(Debug)]
+    struct FakeModel;
+
+    #[async_trait]
+    impl Node for FakeModel {
+        type Input = u64;
+        type Output = u64;
+
+        async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+            record_usage("fake", "model", TokenUsage::new(input, 0), Duration::ZERO);
+            Ok(input)
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limiter_waits_for_window() {
+        let limiter = RateLimiter::new().with_limit(
+            "fake",
+            RateLimit::per_minute()
+                .with_requests(2)
+                .unwrap()
+                .with_tokens(100)
+                .unwrap(),
+        );
+        let node = Limited::new(FakeModel).with_rate_limiter(limiter.clone(), "fake");
+
+        let start = Instant::now();
+        node.process(10).await.unwrap();
+        node.process(10).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        node.process(100).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(60));
+        node.process(10).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(120));
+
+        assert!(RateLimit::per_minute().with_requests(0).is_err());
+        assert!(RateLimit::per_minute().with_tokens(0).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_budget_exceeded() {
+        let prices = PriceTable::new().with_price("fake", "model", ModelPrice::new(1.0, 0.0));
+        let budget = Budget::new(1.0, prices);
+        let node = Limited::new(FakeModel).with_budget(budget.clone());
+
+        let ledger = UsageLedger::new();
+        ledger.scope(node.process(1_000_000)).await.unwrap();
+        assert_eq!(ledger.calls(), 1);
+        assert_eq!(budget.spent(), 1.0);
+        assert!(matches!(
+            node.process(1).await,
+            Err(AnchorChainError::BudgetExceeded { .. })
+        ));
+    }
+
+    #[derive(Debug)]
+    struct SlowModel;
+
+    #[async_trait]
+    impl Node for SlowModel {
+        type Input = u64;
+        type Output = u64;
+
+        async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            FakeModel.process(input).await
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_budget_reservations_limit_concurrent_calls() {
+        let prices = PriceTable::new().with_price("fake", "model", ModelPrice::new(1.0, 0.0));
+        let budget = Budget::new(1.0, prices).with_reservation(0.6);
+        let node = Limited::new(SlowModel).with_budget(budget.clone());
+
+        let (first, second) = tokio::join!(node.process(500_000), node.process(500_000));
+        assert!(first.is_ok());
+        assert!(matches!(
+            second,
+            Err(AnchorChainError::BudgetExceeded { .. })
+        ));
+        assert_eq!(budget.spent(), 0.5);
+        assert!(budget.check().is_err());
+    }
+}
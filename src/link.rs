@@ -5,13 +5,12 @@
 
 use async_trait::async_trait;
 use std::fmt::Debug;
-use std::hash::Hash;
-use std::sync::Arc;
-use tokio::sync::Mutex;
 
+use crate::chain_state::ChainState;
+use crate::context::RunContext;
+use crate::describe::NodeDescription;
 use crate::error::AnchorChainError;
 use crate::node::{Node, Stateful};
-use crate::state_manager::StateManager;
 
 /// A link in a processing chain that connects one `Node` to another.
 ///
@@ -64,62 +63,161 @@ where
     /// node is passed to the next node or link in the chain for further processing.
     async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
         let output = self.node.process(input).await?;
+        RunContext::check_current()?;
         self.next.process(output).await
     }
+
+    /// Describes the nodes of the link as a sequence.
+    fn describe(&self) -> NodeDescription {
+        NodeDescription::of::<Self>()
+            .with_name("Link")
+            .sequence([self.node.describe(), self.next.describe()])
+    }
 }
 
 /// A stateful link in a processing chain that connects one `Node` to another.
 ///
 /// `StatefulLink` serves as a container for chaining two `Node` instances together,
 /// where the output of the first node is fed as the input to the next. The chain's
-/// `StateManager` is passed to each stateful node before processing.
+/// `ChainState` is passed to the next node when processing.
 #[derive(Debug)]
-pub struct StatefulLink<C, N, K, V>
+pub struct StatefulLink<C, N>
 where
     C: Debug,
     N: Debug,
-    K: Debug + Send,
-    V: Debug + Send,
 {
     pub node: C,
-    pub next: Arc<Mutex<N>>,
-    pub state: StateManager<K, V>,
+    pub next: N,
+    pub state: ChainState,
 }
 
-impl<C, N, K, V> StatefulLink<C, N, K, V>
+impl<C, N> StatefulLink<C, N>
 where
     C: Debug,
     N: Debug,
-    K: Debug + Send,
-    V: Debug + Send,
 {
-    pub fn new(node: C, next: N, memory: StateManager<K, V>) -> Self {
+    pub fn new(node: C, next: N, memory: ChainState) -> Self {
         Self {
             node,
-            next: Arc::new(Mutex::new(next)),
+            next,
             state: memory,
         }
     }
 }
 
 #[async_trait]
-impl<C, N, K, V> Node for StatefulLink<C, N, K, V>
+impl<C, N> Node for StatefulLink<C, N>
 where
     C: Node + Send + Sync + Debug,
     C::Output: Send + 'static,
     C::Input: Send,
-    N: Node<Input = C::Output> + Stateful<K, V> + Send + Sync + Debug,
+    N: Node<Input = C::Output> + Stateful + Send + Sync + Debug,
     N::Output: Send,
-    K: Eq + Hash + Clone + Send + Sync + Debug,
-    V: Clone + Send + Sync + Debug,
 {
     type Input = C::Input;
     type Output = <N as Node>::Output;
 
     async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
         let output = self.node.process(input).await?;
-        let mut next_node = self.next.lock().await;
-        next_node.set_state(self.state.clone()).await;
-        next_node.process(output).await
+        RunContext::check_current()?;
+        self.next.process_with_state(output, &self.state).await
+    }
+
+    /// Describes the nodes of the link as a sequence.
+    fn describe(&self) -> NodeDescription {
+        NodeDescription::of::<Self>()
+            .with_name("StatefulLink")
+            .sequence([self.node.describe(), self.next.describe()])
+    }
+}
+
+/// A stateful node processing its input with the chain's `ChainState`.
+///
+/// `WithState` is used for a stateful node added as the first node of a chain.
+#[derive(Debug)]
+pub struct WithState<N: Debug> {
+    pub node: N,
+    pub state: ChainState,
+}
+
+impl<N: Debug> WithState<N> {
+    pub fn new(node: N, state: ChainState) -> Self {
+        Self { node, state }
+    }
+}
+
+#[async_trait]
+impl<N> Node for WithState<N>
+where
+    N: Stateful + Send + Sync + Debug,
+    N::Input: Send,
+    N::Output: Send,
+{
+    type Input = N::Input;
+    type Output = N::Output;
+
+    async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+        self.node.process_with_state(input, &self.state).await
+    }
+
+    fn describe(&self) -> NodeDescription {
+        self.node.describe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::chain::ChainBuilder;
+    use crate::chain_state::StateKey;
+    use crate::node::NoOpNode;
+
+    const CALLS: StateKey<u32> = StateKey::new("calls");
+
+    #[derive(Debug)]
+    struct SlowCounter;
+
+    #[async_trait]
+    impl Node for SlowCounter {
+        type Input = u32;
+        type Output = u32;
+
+        async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+            self.process_with_state(input, &ChainState::new()).await
+        }
+    }
+
+    #[async_trait]
+    impl Stateful for SlowCounter {
+        async fn process_with_state(
+            &self,
+            input: Self::Input,
+            state: &ChainState,
+        ) -> Result<Self::Output, AnchorChainError> {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            let calls = state.get(&CALLS).await.unwrap_or_default() + 1;
+            state.insert(&CALLS, calls).await?;
+            Ok(input + calls)
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stateful_links_process_concurrently() {
+        let builder = ChainBuilder::new()
+            .link(NoOpNode::new())
+            .link_with_state(SlowCounter);
+        let state = builder.state();
+        let chain = builder.build();
+
+        let start = tokio::time::Instant::now();
+        let outputs = futures::future::join_all(
+            (0..4).map(|session| chain.process_with_session(session.to_string(), 10)),
+        )
+        .await;
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+        assert!(outputs.into_iter().all(|output| output.unwrap() == 11));
+        assert_eq!(state.sessions().await.len(), 4);
     }
 }
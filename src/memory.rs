@@ -0,0 +1,396 @@
+//! Strategies for selecting the conversation history sent with each request.
+//!
+//! Stateful chat models keep every message of a conversation, which eventually
+//! overflows the model's context window. A `MemoryStrategy` selects the messages
+//! sent with each request from the full history: `FullHistory` sends everything,
+//! `LastTurns` keeps a fixed number of recent turns, `TokenWindow` keeps as many
+//! recent turns as fit in a token budget and `SummarizingMemory` replaces older
+//! turns with a rolling summary written by a model.
+//!
+//! History is split into turns, each starting with a user message and including
+//! the replies and tool calls that follow it, so tool requests are never separated
+//! from their results.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+
+use crate::error::AnchorChainError;
+use crate::node::Node;
+use crate::state_manager::current_session;
+use crate::tokenizer::Tokenizer;
+
+/// The author of a chat message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChatRole {
+    User,
+    Assistant,
+}
+
+/// A message in a conversation that memory strategies can inspect.
+pub trait ChatMessage: Clone + Send + Sync {
+    /// Returns the author of the message.
+    fn role(&self) -> ChatRole;
+
+    /// Returns the text content of the message.
+    fn text(&self) -> String;
+
+    /// Returns true if the message starts a new turn.
+    ///
+    /// Defaults to user messages; implementations should exclude messages that only
+    /// return tool results.
+    fn starts_turn(&self) -> bool {
+        self.role() == ChatRole::User
+    }
+
+    /// Returns the message with the context added before its content.
+    fn with_context(self, context: &str) -> Self;
+}
+
+/// Selects the messages to send to a model from the conversation history.
+#[async_trait]
+pub trait MemoryStrategy<M>: fmt::Debug + Send + Sync {
+    /// Returns the messages to send given the full history, which ends with the
+    /// message being sent.
+    async fn select(&self, history: &[M]) -> Result<Vec<M>, AnchorChainError>;
+}
+
+/// Splits the history into turns.
+///
+/// Messages before the first turn are included in the first turn.
+fn turns<M: ChatMessage>(history: &[M]) -> Vec<&[M]> {
+    let mut starts = history
+        .iter()
+        .enumerate()
+        .filter(|(index, message)| *index > 0 && message.starts_turn())
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    starts.insert(0, 0);
+    starts.push(history.len());
+    starts
+        .windows(2)
+        .filter(|bounds| bounds[0] < bounds[1])
+        .map(|bounds| &history[bounds[0]..bounds[1]])
+        .collect()
+}
+
+/// Sends the full conversation history with every request.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FullHistory;
+
+#[async_trait]
+impl<M: ChatMessage> MemoryStrategy<M> for FullHistory {
+    async fn select(&self, history: &[M]) -> Result<Vec<M>, AnchorChainError> {
+        Ok(history.to_vec())
+    }
+}
+
+/// Sends only the most recent turns of the conversation.
+#[derive(Clone, Copy, Debug)]
+pub struct LastTurns {
+    turns: usize,
+}
+
+impl LastTurns {
+    /// Keeps the last `turns` turns, including the turn being sent.
+    pub fn new(turns: usize) -> Self {
+        LastTurns {
+            turns: turns.max(1),
+        }
+    }
+}
+
+#[async_trait]
+impl<M: ChatMessage> MemoryStrategy<M> for LastTurns {
+    async fn select(&self, history: &[M]) -> Result<Vec<M>, AnchorChainError> {
+        let turns = turns(history);
+        let skip = turns.len().saturating_sub(self.turns);
+        Ok(turns[skip..].concat())
+    }
+}
+
+/// Sends as many recent turns as fit in a token budget.
+///
+/// The turn being sent is always included even if it exceeds the budget.
+#[derive(Clone, Debug)]
+pub struct TokenWindow {
+    tokenizer: Arc<dyn Tokenizer>,
+    max_tokens: usize,
+}
+
+impl TokenWindow {
+    /// Keeps recent turns totalling at most `max_tokens` tokens of text.
+    pub fn new(tokenizer: impl Tokenizer + 'static, max_tokens: usize) -> Self {
+        TokenWindow {
+            tokenizer: Arc::new(tokenizer),
+            max_tokens,
+        }
+    }
+}
+
+#[async_trait]
+impl<M: ChatMessage> MemoryStrategy<M> for TokenWindow {
+    async fn select(&self, history: &[M]) -> Result<Vec<M>, AnchorChainError> {
+        let turns = turns(history);
+        let mut used = 0;
+        let mut keep = 0;
+        for turn in turns.iter().rev() {
+            let tokens = turn
+                .iter()
+                .map(|message| self.tokenizer.count_tokens(&message.text()))
+                .sum::<usize>();
+            if keep > 0 && used + tokens > self.max_tokens {
+                break;
+            }
+            used += tokens;
+            keep += 1;
+        }
+        Ok(turns[turns.len() - keep..].concat())
+    }
+}
+
+const DEFAULT_SUMMARY_INSTRUCTIONS: &str = "Summarize the following conversation between a user and an assistant. Keep the facts, decisions and open questions needed to continue the conversation.";
+
+/// The summary of the oldest turns of a conversation.
+#[derive(Clone, Debug, Default)]
+struct Summary {
+    /// The number of turns included in the summary.
+    turns: usize,
+    text: String,
+}
+
+/// Sends the most recent turns along with a summary of older turns.
+///
+/// When turns fall out of the window they are summarized together with the
+/// previous summary by the summarizer, which can be any node producing text such as
+/// a model node. The summary is added to the start of the first message sent.
+/// The summary is rolling and assumes the history is only appended to; if the
+/// history becomes shorter than the summarized turns the summary is rebuilt.
+/// A separate summary is kept for each session, and the summarizer is called
+/// without blocking the selection of other sessions.
+pub struct SummarizingMemory<N> {
+    summarizer: N,
+    recent_turns: usize,
+    instructions: String,
+    summaries: Mutex<HashMap<String, Summary>>,
+}
+
+impl<N> SummarizingMemory<N> {
+    /// Keeps the last `recent_turns` turns verbatim and summarizes older turns
+    /// using the summarizer.
+    pub fn new(summarizer: N, recent_turns: usize) -> Self {
+        SummarizingMemory {
+            summarizer,
+            recent_turns: recent_turns.max(1),
+            instructions: DEFAULT_SUMMARY_INSTRUCTIONS.to_string(),
+            summaries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets the instructions given to the summarizer before the conversation.
+    pub fn with_instructions(mut self, instructions: impl Into<String>) -> Self {
+        self.instructions = instructions.into();
+        self
+    }
+
+    /// Returns the summary of older turns in the current session.
+    pub async fn summary(&self) -> String {
+        self.summaries
+            .lock()
+            .await
+            .get(&current_session())
+            .map(|summary| summary.text.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Formats the messages as a transcript for the summarizer.
+fn transcript<M: ChatMessage>(messages: &[M]) -> String {
+    messages
+        .iter()
+        .map(|message| {
+            let role = match message.role() {
+                ChatRole::User => "User",
+                ChatRole::Assistant => "Assistant",
+            };
+            format!("{role}: {}", message.text())
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[async_trait]
+impl<N, M> MemoryStrategy<M> for SummarizingMemory<N>
+where
+    N: Node<Input = String, Output = String> + Send + Sync,
+    M: ChatMessage + 'static,
+{
+    async fn select(&self, history: &[M]) -> Result<Vec<M>, AnchorChainError> {
+        let turns = turns(history);
+        let split = turns.len().saturating_sub(self.recent_turns);
+
+        let session = current_session();
+        let stored = self
+            .summaries
+            .lock()
+            .await
+            .get(&session)
+            .cloned()
+            .unwrap_or_default();
+        let mut summary = stored.clone();
+        if summary.turns > split {
+            summary = Summary::default();
+        }
+        if summary.turns < split {
+            let mut prompt = self.instructions.clone();
+            if !summary.text.is_empty() {
+                prompt.push_str(&format!(
+                    "\n\nSummary of the conversation so far:\n{}",
+                    summary.text
+                ));
+            }
+            prompt.push_str(&format!(
+                "\n\nConversation:\n{}",
+                transcript(&turns[summary.turns..split].concat())
+            ));
+            summary.text = self.summarizer.process(prompt).await?;
+            summary.turns = split;
+        }
+        if summary.turns != stored.turns {
+            // Another selection may have stored a newer summary while this one
+            // was being written.
+            let mut summaries = self.summaries.lock().await;
+            let current = summaries.entry(session).or_default();
+            if current.turns == stored.turns || current.turns < summary.turns {
+                *current = summary.clone();
+            }
+        }
+
+        let mut messages = turns[split..].concat();
+        if !summary.text.is_empty() {
+            if let Some(first) = messages.first_mut() {
+                let context = format!("Summary of the earlier conversation:\n{}", summary.text);
+                *first = first.clone().with_context(&context);
+            }
+        }
+        Ok(messages)
+    }
+}
+
+impl<N: fmt::Debug> fmt::Debug for SummarizingMemory<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SummarizingMemory")
+            .field("summarizer", &self.summarizer)
+            .field("recent_turns", &self.recent_turns)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state_manager::with_session;
+    use crate::testing::{MockModel, MockResponse};
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct TestMessage(ChatRole, String);
+
+    impl ChatMessage for TestMessage {
+        fn role(&self) -> ChatRole {
+            self.0
+        }
+
+        fn text(&self) -> String {
+            self.1.clone()
+        }
+
+        fn with_context(self, context: &str) -> Self {
+            TestMessage(self.0, format!("{context}\n\n{}", self.1))
+        }
+    }
+
+    fn conversation(turns: usize) -> Vec<TestMessage> {
+        (1..=turns)
+            .flat_map(|turn| {
+                [
+                    TestMessage(ChatRole::User, format!("question {turn}")),
+                    TestMessage(ChatRole::Assistant, format!("answer {turn}")),
+                ]
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_last_turns_and_summarizing_memory() {
+        let history = conversation(3);
+        let selected = LastTurns::new(2).select(&history).await.unwrap();
+        assert_eq!(selected, history[2..]);
+
+        let summarizer = MockModel::new("summarizer")
+            .then(MockResponse::text("first summary"))
+            .then(MockResponse::text("second summary"));
+        let memory = SummarizingMemory::new(summarizer.clone(), 1);
+
+        let selected = memory.select(&history[..5]).await.unwrap();
+        assert_eq!(selected.len(), 1);
+        assert_eq!(
+            selected[0].text(),
+            "Summary of the earlier conversation:\nfirst summary\n\nquestion 3"
+        );
+        // Older turns are only summarized once.
+        memory.select(&history).await.unwrap();
+        assert_eq!(summarizer.prompts().len(), 1);
+
+        let mut history = history;
+        history.push(TestMessage(ChatRole::User, "question 4".to_string()));
+        memory.select(&history).await.unwrap();
+        let prompts = summarizer.prompts();
+        assert!(prompts[1].contains("first summary"));
+        assert!(prompts[1].contains("User: question 3\nAssistant: answer 3"));
+        assert!(!prompts[1].contains("question 2"));
+        assert_eq!(memory.summary().await, "second summary");
+    }
+
+    /// Summarizer waiting until released.
+    #[derive(Clone, Debug, Default)]
+    struct BlockingSummarizer {
+        started: Arc<tokio::sync::Notify>,
+        release: Arc<tokio::sync::Notify>,
+    }
+
+    #[async_trait]
+    impl Node for BlockingSummarizer {
+        type Input = String;
+        type Output = String;
+
+        async fn process(&self, _input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+            self.started.notify_one();
+            self.release.notified().await;
+            Ok("summary".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summarizing_does_not_block_other_sessions() {
+        let summarizer = BlockingSummarizer::default();
+        let memory = Arc::new(SummarizingMemory::new(summarizer.clone(), 1));
+        let history = conversation(3);
+
+        let alice = tokio::spawn({
+            let memory = memory.clone();
+            let history = history.clone();
+            with_session("alice", async move { memory.select(&history).await })
+        });
+        summarizer.started.notified().await;
+        // Short enough not to need a summary.
+        let bob = with_session("bob", memory.select(&history[..2])).await;
+        assert_eq!(bob.unwrap(), history[..2]);
+
+        summarizer.release.notify_one();
+        assert_eq!(alice.await.unwrap().unwrap().len(), 2);
+        assert_eq!(with_session("alice", memory.summary()).await, "summary");
+    }
+}
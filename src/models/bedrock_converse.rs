@@ -1,49 +1,406 @@
-//! Module for interfacing with Claude 3 via AWS Bedrock.
+//! Module for interfacing with models hosted on AWS Bedrock via the Converse API.
 //!
-//! Provides the functionality to construct and send requests to Claude 3 models hosted
-//! on AWS Bedrock, facilitating integration of LLM processing within
-//! processing chains. This module is designed to handle text and image inputs, offering a
-//! flexible interface for various types of content.
+//! Provides the functionality to construct and send requests to Claude, Llama, Mistral,
+//! Cohere Command and Titan Text models hosted on AWS Bedrock, as well as custom model
+//! IDs, inference profiles and provisioned throughput, facilitating integration of LLM
+//! processing within processing chains. Inputs can be plain strings or a
+//! `MultimodalInput` containing text, images and documents.
 
 use std::collections::HashMap;
 use std::fmt;
 use std::marker::PhantomData;
 use std::string::ToString;
+use std::sync::Arc;
+use std::time::Instant;
 
 use async_trait::async_trait;
 use aws_sdk_bedrockruntime::operation::converse::builders::ConverseFluentBuilder;
 use aws_sdk_bedrockruntime::operation::converse::ConverseOutput;
 use aws_sdk_bedrockruntime::types::{
-    ContentBlock, ConversationRole, Message, SystemContentBlock, ToolConfiguration,
-    ToolResultBlock, ToolResultContentBlock, ToolResultStatus,
+    ContentBlock, ConversationRole, DocumentBlock, DocumentFormat, DocumentSource, ImageBlock,
+    ImageFormat, ImageSource, Message, SystemContentBlock, ToolConfiguration, ToolResultBlock,
+    ToolResultContentBlock, ToolResultStatus, ToolUseBlock,
 };
 use aws_sdk_bedrockruntime::Client;
-use aws_smithy_types::Document;
+use aws_smithy_types::{Blob, Document, Number};
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use tokio::sync::RwLock;
 #[cfg(feature = "tracing")]
 use tracing::instrument;
 
 use crate::agents::tool_registry::{convert_document_to_value, convert_value_to_document};
+use crate::chain_state::ChainState;
+use crate::context::RunContext;
 use crate::error::AnchorChainError;
+use crate::memory::{ChatMessage, ChatRole, MemoryStrategy};
+use crate::models::multimodal::{self, ContentPart, MultimodalInput};
 use crate::node::{Node, Stateful};
+use crate::usage::{record_usage, TokenUsage};
 use crate::{StateManager, Stateless, ToolRegistry};
 
 static HISTORY_KEY: &str = "BedrockConverseHistory";
 
-#[derive(Clone, Copy, Debug)]
+/// Features a Bedrock model supports through the Converse API.
+///
+/// Bedrock rejects requests that use features a model doesn't support, so
+/// `BedrockConverse` checks these flags before a request is sent and returns
+/// `AnchorChainError::UnsupportedCapability` instead of making the call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ModelCapabilities {
+    /// The model accepts tool definitions and can return tool use requests.
+    pub tools: bool,
+    /// The model accepts image content blocks.
+    pub images: bool,
+    /// The model accepts document content blocks.
+    pub documents: bool,
+    /// The model accepts a system prompt.
+    pub system_prompt: bool,
+}
+
+impl ModelCapabilities {
+    /// Capabilities for models that support tools, images, documents and system prompts.
+    pub const ALL: Self = Self {
+        tools: true,
+        images: true,
+        documents: true,
+        system_prompt: true,
+    };
+
+    /// Capabilities for text models that support tools, documents and system prompts.
+    const TEXT_WITH_TOOLS: Self = Self {
+        tools: true,
+        images: false,
+        documents: true,
+        system_prompt: true,
+    };
+
+    /// Capabilities for text models that support documents and system prompts.
+    const TEXT_WITH_SYSTEM: Self = Self {
+        tools: false,
+        images: false,
+        documents: true,
+        system_prompt: true,
+    };
+
+    /// Capabilities for text only models without tool, document or system prompt support.
+    const TEXT_ONLY: Self = Self {
+        tools: false,
+        images: false,
+        documents: false,
+        system_prompt: false,
+    };
+}
+
+impl Default for ModelCapabilities {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+/// Models that can be used with the Bedrock Converse API.
+///
+/// The `Custom` variant accepts any model ID, inference profile ID or ARN,
+/// or provisioned throughput ARN that Bedrock accepts as a `modelId`.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum BedrockModel {
+    /// Anthropic Claude 3 Sonnet
     Claude3,
+    /// Anthropic Claude 3.5 Sonnet
     Claude3_5,
+    /// Anthropic Claude 3 Haiku
+    Claude3Haiku,
+    /// Anthropic Claude 3 Opus
+    Claude3Opus,
+    /// Meta Llama 3 8B Instruct
+    Llama3_8BInstruct,
+    /// Meta Llama 3 70B Instruct
+    Llama3_70BInstruct,
+    /// Meta Llama 3.1 8B Instruct
+    Llama3_1_8BInstruct,
+    /// Meta Llama 3.1 70B Instruct
+    Llama3_1_70BInstruct,
+    /// Meta Llama 3.1 405B Instruct
+    Llama3_1_405BInstruct,
+    /// Mistral 7B Instruct
+    Mistral7BInstruct,
+    /// Mixtral 8x7B Instruct
+    Mixtral8x7BInstruct,
+    /// Mistral Small
+    MistralSmall,
+    /// Mistral Large
+    MistralLarge,
+    /// Cohere Command
+    CohereCommand,
+    /// Cohere Command Light
+    CohereCommandLight,
+    /// Cohere Command R
+    CohereCommandR,
+    /// Cohere Command R+
+    CohereCommandRPlus,
+    /// Amazon Titan Text Lite
+    TitanTextLite,
+    /// Amazon Titan Text Express
+    TitanTextExpress,
+    /// Amazon Titan Text Premier
+    TitanTextPremier,
+    /// Any other model ID, inference profile or provisioned throughput ARN.
+    Custom {
+        /// The value passed as the `modelId` of the request.
+        id: String,
+        /// The features supported by the model.
+        capabilities: ModelCapabilities,
+    },
 }
 
 impl BedrockModel {
-    fn as_str(&self) -> &'static str {
+    /// Every model with a dedicated variant.
+    const KNOWN: [BedrockModel; 20] = [
+        Self::Claude3,
+        Self::Claude3_5,
+        Self::Claude3Haiku,
+        Self::Claude3Opus,
+        Self::Llama3_8BInstruct,
+        Self::Llama3_70BInstruct,
+        Self::Llama3_1_8BInstruct,
+        Self::Llama3_1_70BInstruct,
+        Self::Llama3_1_405BInstruct,
+        Self::Mistral7BInstruct,
+        Self::Mixtral8x7BInstruct,
+        Self::MistralSmall,
+        Self::MistralLarge,
+        Self::CohereCommand,
+        Self::CohereCommandLight,
+        Self::CohereCommandR,
+        Self::CohereCommandRPlus,
+        Self::TitanTextLite,
+        Self::TitanTextExpress,
+        Self::TitanTextPremier,
+    ];
+
+    /// Creates a model from an arbitrary model ID or ARN.
+    ///
+    /// IDs matching a known model, including cross-region inference profile IDs
+    /// such as `us.anthropic.claude-3-haiku-20240307-v1:0`, return the known
+    /// variant's capabilities. Anything else is assumed to support all features;
+    /// use `BedrockModel::custom_with_capabilities` to restrict them.
+    pub fn custom(id: impl Into<String>) -> Self {
+        let id = id.into();
+        let capabilities = Self::from_id(&id)
+            .map(|model| model.capabilities())
+            .unwrap_or_default();
+        Self::Custom { id, capabilities }
+    }
+
+    /// Creates a model from an arbitrary model ID or ARN with explicit capabilities.
+    pub fn custom_with_capabilities(
+        id: impl Into<String>,
+        capabilities: ModelCapabilities,
+    ) -> Self {
+        Self::Custom {
+            id: id.into(),
+            capabilities,
+        }
+    }
+
+    /// Creates a model that invokes an inference profile by ID or ARN.
+    pub fn inference_profile(profile: impl Into<String>) -> Self {
+        Self::custom(profile)
+    }
+
+    /// Creates a model that invokes a provisioned throughput ARN.
+    ///
+    /// Provisioned throughput ARNs don't identify the underlying model so the
+    /// capabilities of that model must be provided.
+    pub fn provisioned_throughput(arn: impl Into<String>, capabilities: ModelCapabilities) -> Self {
+        Self::custom_with_capabilities(arn, capabilities)
+    }
+
+    /// Looks up a known model by its model ID.
+    ///
+    /// Cross-region inference profile prefixes (e.g. `us.` or `eu.`) and
+    /// foundation model ARNs are accepted.
+    pub fn from_id(id: &str) -> Option<Self> {
+        let id = id.rsplit('/').next().unwrap_or(id);
+        Self::KNOWN.into_iter().find(|model| {
+            let known = model.as_str();
+            id == known
+                || id
+                    .split_once('.')
+                    .is_some_and(|(_, unprefixed)| unprefixed == known)
+        })
+    }
+
+    /// The model ID sent to Bedrock.
+    pub fn as_str(&self) -> &str {
         match self {
             Self::Claude3 => "anthropic.claude-3-sonnet-20240229-v1:0",
             Self::Claude3_5 => "anthropic.claude-3-5-sonnet-20240620-v1:0",
+            Self::Claude3Haiku => "anthropic.claude-3-haiku-20240307-v1:0",
+            Self::Claude3Opus => "anthropic.claude-3-opus-20240229-v1:0",
+            Self::Llama3_8BInstruct => "meta.llama3-8b-instruct-v1:0",
+            Self::Llama3_70BInstruct => "meta.llama3-70b-instruct-v1:0",
+            Self::Llama3_1_8BInstruct => "meta.llama3-1-8b-instruct-v1:0",
+            Self::Llama3_1_70BInstruct => "meta.llama3-1-70b-instruct-v1:0",
+            Self::Llama3_1_405BInstruct => "meta.llama3-1-405b-instruct-v1:0",
+            Self::Mistral7BInstruct => "mistral.mistral-7b-instruct-v0:2",
+            Self::Mixtral8x7BInstruct => "mistral.mixtral-8x7b-instruct-v0:1",
+            Self::MistralSmall => "mistral.mistral-small-2402-v1:0",
+            Self::MistralLarge => "mistral.mistral-large-2402-v1:0",
+            Self::CohereCommand => "cohere.command-text-v14",
+            Self::CohereCommandLight => "cohere.command-light-text-v14",
+            Self::CohereCommandR => "cohere.command-r-v1:0",
+            Self::CohereCommandRPlus => "cohere.command-r-plus-v1:0",
+            Self::TitanTextLite => "amazon.titan-text-lite-v1",
+            Self::TitanTextExpress => "amazon.titan-text-express-v1",
+            Self::TitanTextPremier => "amazon.titan-text-premier-v1:0",
+            Self::Custom { id, .. } => id,
         }
     }
+
+    /// The features the model supports through the Converse API.
+    pub fn capabilities(&self) -> ModelCapabilities {
+        match self {
+            Self::Claude3 | Self::Claude3_5 | Self::Claude3Haiku | Self::Claude3Opus => {
+                ModelCapabilities::ALL
+            }
+            Self::Llama3_1_8BInstruct
+            | Self::Llama3_1_70BInstruct
+            | Self::Llama3_1_405BInstruct
+            | Self::MistralSmall
+            | Self::MistralLarge
+            | Self::CohereCommandR
+            | Self::CohereCommandRPlus => ModelCapabilities::TEXT_WITH_TOOLS,
+            Self::Llama3_8BInstruct | Self::Llama3_70BInstruct | Self::TitanTextPremier => {
+                ModelCapabilities::TEXT_WITH_SYSTEM
+            }
+            Self::Mistral7BInstruct
+            | Self::Mixtral8x7BInstruct
+            | Self::CohereCommand
+            | Self::CohereCommandLight
+            | Self::TitanTextLite
+            | Self::TitanTextExpress => ModelCapabilities::TEXT_ONLY,
+            Self::Custom { capabilities, .. } => *capabilities,
+        }
+    }
+
+    /// Returns an error if the model doesn't support tool use.
+    fn check_tools(&self) -> Result<(), AnchorChainError> {
+        if self.capabilities().tools {
+            Ok(())
+        } else {
+            Err(AnchorChainError::UnsupportedCapability(format!(
+                "{} does not support tool use",
+                self.as_str()
+            )))
+        }
+    }
+
+    /// Returns an error if the model doesn't support system prompts.
+    fn check_system_prompt(&self) -> Result<(), AnchorChainError> {
+        if self.capabilities().system_prompt {
+            Ok(())
+        } else {
+            Err(AnchorChainError::UnsupportedCapability(format!(
+                "{} does not support system prompts",
+                self.as_str()
+            )))
+        }
+    }
+
+    /// Converts a `MultimodalInput` into Converse content blocks.
+    ///
+    /// Returns an error if the input contains images or documents and the model
+    /// doesn't support them.
+    fn content_blocks(
+        &self,
+        input: MultimodalInput,
+    ) -> Result<Vec<ContentBlock>, AnchorChainError> {
+        let capabilities = self.capabilities();
+        input
+            .into_parts()
+            .into_iter()
+            .map(|part| match part {
+                ContentPart::Text(text) => Ok(ContentBlock::Text(text)),
+                ContentPart::Image(image) if capabilities.images => Ok(ContentBlock::Image(
+                    ImageBlock::builder()
+                        .format(image_format(image.format()))
+                        .source(ImageSource::Bytes(Blob::new(image.bytes())))
+                        .build()
+                        .expect("Error building image block"),
+                )),
+                ContentPart::Document(document) if capabilities.documents => {
+                    Ok(ContentBlock::Document(
+                        DocumentBlock::builder()
+                            .format(document_format(document.format()))
+                            .name(document_name(document.name()))
+                            .source(DocumentSource::Bytes(Blob::new(document.bytes())))
+                            .build()
+                            .expect("Error building document block"),
+                    ))
+                }
+                ContentPart::Image(_) => Err(AnchorChainError::UnsupportedCapability(format!(
+                    "{} does not support image inputs",
+                    self.as_str()
+                ))),
+                ContentPart::Document(_) => Err(AnchorChainError::UnsupportedCapability(format!(
+                    "{} does not support document inputs",
+                    self.as_str()
+                ))),
+            })
+            .collect()
+    }
+}
+
+/// Converts an image format to the Bedrock image format.
+fn image_format(format: multimodal::ImageFormat) -> ImageFormat {
+    match format {
+        multimodal::ImageFormat::Png => ImageFormat::Png,
+        multimodal::ImageFormat::Jpeg => ImageFormat::Jpeg,
+        multimodal::ImageFormat::Gif => ImageFormat::Gif,
+        multimodal::ImageFormat::Webp => ImageFormat::Webp,
+    }
+}
+
+/// Converts a document format to the Bedrock document format.
+fn document_format(format: multimodal::DocumentFormat) -> DocumentFormat {
+    match format {
+        multimodal::DocumentFormat::Pdf => DocumentFormat::Pdf,
+        multimodal::DocumentFormat::Csv => DocumentFormat::Csv,
+        multimodal::DocumentFormat::Doc => DocumentFormat::Doc,
+        multimodal::DocumentFormat::Docx => DocumentFormat::Docx,
+        multimodal::DocumentFormat::Xls => DocumentFormat::Xls,
+        multimodal::DocumentFormat::Xlsx => DocumentFormat::Xlsx,
+        multimodal::DocumentFormat::Html => DocumentFormat::Html,
+        multimodal::DocumentFormat::Txt => DocumentFormat::Txt,
+        multimodal::DocumentFormat::Md => DocumentFormat::Md,
+    }
+}
+
+/// Converts a document name to one accepted by Bedrock.
+///
+/// Bedrock only allows alphanumeric characters, single spaces, hyphens,
+/// parentheses and square brackets in document names.
+fn document_name(name: &str) -> String {
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    let sanitized = stem
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || " -()[]".contains(c) {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>();
+    let name = sanitized.split_whitespace().collect::<Vec<_>>().join(" ");
+    if name.is_empty() {
+        "document".to_string()
+    } else {
+        name
+    }
 }
 
 impl From<BedrockModel> for String {
@@ -58,130 +415,557 @@ impl From<&BedrockModel> for String {
     }
 }
 
-/// A processor for integrating Claude 3 LLM processing within a chain.
+/// A message of a Bedrock conversation in a form that can be serialized.
+///
+/// Conversation histories are kept as `ConverseMessage`s so they can be persisted
+/// with `StateManager::persist_to`, and are converted to and from `Message` when
+/// requests are built.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ConverseMessage {
+    /// The Converse role of the author, `user` or `assistant`.
+    pub role: String,
+    /// The content of the message.
+    pub content: Vec<ConverseContent>,
+}
+
+/// A block of content in a `ConverseMessage`.
+///
+/// Formats are stored by their Bedrock names and the bytes of images and documents
+/// as base64.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ConverseContent {
+    Text {
+        text: String,
+    },
+    /// JSON returned by a tool, only used in tool results.
+    Json {
+        value: Value,
+    },
+    Image {
+        format: String,
+        data: String,
+    },
+    Document {
+        format: String,
+        name: String,
+        data: String,
+    },
+    ToolUse {
+        tool_use_id: String,
+        name: String,
+        input: Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: Vec<ConverseContent>,
+        status: Option<String>,
+    },
+}
+
+/// Returns an error for history that can't be converted.
+fn history_error(error: impl fmt::Display) -> AnchorChainError {
+    AnchorChainError::ModelError(format!("invalid conversation history: {error}"))
+}
+
+/// Converts a Smithy document to JSON exactly, unlike the tool schema conversion.
+fn document_to_value(document: &Document) -> Value {
+    match document {
+        Document::Null => Value::Null,
+        Document::Bool(b) => Value::Bool(*b),
+        Document::Number(Number::PosInt(u)) => Value::from(*u),
+        Document::Number(Number::NegInt(i)) => Value::from(*i),
+        Document::Number(Number::Float(f)) => Value::from(*f),
+        Document::String(s) => Value::String(s.clone()),
+        Document::Array(array) => Value::Array(array.iter().map(document_to_value).collect()),
+        Document::Object(object) => Value::Object(
+            object
+                .iter()
+                .map(|(key, value)| (key.clone(), document_to_value(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// Converts JSON to a Smithy document exactly, unlike the tool schema conversion.
+fn value_to_document(value: &Value) -> Document {
+    match value {
+        Value::Null => Document::Null,
+        Value::Bool(b) => Document::Bool(*b),
+        Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(u), _) => Document::Number(Number::PosInt(u)),
+            (_, Some(i)) => Document::Number(Number::NegInt(i)),
+            _ => Document::Number(Number::Float(n.as_f64().unwrap_or_default())),
+        },
+        Value::String(s) => Document::String(s.clone()),
+        Value::Array(array) => Document::Array(array.iter().map(value_to_document).collect()),
+        Value::Object(object) => Document::Object(
+            object
+                .iter()
+                .map(|(key, value)| (key.clone(), value_to_document(value)))
+                .collect(),
+        ),
+    }
+}
+
+impl ConverseContent {
+    fn from_image(image: &ImageBlock) -> Result<Self, AnchorChainError> {
+        match &image.source {
+            Some(ImageSource::Bytes(bytes)) => Ok(ConverseContent::Image {
+                format: image.format.as_str().to_string(),
+                data: BASE64_STANDARD.encode(bytes.as_ref()),
+            }),
+            _ => Err(history_error("images must have a byte source")),
+        }
+    }
+
+    fn from_document(document: &DocumentBlock) -> Result<Self, AnchorChainError> {
+        match &document.source {
+            Some(DocumentSource::Bytes(bytes)) => Ok(ConverseContent::Document {
+                format: document.format.as_str().to_string(),
+                name: document.name.clone(),
+                data: BASE64_STANDARD.encode(bytes.as_ref()),
+            }),
+            _ => Err(history_error("documents must have a byte source")),
+        }
+    }
+
+    fn to_image(format: &str, data: &str) -> Result<ImageBlock, AnchorChainError> {
+        ImageBlock::builder()
+            .format(ImageFormat::from(format))
+            .source(ImageSource::Bytes(Blob::new(
+                BASE64_STANDARD.decode(data).map_err(history_error)?,
+            )))
+            .build()
+            .map_err(history_error)
+    }
+
+    fn to_document(
+        format: &str,
+        name: &str,
+        data: &str,
+    ) -> Result<DocumentBlock, AnchorChainError> {
+        DocumentBlock::builder()
+            .format(DocumentFormat::from(format))
+            .name(name)
+            .source(DocumentSource::Bytes(Blob::new(
+                BASE64_STANDARD.decode(data).map_err(history_error)?,
+            )))
+            .build()
+            .map_err(history_error)
+    }
+
+    fn from_tool_result(block: &ToolResultContentBlock) -> Result<Self, AnchorChainError> {
+        match block {
+            ToolResultContentBlock::Text(text) => Ok(ConverseContent::Text { text: text.clone() }),
+            ToolResultContentBlock::Json(json) => Ok(ConverseContent::Json {
+                value: document_to_value(json),
+            }),
+            ToolResultContentBlock::Image(image) => Self::from_image(image),
+            ToolResultContentBlock::Document(document) => Self::from_document(document),
+            _ => Err(history_error("unsupported tool result content")),
+        }
+    }
+
+    fn to_tool_result(&self) -> Result<ToolResultContentBlock, AnchorChainError> {
+        match self {
+            ConverseContent::Text { text } => Ok(ToolResultContentBlock::Text(text.clone())),
+            ConverseContent::Json { value } => {
+                Ok(ToolResultContentBlock::Json(value_to_document(value)))
+            }
+            ConverseContent::Image { format, data } => {
+                Ok(ToolResultContentBlock::Image(Self::to_image(format, data)?))
+            }
+            ConverseContent::Document { format, name, data } => Ok(
+                ToolResultContentBlock::Document(Self::to_document(format, name, data)?),
+            ),
+            ConverseContent::ToolUse { .. } | ConverseContent::ToolResult { .. } => {
+                Err(history_error("tool results can't contain tool content"))
+            }
+        }
+    }
+}
+
+impl TryFrom<&ContentBlock> for ConverseContent {
+    type Error = AnchorChainError;
+
+    fn try_from(block: &ContentBlock) -> Result<Self, Self::Error> {
+        match block {
+            ContentBlock::Text(text) => Ok(ConverseContent::Text { text: text.clone() }),
+            ContentBlock::Image(image) => Self::from_image(image),
+            ContentBlock::Document(document) => Self::from_document(document),
+            ContentBlock::ToolUse(tool_use) => Ok(ConverseContent::ToolUse {
+                tool_use_id: tool_use.tool_use_id.clone(),
+                name: tool_use.name.clone(),
+                input: document_to_value(&tool_use.input),
+            }),
+            ContentBlock::ToolResult(result) => Ok(ConverseContent::ToolResult {
+                tool_use_id: result.tool_use_id.clone(),
+                content: result
+                    .content
+                    .iter()
+                    .map(Self::from_tool_result)
+                    .collect::<Result<_, _>>()?,
+                status: result
+                    .status
+                    .as_ref()
+                    .map(|status| status.as_str().to_string()),
+            }),
+            _ => Err(history_error("unsupported message content")),
+        }
+    }
+}
+
+impl TryFrom<&ConverseContent> for ContentBlock {
+    type Error = AnchorChainError;
+
+    fn try_from(content: &ConverseContent) -> Result<Self, Self::Error> {
+        match content {
+            ConverseContent::Text { text } => Ok(ContentBlock::Text(text.clone())),
+            ConverseContent::Json { .. } => Err(history_error(
+                "JSON content is only allowed in tool results",
+            )),
+            ConverseContent::Image { format, data } => Ok(ContentBlock::Image(
+                ConverseContent::to_image(format, data)?,
+            )),
+            ConverseContent::Document { format, name, data } => Ok(ContentBlock::Document(
+                ConverseContent::to_document(format, name, data)?,
+            )),
+            ConverseContent::ToolUse {
+                tool_use_id,
+                name,
+                input,
+            } => Ok(ContentBlock::ToolUse(
+                ToolUseBlock::builder()
+                    .tool_use_id(tool_use_id)
+                    .name(name)
+                    .input(value_to_document(input))
+                    .build()
+                    .map_err(history_error)?,
+            )),
+            ConverseContent::ToolResult {
+                tool_use_id,
+                content,
+                status,
+            } => Ok(ContentBlock::ToolResult(
+                ToolResultBlock::builder()
+                    .tool_use_id(tool_use_id)
+                    .set_content(Some(
+                        content
+                            .iter()
+                            .map(ConverseContent::to_tool_result)
+                            .collect::<Result<_, _>>()?,
+                    ))
+                    .set_status(status.as_deref().map(ToolResultStatus::from))
+                    .build()
+                    .map_err(history_error)?,
+            )),
+        }
+    }
+}
+
+impl TryFrom<&Message> for ConverseMessage {
+    type Error = AnchorChainError;
+
+    fn try_from(message: &Message) -> Result<Self, Self::Error> {
+        Ok(ConverseMessage {
+            role: message.role.as_str().to_string(),
+            content: message
+                .content
+                .iter()
+                .map(ConverseContent::try_from)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl TryFrom<&ConverseMessage> for Message {
+    type Error = AnchorChainError;
+
+    fn try_from(message: &ConverseMessage) -> Result<Self, Self::Error> {
+        Message::builder()
+            .role(ConversationRole::from(message.role.as_str()))
+            .set_content(Some(
+                message
+                    .content
+                    .iter()
+                    .map(ContentBlock::try_from)
+                    .collect::<Result<_, _>>()?,
+            ))
+            .build()
+            .map_err(history_error)
+    }
+}
+
+/// A processor for integrating Bedrock LLM processing within a chain.
 ///
-/// `BedrockConverse` allows for sending requests to Claude 3 models using Bedrock's
-/// Converse API.
+/// `BedrockConverse` allows for sending requests to any `BedrockModel` using Bedrock's
+/// Converse API. The input type defaults to `String`; use `with_input` to accept
+/// `MultimodalInput` or any other type convertible into it.
 #[derive(Clone)]
-pub struct BedrockConverse<'a, O: Clone> {
+pub struct BedrockConverse<'a, O: Clone, I = String> {
     model: BedrockModel,
     /// The system prompt or context to use for all requests.
-    system_prompt: String,
+    system_prompt: Option<String>,
     /// The AWS Bedrock client for sending requests.
     client: Client,
     tool_registry: Option<&'a RwLock<ToolRegistry<'a>>>,
-    history: StateManager<String, Vec<O>>,
+    /// The conversation history used when not processing with a chain's state.
+    history: StateManager<String, Vec<ConverseMessage>>,
+    /// Selects the history sent with each request, defaulting to the full history.
+    memory: Option<Arc<dyn MemoryStrategy<O>>>,
     _output: PhantomData<O>,
+    _input: PhantomData<I>,
 }
 
 impl<'a, O: Clone> BedrockConverse<'a, O> {
+    /// Constructs a new `BedrockConverse` processor for the specified model.
+    ///
+    /// A default system prompt is used if the model supports system prompts.
     pub async fn new(model: BedrockModel) -> Self {
-        Self::new_with_system_prompt(model, "You are a helpful assistant").await
+        let system_prompt = model
+            .capabilities()
+            .system_prompt
+            .then(|| "You are a helpful assistant".to_string());
+        Self::with_client(model, system_prompt).await
     }
 
-    /// Constructs a new `Claude3Bedrock` processor with the specified system prompt.
+    /// Constructs a new `BedrockConverse` processor with the specified system prompt.
     ///
     /// Initializes the AWS Bedrock client using the environment's AWS configuration.
+    /// Requests will fail with `AnchorChainError::UnsupportedCapability` if the
+    /// model doesn't support system prompts.
     pub async fn new_with_system_prompt(
         model: BedrockModel,
         system_prompt: impl Into<String>,
     ) -> Self {
+        Self::with_client(model, Some(system_prompt.into())).await
+    }
+
+    async fn with_client(model: BedrockModel, system_prompt: Option<String>) -> Self {
         let config = aws_config::load_from_env().await;
         let client = Client::new(&config);
         BedrockConverse {
             model,
             client,
             tool_registry: None,
-            system_prompt: system_prompt.into(),
+            system_prompt,
             history: StateManager::new(),
+            memory: None,
+            _output: PhantomData,
+            _input: PhantomData,
+        }
+    }
+
+    /// Changes the input type accepted by the processor.
+    ///
+    /// ```rust,ignore
+    /// let llm = BedrockConverse::<String>::new(BedrockModel::Claude3_5)
+    ///     .await
+    ///     .with_input::<MultimodalInput>();
+    /// ```
+    pub fn with_input<I: Into<MultimodalInput>>(self) -> BedrockConverse<'a, O, I> {
+        BedrockConverse {
+            model: self.model,
+            system_prompt: self.system_prompt,
+            client: self.client,
+            tool_registry: self.tool_registry,
+            history: self.history,
+            memory: self.memory,
             _output: PhantomData,
+            _input: PhantomData,
         }
     }
 }
 
+impl<'a, O: Clone, I> BedrockConverse<'a, O, I> {
+    /// Returns the model used for requests.
+    pub fn model(&self) -> &BedrockModel {
+        &self.model
+    }
+
+    /// Returns the conversation history used when not processing with a chain's
+    /// state, for example to persist it with `StateManager::persist_to`.
+    pub fn history(&self) -> &StateManager<String, Vec<ConverseMessage>> {
+        &self.history
+    }
+
+    /// Creates a Converse request for the model including the system prompt if set.
+    fn converse_request(&self) -> Result<ConverseFluentBuilder, AnchorChainError> {
+        let request = self.client.converse().model_id(&self.model);
+        match &self.system_prompt {
+            Some(system_prompt) => {
+                self.model.check_system_prompt()?;
+                Ok(request.system(SystemContentBlock::Text(system_prompt.clone())))
+            }
+            None => Ok(request),
+        }
+    }
+
+    /// Sends a Converse request, recording its token usage and latency.
+    async fn send(
+        &self,
+        request: ConverseFluentBuilder,
+    ) -> Result<ConverseOutput, AnchorChainError> {
+        let start = Instant::now();
+        let response = request.send().await?;
+        let usage = response.usage().map_or_else(TokenUsage::default, |usage| {
+            TokenUsage::new(
+                usage.input_tokens().max(0) as u64,
+                usage.output_tokens().max(0) as u64,
+            )
+        });
+        record_usage("bedrock", self.model.as_str(), usage, start.elapsed());
+        Ok(response)
+    }
+}
+
 #[async_trait]
-impl<'a> Node for BedrockConverse<'a, String> {
-    type Input = String;
+impl<'a, I> Node for BedrockConverse<'a, String, I>
+where
+    I: Into<MultimodalInput> + Send + Sync + fmt::Debug,
+{
+    type Input = I;
     type Output = String;
 
-    /// Processes the input through the Claude 3 model, returning the model's output.
+    /// Processes the input through the Bedrock model, returning the model's output.
     ///
-    /// Constructs a request to the Claude 3 model with the provided input, sends it via
+    /// Constructs a request to the Bedrock model with the provided input, sends it via
     /// AWS Bedrock, and extracts the text content from the response.
-    #[cfg_attr(feature = "tracing", instrument(fields(system_prompt = self.system_prompt.as_str())))]
+    #[cfg_attr(feature = "tracing", instrument(fields(model = self.model.as_str(), system_prompt = self.system_prompt.as_deref())))]
     async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
-        let request = self
-            .client
-            .converse()
-            .messages(
-                Message::builder()
-                    .content(ContentBlock::Text(input))
-                    .build()
-                    .unwrap(),
-            )
-            .model_id(self.model)
-            .system(SystemContentBlock::Text(self.system_prompt.clone()));
-        let response = request.send().await?;
+        let request = self.converse_request()?.messages(
+            Message::builder()
+                .role(ConversationRole::User)
+                .set_content(Some(self.model.content_blocks(input.into())?))
+                .build()
+                .expect("Error building message"),
+        );
+        let response = self.send(request).await?;
 
-        match response.output() {
-            Some(output) => Ok(output.as_message().unwrap().content[0]
-                .as_text()
-                .unwrap()
-                .clone()),
-            None => Err(AnchorChainError::ModelError(
-                "No output returned".to_string(),
-            )),
-        }
+        let message = response
+            .output()
+            .and_then(|output| output.as_message().ok())
+            .ok_or_else(|| AnchorChainError::ModelError("No output returned".to_string()))?;
+        Ok(message
+            .content
+            .iter()
+            .filter_map(|content| content.as_text().ok())
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("\n"))
     }
 }
 
-impl<'a> Stateless for BedrockConverse<'a, String> {}
+impl<'a, I> Stateless for BedrockConverse<'a, String, I> where
+    I: Into<MultimodalInput> + Send + Sync + fmt::Debug
+{
+}
 
 #[async_trait]
-impl<'a> Stateful<String, Vec<String>> for BedrockConverse<'a, String> {
-    async fn set_state(&mut self, state: StateManager<String, Vec<String>>) {
-        self.history = state;
+impl<'a, I> Stateful for BedrockConverse<'a, String, I>
+where
+    I: Into<MultimodalInput> + Send + Sync + fmt::Debug,
+{
+    /// Processes the input, which doesn't use any state for text output.
+    async fn process_with_state(
+        &self,
+        input: Self::Input,
+        _state: &ChainState,
+    ) -> Result<Self::Output, AnchorChainError> {
+        self.process(input).await
     }
 }
 
-impl<'a> BedrockConverse<'a, Message> {
-    async fn generate_message_with_history(&self, user_message: impl Into<String>) -> Vec<Message> {
+impl<'a, I> BedrockConverse<'a, Message, I> {
+    /// Sets the strategy selecting which messages of the conversation history are
+    /// sent with each request.
+    ///
+    /// ```rust,ignore
+    /// let llm = BedrockConverse::<Message>::new(BedrockModel::Claude3_5)
+    ///     .await
+    ///     .with_memory(LastTurns::new(10));
+    /// ```
+    pub fn with_memory(mut self, memory: impl MemoryStrategy<Message> + 'static) -> Self {
+        self.memory = Some(Arc::new(memory));
+        self
+    }
+
+    /// Returns the messages of the history to send using the memory strategy.
+    async fn history_for_request(
+        &self,
+        history: &StateManager<String, Vec<ConverseMessage>>,
+    ) -> Result<Vec<Message>, AnchorChainError> {
+        let history = history
+            .get(&HISTORY_KEY.to_string())
+            .await
+            .unwrap_or_default()
+            .iter()
+            .map(Message::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        match &self.memory {
+            Some(memory) => memory.select(&history).await,
+            None => Ok(history),
+        }
+    }
+
+    async fn generate_message_with_history(
+        &self,
+        history: &StateManager<String, Vec<ConverseMessage>>,
+        user_message: impl Into<MultimodalInput>,
+    ) -> Result<Vec<Message>, AnchorChainError> {
         let message = Message::builder()
             .role(ConversationRole::User)
-            .content(ContentBlock::Text(user_message.into()))
+            .set_content(Some(self.model.content_blocks(user_message.into())?))
             .build()
             .expect("Error building message");
-        self.history.push(HISTORY_KEY.to_string(), message).await;
-        self.history
-            .get(&HISTORY_KEY.to_string())
-            .await
-            .expect("Messages should not be empty")
+        history
+            .push(
+                HISTORY_KEY.to_string(),
+                ConverseMessage::try_from(&message)?,
+            )
+            .await?;
+        self.history_for_request(history).await
     }
 
     async fn create_request<'b>(
         &self,
-        input: impl Into<String>,
+        history: &StateManager<String, Vec<ConverseMessage>>,
+        input: impl Into<MultimodalInput>,
         tool_registry: Option<&'b RwLock<ToolRegistry<'b>>>,
-    ) -> ConverseFluentBuilder {
-        let mut request = self
-            .client
-            .converse()
-            .model_id(self.model)
-            .set_messages(Some(self.generate_message_with_history(input).await))
-            .system(SystemContentBlock::Text(self.system_prompt.clone()));
+    ) -> Result<ConverseFluentBuilder, AnchorChainError> {
+        let mut request = self.converse_request()?;
 
         if let Some(tools) = tool_registry {
+            self.model.check_tools()?;
             let tool_config = self.generate_tool_configuration(tools).await;
             request = request.tool_config(tool_config);
         }
 
-        request
+        Ok(request.set_messages(Some(
+            self.generate_message_with_history(history, input).await?,
+        )))
+    }
+
+    /// Sends the input with the conversation history, adding the input and the
+    /// model's reply to the history.
+    async fn converse(
+        &self,
+        history: &StateManager<String, Vec<ConverseMessage>>,
+        input: impl Into<MultimodalInput>,
+    ) -> Result<Message, AnchorChainError> {
+        let request = self
+            .create_request(history, input, self.tool_registry)
+            .await?;
+        let response = self.send(request).await?;
+        self.process_model_response(history, response).await
     }
     pub async fn invoke_with_tool_responses(
         &self,
         results: &[ToolResultBlock],
         tool_registry: &RwLock<ToolRegistry<'_>>,
     ) -> Result<Message, AnchorChainError> {
+        self.model.check_tools()?;
         let message = Message::builder()
             .role(ConversationRole::User)
             .set_content(Some(
@@ -193,37 +977,35 @@ impl<'a> BedrockConverse<'a, Message> {
             .build()
             .expect("Error building message");
 
-        self.history.push(HISTORY_KEY.to_string(), message).await;
+        self.history
+            .push(
+                HISTORY_KEY.to_string(),
+                ConverseMessage::try_from(&message)?,
+            )
+            .await?;
 
         let mut request = self
-            .client
-            .converse()
-            .model_id(self.model)
-            .set_messages(Some(
-                self.history
-                    .get(&HISTORY_KEY.to_string())
-                    .await
-                    .expect("History should exist"),
-            ))
-            .system(SystemContentBlock::Text(self.system_prompt.clone()));
+            .converse_request()?
+            .set_messages(Some(self.history_for_request(&self.history).await?));
 
         let tool_config = self.generate_tool_configuration(tool_registry).await;
         request = request.tool_config(tool_config);
 
-        let output = request.send().await?;
-        self.process_model_response(output).await
+        let output = self.send(request).await?;
+        self.process_model_response(&self.history, output).await
     }
 
     async fn process_model_response(
         &self,
+        history: &StateManager<String, Vec<ConverseMessage>>,
         response: ConverseOutput,
     ) -> Result<Message, AnchorChainError> {
         match response.output() {
             Some(output) => {
                 let message = output.as_message().unwrap();
-                self.history
-                    .push(HISTORY_KEY.to_string(), message.clone())
-                    .await;
+                history
+                    .push(HISTORY_KEY.to_string(), ConverseMessage::try_from(message)?)
+                    .await?;
                 Ok(message.clone())
             }
             None => Err(AnchorChainError::ModelError(
@@ -248,14 +1030,17 @@ impl<'a> BedrockConverse<'a, Message> {
         println!("===========\n");
 
         // let mut response = self.process(input.clone()).await?.content;
-        let response = self
-            .create_request(input, Some(tool_registry))
-            .await
-            .send()
+        let request = self
+            .create_request(&self.history, input, Some(tool_registry))
             .await?;
-        let mut response = self.process_model_response(response).await?.content;
+        let response = self.send(request).await?;
+        let mut response = self
+            .process_model_response(&self.history, response)
+            .await?
+            .content;
 
         for _ in 0..max_iterations {
+            RunContext::check_current()?;
             let mut tool_responses = Vec::new();
             for content in response.clone() {
                 match content {
@@ -290,9 +1075,21 @@ impl<'a> BedrockConverse<'a, Message> {
                             }
                         };
                     }
-                    ContentBlock::Image(_) => unimplemented!("Received unexpected Image response"),
-                    ContentBlock::ToolResult(_) => unreachable!("Received ToolResult from model"),
-                    _ => unimplemented!("Unknown response received from model"),
+                    ContentBlock::Image(_) => {
+                        return Err(AnchorChainError::ModelError(
+                            "Received unexpected image response from model".to_string(),
+                        ))
+                    }
+                    ContentBlock::ToolResult(_) => {
+                        return Err(AnchorChainError::ModelError(
+                            "Received tool result from model".to_string(),
+                        ))
+                    }
+                    _ => {
+                        return Err(AnchorChainError::ModelError(
+                            "Unknown response received from model".to_string(),
+                        ))
+                    }
                 }
             }
             if tool_responses.is_empty() {
@@ -342,38 +1139,51 @@ impl<'a> BedrockConverse<'a, Message> {
 }
 
 #[async_trait]
-impl<'a> Node for BedrockConverse<'a, Message> {
-    type Input = String;
+impl<'a, I> Node for BedrockConverse<'a, Message, I>
+where
+    I: Into<MultimodalInput> + Send + Sync + fmt::Debug,
+{
+    type Input = I;
     type Output = Message;
 
-    /// Processes the input through the Claude 3 model, returning the model's output.
+    /// Processes the input through the Bedrock model, returning the model's output.
     ///
-    /// Constructs a request to the Claude 3 model with the provided input, sends it via
+    /// Constructs a request to the Bedrock model with the provided input, sends it via
     /// AWS Bedrock, and extracts the text content from the response.
-    #[cfg_attr(feature = "tracing", instrument(fields(system_prompt = self.system_prompt.as_str())))]
+    #[cfg_attr(feature = "tracing", instrument(fields(model = self.model.as_str(), system_prompt = self.system_prompt.as_deref())))]
     async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
-        let response = self
-            .create_request(input, self.tool_registry)
-            .await
-            .send()
-            .await?;
-
-        self.process_model_response(response).await
+        self.converse(&self.history, input).await
     }
 }
 
-impl<'a> Stateless for BedrockConverse<'a, Message> {}
+impl<'a, I> Stateless for BedrockConverse<'a, Message, I> where
+    I: Into<MultimodalInput> + Send + Sync + fmt::Debug
+{
+}
 
 #[async_trait]
-impl<'a> Stateful<String, Vec<Message>> for BedrockConverse<'a, Message> {
-    async fn set_state(&mut self, state: StateManager<String, Vec<Message>>) {
-        self.history = state;
+impl<'a, I> Stateful for BedrockConverse<'a, Message, I>
+where
+    I: Into<MultimodalInput> + Send + Sync + fmt::Debug,
+{
+    /// Processes the input using the conversation history kept in the chain's
+    /// state instead of the processor's own history.
+    #[cfg_attr(feature = "tracing", instrument(skip(self, state), fields(model = self.model.as_str())))]
+    async fn process_with_state(
+        &self,
+        input: Self::Input,
+        state: &ChainState,
+    ) -> Result<Self::Output, AnchorChainError> {
+        self.converse(&state.manager(), input).await
     }
 }
 
 #[async_trait]
-impl<'a> Node for &BedrockConverse<'a, Message> {
-    type Input = String;
+impl<'a, I> Node for &BedrockConverse<'a, Message, I>
+where
+    I: Into<MultimodalInput> + Send + Sync + fmt::Debug,
+{
+    type Input = I;
     type Output = Message;
 
     async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
@@ -381,19 +1191,64 @@ impl<'a> Node for &BedrockConverse<'a, Message> {
     }
 }
 
-impl<'a> Stateless for &BedrockConverse<'a, Message> {}
+impl<'a, I> Stateless for &BedrockConverse<'a, Message, I> where
+    I: Into<MultimodalInput> + Send + Sync + fmt::Debug
+{
+}
 
 #[async_trait]
-impl<'a> Stateful<String, Vec<Message>> for &BedrockConverse<'a, Message> {
-    async fn set_state(&mut self, state: StateManager<String, Vec<Message>>) {
-        self.set_state(state).await;
+impl<'a, I> Stateful for &BedrockConverse<'a, Message, I>
+where
+    I: Into<MultimodalInput> + Send + Sync + fmt::Debug,
+{
+    async fn process_with_state(
+        &self,
+        input: Self::Input,
+        state: &ChainState,
+    ) -> Result<Self::Output, AnchorChainError> {
+        (**self).process_with_state(input, state).await
     }
 }
 
-impl<'a, T: Clone> fmt::Debug for BedrockConverse<'a, T> {
+impl ChatMessage for Message {
+    fn role(&self) -> ChatRole {
+        match self.role {
+            ConversationRole::Assistant => ChatRole::Assistant,
+            _ => ChatRole::User,
+        }
+    }
+
+    fn text(&self) -> String {
+        self.content
+            .iter()
+            .filter_map(|content| content.as_text().ok())
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Returns true for user messages that aren't only tool results.
+    fn starts_turn(&self) -> bool {
+        self.role == ConversationRole::User
+            && !self
+                .content
+                .iter()
+                .all(|content| matches!(content, ContentBlock::ToolResult(_)))
+    }
+
+    fn with_context(mut self, context: &str) -> Self {
+        self.content
+            .insert(0, ContentBlock::Text(context.to_string()));
+        self
+    }
+}
+
+impl<'a, T: Clone, I> fmt::Debug for BedrockConverse<'a, T, I> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("BedrockConverse")
+            .field("model", &self.model)
             .field("system_prompt", &self.system_prompt)
+            .field("memory", &self.memory)
             .finish()
     }
 }
@@ -423,8 +1278,116 @@ impl<'a> Node for Claude3Bedrock<'a> {
     type Input = String;
     type Output = String;
 
-    #[cfg_attr(feature = "tracing", instrument(fields(system_prompt = self.llm.system_prompt.as_str())))]
+    #[cfg_attr(feature = "tracing", instrument(fields(system_prompt = self.llm.system_prompt.as_deref())))]
     async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
         self.llm.process(input).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state_store::JsonFileStore;
+
+    #[test]
+    fn test_from_id_matches_inference_profiles_and_arns() {
+        assert_eq!(
+            BedrockModel::from_id("us.anthropic.claude-3-haiku-20240307-v1:0"),
+            Some(BedrockModel::Claude3Haiku)
+        );
+        assert_eq!(
+            BedrockModel::from_id(
+                "arn:aws:bedrock:us-east-1::foundation-model/meta.llama3-8b-instruct-v1:0"
+            ),
+            Some(BedrockModel::Llama3_8BInstruct)
+        );
+        assert_eq!(BedrockModel::from_id("example.unknown-model-v1:0"), None);
+    }
+
+    #[test]
+    fn test_custom_model_capabilities() {
+        let profile = BedrockModel::inference_profile("eu.amazon.titan-text-express-v1");
+        assert_eq!(profile.as_str(), "eu.amazon.titan-text-express-v1");
+        assert_eq!(profile.capabilities(), ModelCapabilities::TEXT_ONLY);
+        assert!(profile.check_system_prompt().is_err());
+
+        let unknown = BedrockModel::custom("example.unknown-model-v1:0");
+        assert_eq!(unknown.capabilities(), ModelCapabilities::ALL);
+        assert!(unknown.check_tools().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_persisted_history_reloads_messages() {
+        let messages = vec![
+            Message::builder()
+                .role(ConversationRole::User)
+                .content(ContentBlock::Text("What's the weather?".to_string()))
+                .content(ContentBlock::Image(
+                    ImageBlock::builder()
+                        .format(ImageFormat::Png)
+                        .source(ImageSource::Bytes(Blob::new(vec![137, 80, 78, 71])))
+                        .build()
+                        .unwrap(),
+                ))
+                .build()
+                .unwrap(),
+            Message::builder()
+                .role(ConversationRole::Assistant)
+                .content(ContentBlock::ToolUse(
+                    ToolUseBlock::builder()
+                        .tool_use_id("call-1")
+                        .name("weather")
+                        .input(convert_value_to_document(
+                            &serde_json::json!({"city": "Paris"}),
+                        ))
+                        .build()
+                        .unwrap(),
+                ))
+                .build()
+                .unwrap(),
+            Message::builder()
+                .role(ConversationRole::User)
+                .content(ContentBlock::ToolResult(
+                    BedrockConverse::<Message>::generate_tool_result_block(
+                        "call-1",
+                        serde_json::json!("sunny"),
+                        true,
+                    ),
+                ))
+                .build()
+                .unwrap(),
+        ];
+
+        let dir = std::env::temp_dir().join(format!("anchor-chain-bedrock-{}", std::process::id()));
+        let history = StateManager::<String, Vec<ConverseMessage>>::new();
+        history
+            .persist_to(JsonFileStore::new(&dir).await.unwrap())
+            .await
+            .unwrap();
+        for message in &messages {
+            history
+                .push(
+                    HISTORY_KEY.to_string(),
+                    ConverseMessage::try_from(message).unwrap(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let restored = StateManager::<String, Vec<ConverseMessage>>::new();
+        restored
+            .persist_to(JsonFileStore::new(&dir).await.unwrap())
+            .await
+            .unwrap();
+        let reloaded = restored
+            .get(&HISTORY_KEY.to_string())
+            .await
+            .unwrap()
+            .iter()
+            .map(Message::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(reloaded, messages);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,342 @@
+//! Module for generating embeddings with models hosted on AWS Bedrock.
+//!
+//! Provides `BedrockEmbeddingModel`, an `EmbeddingModel` backed by Amazon Titan Text
+//! Embeddings and Cohere Embed models invoked through the Bedrock `InvokeModel` API.
+//! Combined with `OpenSearchClientBuilder::with_aws_opensearch_serverless_connection`
+//! this allows building retrieval chains entirely on AWS.
+
+use std::fmt;
+use std::time::Instant;
+
+use anchor_chain_macros::Stateless;
+use async_trait::async_trait;
+use aws_config::SdkConfig;
+use aws_sdk_bedrockruntime::Client;
+use aws_smithy_types::Blob;
+use futures::{stream, StreamExt, TryStreamExt};
+use serde_json::{json, Value};
+#[cfg(feature = "tracing")]
+use tracing::instrument;
+
+use crate::error::AnchorChainError;
+use crate::models::embedding_model::EmbeddingModel;
+use crate::node::Node;
+use crate::usage::{record_usage, TokenUsage};
+
+/// The maximum number of texts Cohere Embed accepts in a single request.
+const COHERE_MAX_BATCH_SIZE: usize = 96;
+
+/// The default maximum number of requests sent at once.
+const DEFAULT_CONCURRENCY: usize = 8;
+
+/// Embedding models available through the Bedrock `InvokeModel` API.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BedrockEmbedding {
+    /// Amazon Titan Text Embeddings with 1536 dimensions.
+    TitanTextV1,
+    /// Amazon Titan Text Embeddings V2 with 256, 512 or 1024 dimensions.
+    TitanTextV2,
+    /// Cohere Embed English with 1024 dimensions.
+    CohereEnglishV3,
+    /// Cohere Embed Multilingual with 1024 dimensions.
+    CohereMultilingualV3,
+}
+
+impl BedrockEmbedding {
+    /// The model ID sent to Bedrock.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::TitanTextV1 => "amazon.titan-embed-text-v1",
+            Self::TitanTextV2 => "amazon.titan-embed-text-v2:0",
+            Self::CohereEnglishV3 => "cohere.embed-english-v3",
+            Self::CohereMultilingualV3 => "cohere.embed-multilingual-v3",
+        }
+    }
+
+    /// The number of dimensions the model returns when not configured otherwise.
+    pub fn default_dimensions(&self) -> usize {
+        match self {
+            Self::TitanTextV1 => 1536,
+            Self::TitanTextV2 | Self::CohereEnglishV3 | Self::CohereMultilingualV3 => 1024,
+        }
+    }
+
+    /// The output dimensions the model can be configured to return.
+    pub fn supported_dimensions(&self) -> &'static [usize] {
+        match self {
+            Self::TitanTextV1 => &[1536],
+            Self::TitanTextV2 => &[256, 512, 1024],
+            Self::CohereEnglishV3 | Self::CohereMultilingualV3 => &[1024],
+        }
+    }
+
+    fn is_cohere(&self) -> bool {
+        matches!(self, Self::CohereEnglishV3 | Self::CohereMultilingualV3)
+    }
+}
+
+/// The type of input being embedded by Cohere Embed models.
+///
+/// Cohere recommends embedding indexed documents with `SearchDocument` and
+/// the queries used to search them with `SearchQuery`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CohereInputType {
+    /// Documents stored in a vector database.
+    #[default]
+    SearchDocument,
+    /// Queries used to search a vector database.
+    SearchQuery,
+    /// Text passed to a classifier.
+    Classification,
+    /// Text that will be clustered.
+    Clustering,
+}
+
+impl CohereInputType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::SearchDocument => "search_document",
+            Self::SearchQuery => "search_query",
+            Self::Classification => "classification",
+            Self::Clustering => "clustering",
+        }
+    }
+}
+
+/// Node for generating embeddings with Bedrock embedding models.
+///
+/// `BedrockEmbeddingModel` implements `EmbeddingModel` so it can be used directly
+/// with `OpenSearchIndexer` and `OpenSearchRetriever`. Embeddings are normalized
+/// by the model for Titan Text Embeddings V2 and on the client for all other models.
+#[derive(Clone, Stateless)]
+pub struct BedrockEmbeddingModel {
+    model: BedrockEmbedding,
+    client: Client,
+    dimensions: usize,
+    normalize: bool,
+    input_type: CohereInputType,
+    concurrency: usize,
+}
+
+impl BedrockEmbeddingModel {
+    /// Constructs a new `BedrockEmbeddingModel` for the specified model.
+    ///
+    /// Initializes the AWS Bedrock client using the environment's AWS configuration.
+    pub async fn new(model: BedrockEmbedding) -> Self {
+        let config = aws_config::load_from_env().await;
+        Self::new_with_config(model, &config)
+    }
+
+    /// Constructs a new `BedrockEmbeddingModel` using the provided AWS SDK config.
+    pub fn new_with_config(model: BedrockEmbedding, config: &SdkConfig) -> Self {
+        BedrockEmbeddingModel {
+            model,
+            client: Client::new(config),
+            dimensions: model.default_dimensions(),
+            normalize: true,
+            input_type: CohereInputType::default(),
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+
+    /// Sets the number of dimensions for the returned embeddings.
+    ///
+    /// Returns an error if the model doesn't support the requested dimensions.
+    pub fn with_dimensions(mut self, dimensions: usize) -> Result<Self, AnchorChainError> {
+        if !self.model.supported_dimensions().contains(&dimensions) {
+            return Err(AnchorChainError::InvalidInputError(format!(
+                "{} does not support {} dimensions, expected one of {:?}",
+                self.model.as_str(),
+                dimensions,
+                self.model.supported_dimensions()
+            )));
+        }
+        self.dimensions = dimensions;
+        Ok(self)
+    }
+
+    /// Sets whether embeddings are normalized to unit length. Defaults to `true`.
+    pub fn with_normalize(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    /// Sets the input type sent to Cohere Embed models.
+    ///
+    /// This setting is ignored for Titan models.
+    pub fn with_input_type(mut self, input_type: CohereInputType) -> Self {
+        self.input_type = input_type;
+        self
+    }
+
+    /// Sets the maximum number of requests sent at once when embedding more texts
+    /// than fit in one request. Defaults to 8.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Builds the `InvokeModel` request body for the given texts.
+    ///
+    /// Titan models accept a single text per request while Cohere models accept a batch.
+    fn request_body(&self, texts: &[String]) -> Value {
+        match self.model {
+            BedrockEmbedding::TitanTextV1 => json!({ "inputText": texts[0] }),
+            BedrockEmbedding::TitanTextV2 => json!({
+                "inputText": texts[0],
+                "dimensions": self.dimensions,
+                "normalize": self.normalize,
+            }),
+            BedrockEmbedding::CohereEnglishV3 | BedrockEmbedding::CohereMultilingualV3 => json!({
+                "texts": texts,
+                "input_type": self.input_type.as_str(),
+            }),
+        }
+    }
+
+    /// Extracts the embeddings and input token count from an `InvokeModel` response body.
+    ///
+    /// Cohere responses don't include a token count so it is reported as zero.
+    fn parse_response(&self, body: &[u8]) -> Result<(Vec<Vec<f32>>, u64), AnchorChainError> {
+        let response: Value = serde_json::from_slice(body)?;
+        let tokens = response["inputTextTokenCount"].as_u64().unwrap_or_default();
+        let embeddings: Vec<Vec<f32>> = if self.model.is_cohere() {
+            serde_json::from_value(response["embeddings"].clone())?
+        } else {
+            vec![serde_json::from_value(response["embedding"].clone())?]
+        };
+
+        if self.normalize && self.model != BedrockEmbedding::TitanTextV2 {
+            Ok((embeddings.into_iter().map(normalize).collect(), tokens))
+        } else {
+            Ok((embeddings, tokens))
+        }
+    }
+
+    /// Sends a single `InvokeModel` request for the given texts.
+    async fn invoke(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, AnchorChainError> {
+        let body = serde_json::to_vec(&self.request_body(texts))?;
+        let start = Instant::now();
+        let response = self
+            .client
+            .invoke_model()
+            .model_id(self.model.as_str())
+            .content_type("application/json")
+            .accept("application/json")
+            .body(Blob::new(body))
+            .send()
+            .await?;
+        let (embeddings, tokens) = self.parse_response(response.body().as_ref())?;
+        record_usage(
+            "bedrock",
+            self.model.as_str(),
+            TokenUsage::new(tokens, 0),
+            start.elapsed(),
+        );
+        Ok(embeddings)
+    }
+}
+
+/// Scales a vector to unit length.
+fn normalize(vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        vector
+    } else {
+        vector.into_iter().map(|v| v / norm).collect()
+    }
+}
+
+#[async_trait]
+impl Node for BedrockEmbeddingModel {
+    type Input = Vec<String>;
+    type Output = Vec<Vec<f32>>;
+
+    /// Embeds each input text, returning the embeddings in the same order.
+    #[cfg_attr(feature = "tracing", instrument(skip(self), fields(model = self.model.as_str())))]
+    async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+        if input.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let batch_size = if self.model.is_cohere() {
+            COHERE_MAX_BATCH_SIZE
+        } else {
+            1
+        };
+        // Requests only start once polled, so at most `concurrency` run at once.
+        let requests: Vec<_> = input
+            .chunks(batch_size)
+            .map(|texts| self.invoke(texts))
+            .collect();
+        let batches: Vec<_> = stream::iter(requests)
+            .buffered(self.concurrency)
+            .try_collect()
+            .await?;
+        Ok(batches.into_iter().flatten().collect())
+    }
+}
+
+#[async_trait]
+impl EmbeddingModel for BedrockEmbeddingModel {
+    #[cfg_attr(feature = "tracing", instrument(skip(self), fields(model = self.model.as_str())))]
+    async fn embed(&self, input: String) -> Result<Vec<f32>, AnchorChainError> {
+        self.process(vec![input])
+            .await?
+            .into_iter()
+            .next()
+            .ok_or(AnchorChainError::EmptyResponseError)
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+}
+
+impl fmt::Debug for BedrockEmbeddingModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BedrockEmbeddingModel")
+            .field("model", &self.model)
+            .field("dimensions", &self.dimensions)
+            .field("normalize", &self.normalize)
+            .field("input_type", &self.input_type)
+            .field("concurrency", &self.concurrency)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_config::BehaviorVersion;
+
+    fn model(model: BedrockEmbedding) -> BedrockEmbeddingModel {
+        let config = SdkConfig::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .build();
+        BedrockEmbeddingModel::new_with_config(model, &config)
+    }
+
+    #[test]
+    fn test_titan_v2_request_body() {
+        let model = model(BedrockEmbedding::TitanTextV2)
+            .with_dimensions(256)
+            .unwrap()
+            .with_normalize(false);
+        assert_eq!(
+            model.request_body(&["hello".to_string()]),
+            json!({"inputText": "hello", "dimensions": 256, "normalize": false})
+        );
+        assert!(model.with_dimensions(300).is_err());
+    }
+
+    #[test]
+    fn test_cohere_response_is_normalized() {
+        let model = model(BedrockEmbedding::CohereEnglishV3);
+        let body = br#"{"id": "1", "embeddings": [[3.0, 4.0], [0.0, 2.0]], "texts": ["a", "b"]}"#;
+        assert_eq!(
+            model.parse_response(body).unwrap(),
+            (vec![vec![0.6, 0.8], vec![0.0, 1.0]], 0)
+        );
+    }
+}
@@ -0,0 +1,340 @@
+//! Module for running quantized GGUF models in-process on the CPU.
+//!
+//! Uses [candle](https://github.com/huggingface/candle) to load Llama-family models
+//! (Llama, Mistral and compatible fine-tunes) from GGUF files so chains can run
+//! without network access or a separate inference server. A Hugging Face
+//! `tokenizer.json` for the model is required alongside the GGUF file.
+//!
+//! Chat models expect prompts in their chat template, which can be applied with a
+//! `Prompt` node before the model.
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+
+use async_trait::async_trait;
+use candle_core::quantized::{gguf_file, QTensor};
+use candle_core::{Device, Tensor};
+use candle_transformers::generation::LogitsProcessor;
+use candle_transformers::models::quantized_llama::{ModelWeights, MAX_SEQ_LEN};
+use tokenizers::Tokenizer;
+#[cfg(feature = "tracing")]
+use tracing::instrument;
+
+use crate::context::RunContext;
+use crate::models::embedding_model::EmbeddingModel;
+use crate::tokenizer;
+use crate::usage::{record_usage, TokenUsage};
+use crate::{AnchorChainError, Node, Stateless};
+
+const PROVIDER: &str = "gguf";
+
+/// End of sequence tokens used by common models when the GGUF file doesn't
+/// specify one.
+const EOS_TOKENS: [&str; 3] = ["</s>", "<|eot_id|>", "<|end_of_text|>"];
+
+/// The loaded model shared between clones.
+struct Inner {
+    weights: Mutex<ModelWeights>,
+    token_embeddings: QTensor,
+    /// Token embeddings dequantized on first use by `embed`.
+    dequantized_embeddings: OnceLock<Tensor>,
+    tokenizer: Tokenizer,
+    eos_token: Option<u32>,
+    dimensions: usize,
+}
+
+/// A quantized GGUF model running in-process on the CPU.
+///
+/// Generation runs on a blocking thread and requests to the same model are
+/// processed one at a time. Clones share the loaded model.
+///
+/// As an `EmbeddingModel`, the input is embedded as the mean of the model's input
+/// token embeddings. These static embeddings are cheap to compute and useful for
+/// caching and lightweight retrieval, but a dedicated embedding model produces
+/// better results for semantic search.
+///
+/// # Example
+/// ```rust,no_run
+/// use anchor_chain::models::gguf::GgufModel;
+/// use anchor_chain::ChainBuilder;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let model = GgufModel::from_files(
+///         "models/mistral-7b-instruct-v0.2.Q4_K_M.gguf",
+///         "models/tokenizer.json",
+///     )
+///     .await
+///     .expect("Error loading model")
+///     .with_max_tokens(256);
+///
+///     let chain = ChainBuilder::new().link(model).build();
+///     let output = chain
+///         .process("[INST] Write a haiku about Rust [/INST]".to_string())
+///         .await
+///         .expect("Error processing chain");
+///     println!("{}", output);
+/// }
+/// ```
+#[derive(Clone)]
+pub struct GgufModel {
+    /// The file name of the model, used when recording usage.
+    name: String,
+    inner: Arc<Inner>,
+    /// The maximum number of tokens to generate.
+    max_tokens: usize,
+    /// The sampling temperature, or `None` to always pick the most likely token.
+    temperature: Option<f64>,
+    /// The cumulative probability of tokens to sample from.
+    top_p: Option<f64>,
+    /// The seed for sampling.
+    seed: u64,
+}
+
+impl GgufModel {
+    /// Loads a model from a GGUF file and its Hugging Face tokenizer.
+    ///
+    /// Defaults to generating at most 512 tokens with a temperature of 0.8.
+    pub async fn from_files(
+        model_path: impl AsRef<Path>,
+        tokenizer_path: impl AsRef<Path>,
+    ) -> Result<Self, AnchorChainError> {
+        let model_path = model_path.as_ref().to_path_buf();
+        let tokenizer_path = tokenizer_path.as_ref().to_path_buf();
+        let name = model_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let inner = tokio::task::spawn_blocking(move || {
+            let tokenizer = Tokenizer::from_file(&tokenizer_path).map_err(|e| {
+                AnchorChainError::ModelError(format!("{}: {e}", tokenizer_path.display()))
+            })?;
+            let mut file = std::fs::File::open(&model_path).map_err(|e| {
+                AnchorChainError::ModelError(format!("{}: {e}", model_path.display()))
+            })?;
+            let content = gguf_file::Content::read(&mut file)?;
+
+            let eos_token = content
+                .metadata
+                .get("tokenizer.ggml.eos_token_id")
+                .and_then(|id| id.to_u32().ok())
+                .or_else(|| {
+                    EOS_TOKENS
+                        .iter()
+                        .find_map(|token| tokenizer.token_to_id(token))
+                });
+            let token_embeddings = content.tensor(&mut file, "token_embd.weight", &Device::Cpu)?;
+            let dimensions = token_embeddings.shape().dims().last().copied().unwrap_or(0);
+            let weights = ModelWeights::from_gguf(content, &mut file, &Device::Cpu)?;
+
+            Ok::<_, AnchorChainError>(Inner {
+                weights: Mutex::new(weights),
+                token_embeddings,
+                dequantized_embeddings: OnceLock::new(),
+                tokenizer,
+                eos_token,
+                dimensions,
+            })
+        })
+        .await
+        .map_err(|e| AnchorChainError::ModelError(e.to_string()))??;
+
+        Ok(GgufModel {
+            name,
+            inner: Arc::new(inner),
+            max_tokens: 512,
+            temperature: Some(0.8),
+            top_p: None,
+            seed: 299792458,
+        })
+    }
+
+    /// Sets the maximum number of tokens to generate.
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    /// Sets the sampling temperature. A temperature of 0 always picks the most
+    /// likely token.
+    pub fn with_temperature(mut self, temperature: f64) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    /// Samples only from the most likely tokens whose cumulative probability is `top_p`.
+    pub fn with_top_p(mut self, top_p: f64) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    /// Sets the seed used for sampling.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Returns the token IDs of the text.
+    fn encode(&self, text: &str) -> Result<Vec<u32>, AnchorChainError> {
+        let encoding = self
+            .inner
+            .tokenizer
+            .encode(text, true)
+            .map_err(|e| AnchorChainError::ModelError(e.to_string()))?;
+        Ok(encoding.get_ids().to_vec())
+    }
+
+    /// Generates a completion of the prompt tokens, returning the generated tokens.
+    ///
+    /// Generation stops if the run is cancelled or its deadline passes.
+    fn generate(
+        &self,
+        prompt_tokens: &[u32],
+        context: &RunContext,
+    ) -> Result<Vec<u32>, AnchorChainError> {
+        if prompt_tokens.len() + self.max_tokens > MAX_SEQ_LEN {
+            return Err(AnchorChainError::InvalidInputError(format!(
+                "prompt of {} tokens plus {} generated tokens exceeds the context of {MAX_SEQ_LEN} tokens",
+                prompt_tokens.len(),
+                self.max_tokens
+            )));
+        }
+
+        let mut weights = self.inner.weights.lock().expect("Model lock poisoned");
+        let mut logits_processor = LogitsProcessor::new(self.seed, self.temperature, self.top_p);
+        let input = Tensor::new(prompt_tokens, &Device::Cpu)?.unsqueeze(0)?;
+        let logits = weights.forward(&input, 0)?.squeeze(0)?;
+        let mut next = logits_processor.sample(&logits)?;
+
+        let mut generated = Vec::new();
+        while generated.len() < self.max_tokens && Some(next) != self.inner.eos_token {
+            context.check()?;
+            generated.push(next);
+            let input = Tensor::new(&[next], &Device::Cpu)?.unsqueeze(0)?;
+            let position = prompt_tokens.len() + generated.len() - 1;
+            let logits = weights.forward(&input, position)?.squeeze(0)?;
+            next = logits_processor.sample(&logits)?;
+        }
+        Ok(generated)
+    }
+
+    /// Returns the mean of the token embeddings of the text.
+    fn mean_embedding(&self, tokens: &[u32]) -> Result<Vec<f32>, AnchorChainError> {
+        if tokens.is_empty() {
+            return Ok(vec![0.0; self.inner.dimensions]);
+        }
+        let embeddings = match self.inner.dequantized_embeddings.get() {
+            Some(embeddings) => embeddings,
+            None => {
+                let embeddings = self.inner.token_embeddings.dequantize(&Device::Cpu)?;
+                self.inner.dequantized_embeddings.get_or_init(|| embeddings)
+            }
+        };
+        let ids = Tensor::new(tokens, &Device::Cpu)?;
+        Ok(embeddings.index_select(&ids, 0)?.mean(0)?.to_vec1()?)
+    }
+}
+
+#[async_trait]
+impl Node for GgufModel {
+    type Input = String;
+    type Output = String;
+
+    /// Generates a completion of the input using the model.
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+        let model = self.clone();
+        let context = RunContext::current();
+        let start = Instant::now();
+        let (prompt_tokens, completion_tokens, output) = tokio::task::spawn_blocking(move || {
+            let prompt_tokens = model.encode(&input)?;
+            let generated = model.generate(&prompt_tokens, &context)?;
+            let output = model
+                .inner
+                .tokenizer
+                .decode(&generated, true)
+                .map_err(|e| AnchorChainError::ModelError(e.to_string()))?;
+            Ok::<_, AnchorChainError>((prompt_tokens.len(), generated.len(), output))
+        })
+        .await
+        .map_err(|e| AnchorChainError::ModelError(e.to_string()))??;
+
+        record_usage(
+            PROVIDER,
+            &self.name,
+            TokenUsage::new(prompt_tokens as u64, completion_tokens as u64),
+            start.elapsed(),
+        );
+        Ok(output)
+    }
+}
+
+impl Stateless for GgufModel {}
+
+#[async_trait]
+impl EmbeddingModel for GgufModel {
+    /// Embeds the input as the mean of its token embeddings.
+    async fn embed(&self, input: String) -> Result<Vec<f32>, AnchorChainError> {
+        let model = self.clone();
+        let start = Instant::now();
+        let (tokens, embedding) = tokio::task::spawn_blocking(move || {
+            let tokens = model.encode(&input)?;
+            let embedding = model.mean_embedding(&tokens)?;
+            Ok::<_, AnchorChainError>((tokens.len(), embedding))
+        })
+        .await
+        .map_err(|e| AnchorChainError::ModelError(e.to_string()))??;
+
+        record_usage(
+            PROVIDER,
+            &self.name,
+            TokenUsage::new(tokens as u64, 0),
+            start.elapsed(),
+        );
+        Ok(embedding)
+    }
+
+    fn dimensions(&self) -> usize {
+        self.inner.dimensions
+    }
+}
+
+impl tokenizer::Tokenizer for GgufModel {
+    fn count_tokens(&self, text: &str) -> usize {
+        self.inner
+            .tokenizer
+            .encode(text, false)
+            .map(|encoding| encoding.len())
+            .unwrap_or_else(|_| tokenizer::ApproximateTokenizer::default().count_tokens(text))
+    }
+
+    fn truncate(&self, text: &str, max_tokens: usize) -> String {
+        let Ok(encoding) = self.inner.tokenizer.encode(text, false) else {
+            return tokenizer::ApproximateTokenizer::default().truncate(text, max_tokens);
+        };
+        match encoding.get_offsets().get(max_tokens) {
+            Some(&(start, _)) => {
+                let end = (0..=start.min(text.len()))
+                    .rev()
+                    .find(|&end| text.is_char_boundary(end))
+                    .unwrap_or(0);
+                text[..end].to_string()
+            }
+            None => text.to_string(),
+        }
+    }
+}
+
+impl fmt::Debug for GgufModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GgufModel")
+            .field("name", &self.name)
+            .field("max_tokens", &self.max_tokens)
+            .field("temperature", &self.temperature)
+            .field("top_p", &self.top_p)
+            .field("seed", &self.seed)
+            .finish()
+    }
+}
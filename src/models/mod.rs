@@ -7,7 +7,12 @@
 
 #[cfg(feature = "bedrock")]
 pub mod bedrock_converse;
+#[cfg(feature = "bedrock")]
+pub mod bedrock_embedding;
 pub mod embedding_model;
+#[cfg(feature = "gguf")]
+pub mod gguf;
+pub mod multimodal;
 #[cfg(feature = "ollama")]
 pub mod ollama;
 #[cfg(feature = "openai")]
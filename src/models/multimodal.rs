@@ -0,0 +1,501 @@
+//! Provider-neutral multimodal inputs for model nodes.
+//!
+//! `MultimodalInput` is an ordered list of text, image and document parts that can be
+//! passed to `BedrockConverse`, `OpenAIChatModel` and `Ollama`. Images and documents
+//! can be created from raw bytes, file paths or base64 strings. The format of each
+//! attachment is detected from its contents (falling back to the file extension
+//! where the contents are ambiguous) and its size is validated on construction.
+
+use std::path::Path;
+
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine;
+
+use crate::error::AnchorChainError;
+
+/// The maximum size in bytes of an image attachment.
+///
+/// This is the smallest limit of the supported providers (AWS Bedrock).
+pub const MAX_IMAGE_SIZE: usize = 3_750_000;
+
+/// The maximum size in bytes of a document attachment.
+///
+/// This is the smallest limit of the supported providers (AWS Bedrock).
+pub const MAX_DOCUMENT_SIZE: usize = 4_500_000;
+
+/// Image formats supported as model inputs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Gif,
+    Webp,
+}
+
+impl ImageFormat {
+    /// Detects the image format from the leading bytes of the image.
+    pub fn sniff(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(Self::Png)
+        } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(Self::Jpeg)
+        } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+            Some(Self::Gif)
+        } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+            Some(Self::Webp)
+        } else {
+            None
+        }
+    }
+
+    /// The MIME type of the image format.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::Gif => "image/gif",
+            Self::Webp => "image/webp",
+        }
+    }
+}
+
+/// Document formats supported as model inputs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DocumentFormat {
+    Pdf,
+    Csv,
+    Doc,
+    Docx,
+    Xls,
+    Xlsx,
+    Html,
+    Txt,
+    Md,
+}
+
+impl DocumentFormat {
+    /// Returns the document format for a file extension.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "pdf" => Some(Self::Pdf),
+            "csv" => Some(Self::Csv),
+            "doc" => Some(Self::Doc),
+            "docx" => Some(Self::Docx),
+            "xls" => Some(Self::Xls),
+            "xlsx" => Some(Self::Xlsx),
+            "html" | "htm" => Some(Self::Html),
+            "txt" => Some(Self::Txt),
+            "md" | "markdown" => Some(Self::Md),
+            _ => None,
+        }
+    }
+
+    /// Detects the document format from the contents of the document.
+    ///
+    /// Office formats share container formats with each other so they can only be
+    /// identified by their extension. Text documents are detected as HTML or plain text.
+    pub fn sniff(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(b"%PDF-") {
+            return Some(Self::Pdf);
+        }
+        let text = std::str::from_utf8(bytes).ok()?;
+        let start = text.trim_start().to_ascii_lowercase();
+        if start.starts_with("<!doctype html") || start.starts_with("<html") {
+            Some(Self::Html)
+        } else {
+            Some(Self::Txt)
+        }
+    }
+
+    /// The MIME type of the document format.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Pdf => "application/pdf",
+            Self::Csv => "text/csv",
+            Self::Doc => "application/msword",
+            Self::Docx => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+            Self::Xls => "application/vnd.ms-excel",
+            Self::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            Self::Html => "text/html",
+            Self::Txt => "text/plain",
+            Self::Md => "text/markdown",
+        }
+    }
+
+    /// Returns true if the document is plain text that can be inlined into a prompt.
+    pub fn is_text(&self) -> bool {
+        matches!(self, Self::Csv | Self::Html | Self::Txt | Self::Md)
+    }
+}
+
+/// An image passed to a model.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ImageInput {
+    format: ImageFormat,
+    bytes: Vec<u8>,
+}
+
+impl ImageInput {
+    /// Creates an image from raw bytes, detecting the format from its contents.
+    pub fn from_bytes(bytes: impl Into<Vec<u8>>) -> Result<Self, AnchorChainError> {
+        let bytes = bytes.into();
+        validate_size("image", bytes.len(), MAX_IMAGE_SIZE)?;
+        let format = ImageFormat::sniff(&bytes).ok_or_else(|| {
+            AnchorChainError::InvalidInputError(
+                "unsupported image format, expected PNG, JPEG, GIF or WebP".to_string(),
+            )
+        })?;
+        Ok(Self { format, bytes })
+    }
+
+    /// Reads an image from a file.
+    pub async fn from_path(path: impl AsRef<Path>) -> Result<Self, AnchorChainError> {
+        Self::from_bytes(read_file(path.as_ref()).await?)
+    }
+
+    /// Creates an image from a base64 string or a base64 `data:` URL.
+    pub fn from_base64(data: &str) -> Result<Self, AnchorChainError> {
+        Self::from_bytes(decode_base64(data)?)
+    }
+
+    /// The format of the image.
+    pub fn format(&self) -> ImageFormat {
+        self.format
+    }
+
+    /// The raw bytes of the image.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The image encoded as base64.
+    pub fn to_base64(&self) -> String {
+        BASE64_STANDARD.encode(&self.bytes)
+    }
+
+    /// The image encoded as a base64 `data:` URL.
+    pub fn to_data_url(&self) -> String {
+        format!(
+            "data:{};base64,{}",
+            self.format.mime_type(),
+            self.to_base64()
+        )
+    }
+}
+
+/// A document passed to a model such as a PDF, spreadsheet or text file.
+#[derive(Clone, PartialEq, Eq)]
+pub struct DocumentInput {
+    name: String,
+    format: DocumentFormat,
+    bytes: Vec<u8>,
+}
+
+impl DocumentInput {
+    /// Creates a document from raw bytes.
+    ///
+    /// The format is taken from the extension of `name` if present, otherwise it
+    /// is detected from the contents of the document.
+    pub fn from_bytes(
+        name: impl Into<String>,
+        bytes: impl Into<Vec<u8>>,
+    ) -> Result<Self, AnchorChainError> {
+        let name = name.into();
+        let bytes = bytes.into();
+        validate_size("document", bytes.len(), MAX_DOCUMENT_SIZE)?;
+        let format = Path::new(&name)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(DocumentFormat::from_extension)
+            .or_else(|| DocumentFormat::sniff(&bytes))
+            .ok_or_else(|| {
+                AnchorChainError::InvalidInputError(format!(
+                    "unable to determine the format of document {}",
+                    name
+                ))
+            })?;
+        Ok(Self {
+            name,
+            format,
+            bytes,
+        })
+    }
+
+    /// Reads a document from a file, using the file name as the document name.
+    pub async fn from_path(path: impl AsRef<Path>) -> Result<Self, AnchorChainError> {
+        let path = path.as_ref();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "document".to_string());
+        Self::from_bytes(name, read_file(path).await?)
+    }
+
+    /// Creates a document from a base64 string or a base64 `data:` URL.
+    pub fn from_base64(name: impl Into<String>, data: &str) -> Result<Self, AnchorChainError> {
+        Self::from_bytes(name, decode_base64(data)?)
+    }
+
+    /// The name of the document.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The format of the document.
+    pub fn format(&self) -> DocumentFormat {
+        self.format
+    }
+
+    /// The raw bytes of the document.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns the contents of text documents, or `None` for binary documents.
+    pub fn text(&self) -> Option<String> {
+        if self.format.is_text() {
+            Some(String::from_utf8_lossy(&self.bytes).to_string())
+        } else {
+            None
+        }
+    }
+
+    /// Formats a text document for inclusion in a prompt for models that don't
+    /// accept document attachments.
+    ///
+    /// Returns an error for binary documents.
+    pub fn to_inline_text(&self) -> Result<String, AnchorChainError> {
+        let text = self.text().ok_or_else(|| {
+            AnchorChainError::UnsupportedCapability(format!(
+                "{:?} documents can only be sent to models that accept document attachments",
+                self.format
+            ))
+        })?;
+        Ok(format!(
+            "<document name=\"{}\">\n{}\n</document>",
+            self.name, text
+        ))
+    }
+}
+
+/// A single part of a multimodal input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ContentPart {
+    Text(String),
+    Image(ImageInput),
+    Document(DocumentInput),
+}
+
+/// Input for model nodes made up of text, images and documents.
+///
+/// Parts are sent to the model in the order they are added.
+///
+/// # Example
+/// ```rust,no_run
+/// use anchor_chain::models::multimodal::{ImageInput, MultimodalInput};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let input = MultimodalInput::new()
+///         .with_image(ImageInput::from_path("cat.png").await.expect("Invalid image"))
+///         .with_text("What animal is in this picture?");
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MultimodalInput {
+    parts: Vec<ContentPart>,
+}
+
+impl MultimodalInput {
+    /// Creates an empty input.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a text part.
+    pub fn with_text(mut self, text: impl Into<String>) -> Self {
+        self.parts.push(ContentPart::Text(text.into()));
+        self
+    }
+
+    /// Appends an image part.
+    pub fn with_image(mut self, image: ImageInput) -> Self {
+        self.parts.push(ContentPart::Image(image));
+        self
+    }
+
+    /// Appends a document part.
+    pub fn with_document(mut self, document: DocumentInput) -> Self {
+        self.parts.push(ContentPart::Document(document));
+        self
+    }
+
+    /// The parts of the input in order.
+    pub fn parts(&self) -> &[ContentPart] {
+        &self.parts
+    }
+
+    /// Consumes the input, returning its parts.
+    pub fn into_parts(self) -> Vec<ContentPart> {
+        self.parts
+    }
+
+    /// Returns true if the input contains any images.
+    pub fn has_images(&self) -> bool {
+        self.parts
+            .iter()
+            .any(|part| matches!(part, ContentPart::Image(_)))
+    }
+
+    /// Returns true if the input contains any documents.
+    pub fn has_documents(&self) -> bool {
+        self.parts
+            .iter()
+            .any(|part| matches!(part, ContentPart::Document(_)))
+    }
+
+    /// Joins all text parts, inlining text documents, for text only models.
+    ///
+    /// Returns an error if the input contains images or binary documents.
+    pub fn to_text(&self) -> Result<String, AnchorChainError> {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                ContentPart::Text(text) => Ok(text.clone()),
+                ContentPart::Document(document) => document.to_inline_text(),
+                ContentPart::Image(_) => Err(AnchorChainError::UnsupportedCapability(
+                    "images can only be sent to models that accept image inputs".to_string(),
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|parts| parts.join("\n\n"))
+    }
+}
+
+impl From<String> for MultimodalInput {
+    fn from(text: String) -> Self {
+        Self::new().with_text(text)
+    }
+}
+
+impl From<&str> for MultimodalInput {
+    fn from(text: &str) -> Self {
+        Self::new().with_text(text)
+    }
+}
+
+impl From<ImageInput> for MultimodalInput {
+    fn from(image: ImageInput) -> Self {
+        Self::new().with_image(image)
+    }
+}
+
+impl From<DocumentInput> for MultimodalInput {
+    fn from(document: DocumentInput) -> Self {
+        Self::new().with_document(document)
+    }
+}
+
+impl std::fmt::Debug for ImageInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ImageInput")
+            .field("format", &self.format)
+            .field("size", &self.bytes.len())
+            .finish()
+    }
+}
+
+impl std::fmt::Debug for DocumentInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DocumentInput")
+            .field("name", &self.name)
+            .field("format", &self.format)
+            .field("size", &self.bytes.len())
+            .finish()
+    }
+}
+
+fn validate_size(kind: &str, size: usize, max: usize) -> Result<(), AnchorChainError> {
+    if size == 0 {
+        Err(AnchorChainError::InvalidInputError(format!(
+            "{kind} is empty"
+        )))
+    } else if size > max {
+        Err(AnchorChainError::InvalidInputError(format!(
+            "{kind} is {size} bytes which exceeds the maximum of {max} bytes"
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+async fn read_file(path: &Path) -> Result<Vec<u8>, AnchorChainError> {
+    tokio::fs::read(path).await.map_err(|e| {
+        AnchorChainError::InvalidInputError(format!("unable to read {}: {}", path.display(), e))
+    })
+}
+
+/// Decodes a base64 string, stripping the prefix of a `data:` URL if present.
+fn decode_base64(data: &str) -> Result<Vec<u8>, AnchorChainError> {
+    let data = match data.strip_prefix("data:") {
+        Some(url) => url
+            .split_once(";base64,")
+            .map(|(_, data)| data)
+            .ok_or_else(|| {
+                AnchorChainError::ParseError("data URL is not base64 encoded".to_string())
+            })?,
+        None => data,
+    };
+    BASE64_STANDARD
+        .decode(data.trim())
+        .map_err(|e| AnchorChainError::ParseError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG_HEADER: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    #[test]
+    fn test_image_format_sniffing() {
+        assert_eq!(ImageFormat::sniff(PNG_HEADER), Some(ImageFormat::Png));
+        assert_eq!(
+            ImageFormat::sniff(&[0xFF, 0xD8, 0xFF, 0xE0]),
+            Some(ImageFormat::Jpeg)
+        );
+        assert_eq!(
+            ImageFormat::sniff(b"RIFF\x24\0\0\0WEBPVP8 "),
+            Some(ImageFormat::Webp)
+        );
+        assert!(ImageInput::from_bytes(b"not an image".to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_image_from_data_url() {
+        let url = ImageInput::from_bytes(PNG_HEADER).unwrap().to_data_url();
+        assert!(url.starts_with("data:image/png;base64,"));
+        let image = ImageInput::from_base64(&url).unwrap();
+        assert_eq!(image.bytes(), PNG_HEADER);
+    }
+
+    #[test]
+    fn test_document_format_and_size() {
+        let pdf = DocumentInput::from_bytes("report", b"%PDF-1.7".to_vec()).unwrap();
+        assert_eq!(pdf.format(), DocumentFormat::Pdf);
+        assert!(pdf.to_inline_text().is_err());
+
+        let csv = DocumentInput::from_bytes("data.csv", b"a,b\n1,2".to_vec()).unwrap();
+        assert_eq!(csv.format(), DocumentFormat::Csv);
+        assert_eq!(
+            MultimodalInput::from("Summarize")
+                .with_document(csv)
+                .to_text()
+                .unwrap(),
+            "Summarize\n\n<document name=\"data.csv\">\na,b\n1,2\n</document>"
+        );
+
+        let too_large = vec![b'a'; MAX_DOCUMENT_SIZE + 1];
+        assert!(DocumentInput::from_bytes("large.txt", too_large).is_err());
+    }
+}
@@ -3,23 +3,31 @@
 //! Provides the functionality to construct and send requests to Ollama via the
 //! Ollama API. Ollama is a tool for managing and running local LLMs. For more
 //! information on how to install and run Ollama, see [https://ollama.com](https://ollama.com/).
-use crate::{AnchorChainError, Node};
-use anchor_chain_macros::Stateless;
+//! Multimodal models such as LLaVA accept images through `Ollama<MultimodalInput>`.
+use crate::models::multimodal::{ContentPart, MultimodalInput};
+use crate::usage::{record_usage, TokenUsage};
+use crate::{AnchorChainError, Node, Stateless};
 use async_trait::async_trait;
 use reqwest;
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 use std::io::{BufRead, BufReader};
+use std::marker::PhantomData;
+use std::time::Instant;
 
 /// Struct for interfacing with Ollama models via the Ollama API.
-#[derive(Debug, Clone, Stateless)]
-pub struct Ollama {
+///
+/// The input type defaults to `String`; use `with_input` to accept
+/// `MultimodalInput` or any other type convertible into it.
+#[derive(Debug, Clone)]
+pub struct Ollama<I = String> {
     /// The model tag of the installed Ollama model to use.
     model: String,
     /// The base URL of the Ollama API.
     url: String,
     /// HTTP client for sending requests to the Ollama API.
     client: reqwest::Client,
+    _input: PhantomData<I>,
 }
 
 impl Ollama {
@@ -36,6 +44,7 @@ impl Ollama {
             model,
             url: format!("http://{}:{}/api/generate", host, port),
             client,
+            _input: PhantomData,
         }
     }
 
@@ -44,6 +53,16 @@ impl Ollama {
     pub fn new_with_defaults(model: &str) -> Self {
         Self::new(model, "localhost", "11434")
     }
+
+    /// Changes the input type accepted by the node.
+    pub fn with_input<I: Into<MultimodalInput>>(self) -> Ollama<I> {
+        Ollama {
+            model: self.model,
+            url: self.url,
+            client: self.client,
+            _input: PhantomData,
+        }
+    }
 }
 
 /// Struct representing the response from the Ollama chat completion API
@@ -56,22 +75,48 @@ struct OllamaResponse {
     context: Option<Vec<u64>>,
     total_duration: Option<u64>,
     load_duration: Option<u64>,
+    prompt_eval_count: Option<u64>,
     prompt_eval_duration: Option<u64>,
     eval_count: Option<u64>,
     eval_duration: Option<u64>,
 }
 
+/// Splits a `MultimodalInput` into an Ollama prompt and base64 encoded images.
+///
+/// Text documents are inlined into the prompt. Returns an error if the input
+/// contains binary documents.
+fn prompt_and_images(input: MultimodalInput) -> Result<(String, Vec<String>), AnchorChainError> {
+    let mut prompt = Vec::new();
+    let mut images = Vec::new();
+    for part in input.into_parts() {
+        match part {
+            ContentPart::Text(text) => prompt.push(text),
+            ContentPart::Image(image) => images.push(image.to_base64()),
+            ContentPart::Document(document) => prompt.push(document.to_inline_text()?),
+        }
+    }
+    Ok((prompt.join("\n\n"), images))
+}
+
 #[async_trait]
-impl Node for Ollama {
-    type Input = String;
+impl<I> Node for Ollama<I>
+where
+    I: Into<MultimodalInput> + Send + Sync + Debug,
+{
+    type Input = I;
     type Output = String;
 
     /// Processes the input through the Ollama model, returning the model's output.
     async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
-        let body = serde_json::json!({
+        let (prompt, images) = prompt_and_images(input.into())?;
+        let mut body = serde_json::json!({
             "model": self.model,
-            "prompt": input,
+            "prompt": prompt,
         });
+        if !images.is_empty() {
+            body["images"] = serde_json::json!(images);
+        }
+        let start = Instant::now();
         let response = self
             .client
             .post(&self.url)
@@ -84,14 +129,24 @@ impl Node for Ollama {
 
         let reader = BufReader::new(response_text.as_bytes());
         let mut output = String::new();
+        let mut usage = TokenUsage::default();
 
         for line in reader.lines() {
             let line = line.map_err(|e| AnchorChainError::ParseError(e.to_string()))?;
             let api_response: OllamaResponse =
                 serde_json::from_str(&line).map_err(AnchorChainError::from)?;
             output.push_str(&api_response.response);
+            if api_response.done {
+                usage = TokenUsage::new(
+                    api_response.prompt_eval_count.unwrap_or_default(),
+                    api_response.eval_count.unwrap_or_default(),
+                );
+            }
         }
+        record_usage("ollama", &self.model, usage, start.elapsed());
 
         Ok(output)
     }
 }
+
+impl<I> Stateless for Ollama<I> where I: Into<MultimodalInput> + Send + Sync + Debug {}
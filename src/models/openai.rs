@@ -1,15 +1,19 @@
 //! Module for integrating OpenAI models.
 //!
 //! Facilitates the construction and execution of requests to OpenAI models,
-//! leveraging the OpenAI API.
+//! leveraging the OpenAI API. `OpenAIChatModel<MultimodalInput>` sends images
+//! as base64 data URLs to vision capable models.
 
 use std::fmt;
+use std::time::Instant;
 
 use anchor_chain_macros::Stateless;
 use async_openai::types::{
-    ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
-    ChatCompletionRequestUserMessageContent, CreateChatCompletionRequestArgs,
-    CreateCompletionRequestArgs, CreateEmbeddingRequestArgs, Prompt,
+    ChatCompletionRequestMessageContentPart, ChatCompletionRequestMessageContentPartImageArgs,
+    ChatCompletionRequestMessageContentPartTextArgs, ChatCompletionRequestSystemMessageArgs,
+    ChatCompletionRequestUserMessageArgs, ChatCompletionRequestUserMessageContent, CompletionUsage,
+    CreateChatCompletionRequestArgs, CreateCompletionRequestArgs, CreateEmbeddingRequestArgs,
+    ImageUrlArgs, Prompt,
 };
 use async_trait::async_trait;
 #[cfg(feature = "tracing")]
@@ -17,7 +21,19 @@ use tracing::instrument;
 
 use crate::error::AnchorChainError;
 use crate::models::embedding_model::EmbeddingModel;
+use crate::models::multimodal::{ContentPart, MultimodalInput};
 use crate::node::Node;
+use crate::usage::{record_usage, TokenUsage};
+
+/// The provider name used when recording usage of OpenAI models.
+const PROVIDER: &str = "openai";
+
+/// Converts OpenAI completion usage into `TokenUsage`.
+fn token_usage(usage: Option<&CompletionUsage>) -> TokenUsage {
+    usage.map_or_else(TokenUsage::default, |usage| {
+        TokenUsage::new(usage.prompt_tokens.into(), usage.completion_tokens.into())
+    })
+}
 
 /// OpenAI model types supported by the `OpenAI` node
 #[derive(Debug, Stateless, Clone)]
@@ -135,7 +151,7 @@ impl<T> OpenAIChatModel<T> {
     /// gpt-4-0125-preview
     /// gpt-3.5-turbo-0613
     /// gpt-3.5-turbo-16k-0613
-    async fn new(system_prompt: String, model: String) -> Self {
+    pub async fn new(system_prompt: String, model: String) -> Self {
         let config = async_openai::config::OpenAIConfig::new();
         let client = async_openai::Client::with_config(config);
         OpenAIChatModel {
@@ -160,22 +176,12 @@ impl<T> OpenAIChatModel<T> {
             _phantom: std::marker::PhantomData,
         }
     }
-}
-
-#[async_trait]
-impl<T> Node for OpenAIChatModel<T>
-where
-    T: Into<ChatCompletionRequestUserMessageContent> + fmt::Debug + Send + Sync,
-{
-    type Input = T;
-    type Output = String;
 
-    /// Sends the input to the OpenAI API and processes the response.
-    ///
-    /// Constructs a request based on the input and the system prompt, then parses
-    /// the model's response to extract and return final output.
-    #[cfg_attr(feature = "tracing", instrument(skip(self), fields(model = self.model.as_str(), system_prompt = self.system_prompt.as_str())))]
-    async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+    /// Sends the user message to the OpenAI API and returns the response content.
+    async fn complete(
+        &self,
+        input: ChatCompletionRequestUserMessageContent,
+    ) -> Result<String, AnchorChainError> {
         let system_prompt = ChatCompletionRequestSystemMessageArgs::default()
             .content(self.system_prompt.clone())
             .build()?
@@ -192,7 +198,14 @@ where
             .messages([system_prompt, input])
             .build()?;
 
+        let start = Instant::now();
         let response = self.client.chat().create(request).await?;
+        record_usage(
+            PROVIDER,
+            &self.model,
+            token_usage(response.usage.as_ref()),
+            start.elapsed(),
+        );
         if response.choices.is_empty() {
             return Err(AnchorChainError::EmptyResponseError);
         }
@@ -210,9 +223,77 @@ where
     }
 }
 
+#[async_trait]
+impl<T> Node for OpenAIChatModel<T>
+where
+    T: Into<ChatCompletionRequestUserMessageContent> + fmt::Debug + Send + Sync,
+{
+    type Input = T;
+    type Output = String;
+
+    /// Sends the input to the OpenAI API and processes the response.
+    ///
+    /// Constructs a request based on the input and the system prompt, then parses
+    /// the model's response to extract and return final output.
+    #[cfg_attr(feature = "tracing", instrument(skip(self), fields(model = self.model.as_str(), system_prompt = self.system_prompt.as_str())))]
+    async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+        self.complete(input.into()).await
+    }
+}
+
+#[async_trait]
+impl Node for OpenAIChatModel<MultimodalInput> {
+    type Input = MultimodalInput;
+    type Output = String;
+
+    /// Sends the multimodal input to the OpenAI API and processes the response.
+    ///
+    /// Images are sent as base64 data URLs and text documents are inlined into
+    /// the message. Returns an error if the input contains binary documents.
+    #[cfg_attr(feature = "tracing", instrument(skip(self), fields(model = self.model.as_str(), system_prompt = self.system_prompt.as_str())))]
+    async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+        self.complete(user_message_content(input)?).await
+    }
+}
+
+/// Converts a `MultimodalInput` into OpenAI chat message content parts.
+fn user_message_content(
+    input: MultimodalInput,
+) -> Result<ChatCompletionRequestUserMessageContent, AnchorChainError> {
+    let parts = input
+        .into_parts()
+        .into_iter()
+        .map(|part| {
+            let part = match part {
+                ContentPart::Text(text) => {
+                    ChatCompletionRequestMessageContentPartTextArgs::default()
+                        .text(text)
+                        .build()?
+                        .into()
+                }
+                ContentPart::Image(image) => {
+                    ChatCompletionRequestMessageContentPartImageArgs::default()
+                        .image_url(ImageUrlArgs::default().url(image.to_data_url()).build()?)
+                        .build()?
+                        .into()
+                }
+                ContentPart::Document(document) => {
+                    ChatCompletionRequestMessageContentPartTextArgs::default()
+                        .text(document.to_inline_text()?)
+                        .build()?
+                        .into()
+                }
+            };
+            Ok::<ChatCompletionRequestMessageContentPart, AnchorChainError>(part)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(ChatCompletionRequestUserMessageContent::Array(parts))
+}
+
 impl<T> fmt::Debug for OpenAIChatModel<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("OpenAI")
+            .field("model", &self.model)
             .field("system_prompt", &self.system_prompt)
             .finish()
     }
@@ -289,7 +370,14 @@ where
             .max_tokens(512u16)
             .build()?;
 
+        let start = Instant::now();
         let response = self.client.completions().create(request).await?;
+        record_usage(
+            PROVIDER,
+            &self.model,
+            token_usage(response.usage.as_ref()),
+            start.elapsed(),
+        );
 
         let content = response
             .choices
@@ -372,7 +460,14 @@ impl Node for OpenAIEmbeddingModel {
             .input(input)
             .build()?;
 
+        let start = Instant::now();
         let response = self.client.embeddings().create(request).await?;
+        record_usage(
+            PROVIDER,
+            &self.model,
+            TokenUsage::new(response.usage.prompt_tokens.into(), 0),
+            start.elapsed(),
+        );
 
         Ok(response
             .data
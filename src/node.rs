@@ -11,8 +11,9 @@ use async_trait::async_trait;
 #[cfg(feature = "tracing")]
 use tracing::instrument;
 
+use crate::chain_state::ChainState;
+use crate::describe::NodeDescription;
 use crate::error::AnchorChainError;
-use crate::state_manager::StateManager;
 
 /// Represents a node that can process an input to produce an output.
 ///
@@ -30,13 +31,32 @@ pub trait Node: std::fmt::Debug {
     /// chained together the output type of one node must match the input of
     /// the next node in the chain.
     async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError>;
+
+    /// Returns a description of the node's structure, including any inner nodes.
+    ///
+    /// Nodes containing other nodes override this to describe them.
+    fn describe(&self) -> NodeDescription {
+        NodeDescription::of::<Self>()
+    }
 }
 
 pub trait Stateless: Node {}
 
+/// A node that keeps state in the `ChainState` shared by the stateful nodes of
+/// its chain.
+///
+/// Each node takes the typed state it needs from the `ChainState`, so nodes with
+/// different state types can be linked into the same chain. The state is passed
+/// with each call rather than stored on the node, so a chain can process many
+/// inputs concurrently.
 #[async_trait]
-pub trait Stateful<K, V>: Node {
-    async fn set_state(&mut self, state: StateManager<K, V>);
+pub trait Stateful: Node {
+    /// Processes the input using the state of the chain.
+    async fn process_with_state(
+        &self,
+        input: Self::Input,
+        state: &ChainState,
+    ) -> Result<Self::Output, AnchorChainError>;
 }
 
 /// A no-op node that passes input through unchanged.
@@ -0,0 +1,332 @@
+//! Human approval of intermediate outputs.
+//!
+//! `ApprovalNode` pauses a run to ask a person whether a proposed output, such as
+//! a drafted email or the arguments of a tool call with side effects, should be
+//! used. The request is sent through an `ApprovalChannel` and the run resumes
+//! with the proposed output if it is approved, an edited output, or
+//! `AnchorChainError::ApprovalRejected` if it is rejected. A timeout applies a
+//! default decision when nobody responds in time.
+//!
+//! `ApprovalQueue` is a channel delivering requests to a reviewer task, which
+//! answers each `PendingApproval`. Other channels, such as chat integrations or
+//! web UIs, implement `ApprovalChannel`.
+//!
+//! # Example
+//! ```rust
+//! use anchor_chain::nodes::approval::{ApprovalNode, ApprovalQueue};
+//! use anchor_chain::{ChainBuilder, NoOpNode};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let (queue, mut pending) = ApprovalQueue::<String>::new();
+//!     let chain = ChainBuilder::new()
+//!         .link(NoOpNode::<String>::new())
+//!         .link(ApprovalNode::new("send_email", queue))
+//!         .build();
+//!
+//!     tokio::spawn(async move {
+//!         while let Some(approval) = pending.recv().await {
+//!             let edited = approval.request().proposed.replace("Hi", "Hello");
+//!             approval.edit(edited);
+//!         }
+//!     });
+//!
+//!     let output = chain.process("Hi Ada".to_string()).await.unwrap();
+//!     assert_eq!(output, "Hello Ada");
+//! }
+//! ```
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::sync::{mpsc, oneshot};
+#[cfg(feature = "tracing")]
+use tracing::instrument;
+
+use crate::context::RunContext;
+use crate::error::AnchorChainError;
+use crate::node::{Node, Stateless};
+
+/// Counter making the IDs of approval requests unique within the process.
+static NEXT_REQUEST: AtomicU64 = AtomicU64::new(1);
+
+/// A request for a person to review a proposed output.
+#[derive(Clone, Debug)]
+pub struct ApprovalRequest<T> {
+    /// The unique ID of the request.
+    pub id: String,
+    /// The name of the node requesting approval.
+    pub node: String,
+    /// The ID of the run, if set on the run's `RunContext`.
+    pub run_id: Option<String>,
+    /// The session of the run.
+    pub session_id: String,
+    /// The user the run is for, if set on the run's `RunContext`.
+    pub user_id: Option<String>,
+    /// The output awaiting approval.
+    pub proposed: T,
+}
+
+/// A reviewer's decision on a proposed output.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ApprovalDecision<T> {
+    /// Use the proposed output.
+    Approve,
+    /// Use the given output instead of the proposed output.
+    Edit(T),
+    /// Stop the run with the given reason.
+    Reject(String),
+}
+
+/// Delivers approval requests to reviewers and waits for their decisions.
+#[async_trait]
+pub trait ApprovalChannel<T>: fmt::Debug + Send + Sync {
+    /// Sends the request and waits for the reviewer's decision.
+    async fn request(
+        &self,
+        request: ApprovalRequest<T>,
+    ) -> Result<ApprovalDecision<T>, AnchorChainError>;
+}
+
+/// Node pausing the run until a reviewer approves, edits or rejects its input.
+///
+/// Without a timeout the node waits until a decision arrives or the run is
+/// cancelled or reaches its deadline.
+pub struct ApprovalNode<T> {
+    name: String,
+    channel: Arc<dyn ApprovalChannel<T>>,
+    timeout: Option<(Duration, ApprovalDecision<T>)>,
+}
+
+impl<T> ApprovalNode<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    /// Creates a node named `name` sending its requests through the channel.
+    pub fn new(name: impl Into<String>, channel: impl ApprovalChannel<T> + 'static) -> Self {
+        ApprovalNode {
+            name: name.into(),
+            channel: Arc::new(channel),
+            timeout: None,
+        }
+    }
+
+    /// Applies `default` if no decision arrives within `timeout`.
+    pub fn with_timeout(mut self, timeout: Duration, default: ApprovalDecision<T>) -> Self {
+        self.timeout = Some((timeout, default));
+        self
+    }
+}
+
+impl<T> fmt::Debug for ApprovalNode<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApprovalNode")
+            .field("name", &self.name)
+            .field("channel", &self.channel)
+            .field(
+                "timeout",
+                &self.timeout.as_ref().map(|(timeout, _)| timeout),
+            )
+            .finish()
+    }
+}
+
+#[async_trait]
+impl<T> Node for ApprovalNode<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    type Input = T;
+    type Output = T;
+
+    /// Requests approval of the input, returning the approved or edited output.
+    #[cfg_attr(feature = "tracing", instrument(skip(self, input), fields(name = %self.name)))]
+    async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+        let context = RunContext::current();
+        let request = ApprovalRequest {
+            id: format!(
+                "{}-{}",
+                self.name,
+                NEXT_REQUEST.fetch_add(1, Ordering::Relaxed)
+            ),
+            node: self.name.clone(),
+            run_id: context.run_id().map(String::from),
+            session_id: context.session_id().to_string(),
+            user_id: context.user_id().map(String::from),
+            proposed: input.clone(),
+        };
+
+        let decision = match &self.timeout {
+            Some((timeout, default)) => {
+                match tokio::time::timeout(*timeout, self.channel.request(request)).await {
+                    Ok(decision) => decision?,
+                    Err(_) => default.clone(),
+                }
+            }
+            None => self.channel.request(request).await?,
+        };
+        match decision {
+            ApprovalDecision::Approve => Ok(input),
+            ApprovalDecision::Edit(output) => Ok(output),
+            ApprovalDecision::Reject(reason) => Err(AnchorChainError::ApprovalRejected(reason)),
+        }
+    }
+}
+
+impl<T> Stateless for ApprovalNode<T> where T: Clone + Send + Sync + 'static {}
+
+/// An approval request awaiting a decision from a reviewer.
+///
+/// Dropping a pending approval without deciding fails the request with
+/// `AnchorChainError::ApprovalChannelError`.
+#[derive(Debug)]
+pub struct PendingApproval<T> {
+    request: ApprovalRequest<T>,
+    responder: oneshot::Sender<ApprovalDecision<T>>,
+}
+
+impl<T> PendingApproval<T> {
+    /// Returns the request to review.
+    pub fn request(&self) -> &ApprovalRequest<T> {
+        &self.request
+    }
+
+    /// Sends the decision to the waiting node.
+    pub fn decide(self, decision: ApprovalDecision<T>) {
+        // The node may have timed out or been cancelled, leaving nobody to notify.
+        let _ = self.responder.send(decision);
+    }
+
+    /// Approves the proposed output.
+    pub fn approve(self) {
+        self.decide(ApprovalDecision::Approve);
+    }
+
+    /// Replaces the proposed output.
+    pub fn edit(self, output: T) {
+        self.decide(ApprovalDecision::Edit(output));
+    }
+
+    /// Rejects the proposed output, stopping the run.
+    pub fn reject(self, reason: impl Into<String>) {
+        self.decide(ApprovalDecision::Reject(reason.into()));
+    }
+}
+
+/// An `ApprovalChannel` delivering requests to a `PendingApprovals` receiver.
+pub struct ApprovalQueue<T> {
+    sender: mpsc::UnboundedSender<PendingApproval<T>>,
+}
+
+impl<T> ApprovalQueue<T> {
+    /// Creates a queue along with the receiver reviewers take requests from.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new() -> (Self, PendingApprovals<T>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (ApprovalQueue { sender }, PendingApprovals { receiver })
+    }
+}
+
+impl<T> Clone for ApprovalQueue<T> {
+    fn clone(&self) -> Self {
+        ApprovalQueue {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for ApprovalQueue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApprovalQueue").finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<T> ApprovalChannel<T> for ApprovalQueue<T>
+where
+    T: Send + Sync,
+{
+    async fn request(
+        &self,
+        request: ApprovalRequest<T>,
+    ) -> Result<ApprovalDecision<T>, AnchorChainError> {
+        let (responder, response) = oneshot::channel();
+        self.sender
+            .send(PendingApproval { request, responder })
+            .map_err(|_| {
+                AnchorChainError::ApprovalChannelError(
+                    "no reviewer is receiving requests".to_string(),
+                )
+            })?;
+        response.await.map_err(|_| {
+            AnchorChainError::ApprovalChannelError("request dropped without a decision".to_string())
+        })
+    }
+}
+
+/// Receives the requests sent through an `ApprovalQueue`.
+#[derive(Debug)]
+pub struct PendingApprovals<T> {
+    receiver: mpsc::UnboundedReceiver<PendingApproval<T>>,
+}
+
+impl<T> PendingApprovals<T> {
+    /// Waits for the next request, returning `None` once every `ApprovalQueue`
+    /// has been dropped.
+    pub async fn recv(&mut self) -> Option<PendingApproval<T>> {
+        self.receiver.recv().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::ChainBuilder;
+    use crate::node::NoOpNode;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_approval_decisions_and_timeouts() {
+        let (queue, mut pending) = ApprovalQueue::<String>::new();
+        let chain = ChainBuilder::new()
+            .link(NoOpNode::new())
+            .link(ApprovalNode::new("email", queue).with_timeout(
+                Duration::from_secs(60),
+                ApprovalDecision::Reject("timed out".to_string()),
+            ))
+            .build();
+
+        let reviewer = tokio::spawn(async move {
+            let approval = pending.recv().await.unwrap();
+            assert_eq!(approval.request().node, "email");
+            assert_eq!(approval.request().run_id.as_deref(), Some("run-1"));
+            approval.approve();
+
+            pending.recv().await.unwrap().edit("edited".to_string());
+            pending.recv().await.unwrap().reject("too rude");
+            let ignored = pending.recv().await.unwrap();
+            tokio::time::sleep(Duration::from_secs(120)).await;
+            drop(ignored);
+        });
+
+        let context = RunContext::new().with_run_id("run-1");
+        let output = chain.process_with_context(context, "draft".to_string());
+        assert_eq!(output.await.unwrap(), "draft");
+        assert_eq!(chain.process("draft".to_string()).await.unwrap(), "edited");
+        assert!(matches!(
+            chain.process("draft".to_string()).await,
+            Err(AnchorChainError::ApprovalRejected(reason)) if reason == "too rude"
+        ));
+        assert!(matches!(
+            chain.process("draft".to_string()).await,
+            Err(AnchorChainError::ApprovalRejected(reason)) if reason == "timed out"
+        ));
+        reviewer.await.unwrap();
+        assert!(matches!(
+            chain.process("draft".to_string()).await,
+            Err(AnchorChainError::ApprovalChannelError(_))
+        ));
+    }
+}
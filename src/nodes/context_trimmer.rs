@@ -0,0 +1,195 @@
+//! A node fitting prioritized context into a token budget.
+//!
+//! Prompts for retrieval augmented generation often include many retrieved
+//! documents. `ContextTrimmer` keeps the highest priority items that fit within a
+//! token budget, truncating the item that crosses the budget and dropping the rest,
+//! so the rendered prompt stays within the model's context window.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+#[cfg(feature = "tracing")]
+use tracing::instrument;
+
+use crate::error::AnchorChainError;
+use crate::node::{Node, Stateless};
+use crate::tokenizer::{context_window, tokenizer_for_model, Tokenizer};
+use crate::vector::document::Document;
+
+/// A piece of context with a priority.
+///
+/// Items with a higher priority are kept first when trimming context.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContextItem {
+    /// The text of the item.
+    pub text: String,
+    /// The priority of the item.
+    pub priority: i64,
+}
+
+impl ContextItem {
+    /// Creates a new item with the given priority.
+    pub fn new(text: impl Into<String>, priority: i64) -> Self {
+        ContextItem {
+            text: text.into(),
+            priority,
+        }
+    }
+
+    /// Creates items prioritized by their order, such as documents ordered by
+    /// relevance, with the first item having the highest priority.
+    pub fn ranked<T: Into<String>>(texts: impl IntoIterator<Item = T>) -> Vec<Self> {
+        texts
+            .into_iter()
+            .enumerate()
+            .map(|(rank, text)| ContextItem::new(text, -(rank as i64)))
+            .collect()
+    }
+}
+
+impl From<Document> for ContextItem {
+    /// Creates an item from the document text with a priority of 0.
+    fn from(document: Document) -> Self {
+        ContextItem::new(document.text, 0)
+    }
+}
+
+/// Node that joins the highest priority context items fitting in a token budget.
+///
+/// Items are added in order of priority, with ties kept in input order, until the
+/// next item doesn't fit. That item is truncated to the remaining budget when
+/// truncation is enabled and all lower priority items are dropped. The kept items
+/// are joined by the separator in their original order.
+///
+/// # Example
+/// ```rust
+/// use anchor_chain::nodes::context_trimmer::{ContextItem, ContextTrimmer};
+/// use anchor_chain::tokenizer::ApproximateTokenizer;
+/// use anchor_chain::Node;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let trimmer = ContextTrimmer::new(ApproximateTokenizer::default(), 4);
+///     let items = ContextItem::ranked(["most relevant", "less relevant"]);
+///     let context = trimmer.process(items).await.unwrap();
+///     assert_eq!(context, "most relevant");
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ContextTrimmer {
+    tokenizer: Arc<dyn Tokenizer>,
+    max_tokens: usize,
+    separator: String,
+    truncate: bool,
+}
+
+impl ContextTrimmer {
+    /// Creates a trimmer keeping at most `max_tokens` tokens of context.
+    pub fn new(tokenizer: impl Tokenizer + 'static, max_tokens: usize) -> Self {
+        Self::with_shared_tokenizer(Arc::new(tokenizer), max_tokens)
+    }
+
+    /// Creates a trimmer using a tokenizer shared with other nodes.
+    pub fn with_shared_tokenizer(tokenizer: Arc<dyn Tokenizer>, max_tokens: usize) -> Self {
+        ContextTrimmer {
+            tokenizer,
+            max_tokens,
+            separator: "\n\n".to_string(),
+            truncate: true,
+        }
+    }
+
+    /// Creates a trimmer for the model's context window, leaving `reserved` tokens
+    /// for the rest of the prompt and the model's response.
+    ///
+    /// Returns an error if the model's context window isn't known.
+    pub fn for_model(model: &str, reserved: usize) -> Result<Self, AnchorChainError> {
+        let window = context_window(model).ok_or_else(|| {
+            AnchorChainError::InvalidInputError(format!("unknown context window for {model}"))
+        })?;
+        Ok(Self::with_shared_tokenizer(
+            tokenizer_for_model(model),
+            window.saturating_sub(reserved),
+        ))
+    }
+
+    /// Sets the separator used to join items. Defaults to a blank line.
+    pub fn with_separator(mut self, separator: impl Into<String>) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    /// Sets whether the item crossing the budget is truncated or dropped.
+    /// Defaults to truncating.
+    pub fn with_truncation(mut self, truncate: bool) -> Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Returns the kept items, possibly truncated, in their original order.
+    fn trim(&self, items: Vec<ContextItem>) -> Vec<String> {
+        let separator_tokens = self.tokenizer.count_tokens(&self.separator);
+        let mut order = (0..items.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&index| std::cmp::Reverse(items[index].priority));
+
+        let mut kept: Vec<Option<String>> = vec![None; items.len()];
+        let mut remaining = self.max_tokens;
+        for (count, index) in order.into_iter().enumerate() {
+            let overhead = if count == 0 { 0 } else { separator_tokens };
+            let available = remaining.saturating_sub(overhead);
+            let text = &items[index].text;
+            let tokens = self.tokenizer.count_tokens(text);
+            if tokens <= available {
+                remaining = available - tokens;
+                kept[index] = Some(text.clone());
+                continue;
+            }
+            if self.truncate && available > 0 {
+                let truncated = self.tokenizer.truncate(text, available);
+                if !truncated.is_empty() {
+                    kept[index] = Some(truncated);
+                }
+            }
+            break;
+        }
+        kept.into_iter().flatten().collect()
+    }
+}
+
+#[async_trait]
+impl Node for ContextTrimmer {
+    type Input = Vec<ContextItem>;
+    type Output = String;
+
+    /// Joins the highest priority items that fit in the token budget.
+    #[cfg_attr(feature = "tracing", instrument(skip(self, input)))]
+    async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+        Ok(self.trim(input).join(&self.separator))
+    }
+}
+
+impl Stateless for ContextTrimmer {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::ApproximateTokenizer;
+
+    #[tokio::test]
+    async fn test_trimmer_keeps_highest_priority_items() {
+        // One token per character makes the budget easy to follow.
+        let trimmer = ContextTrimmer::new(ApproximateTokenizer::new(1.0), 12).with_separator("|");
+        let items = vec![
+            ContextItem::new("low", 0),
+            ContextItem::new("high", 2),
+            ContextItem::new("middle", 1),
+        ];
+        assert_eq!(trimmer.process(items.clone()).await.unwrap(), "high|middle");
+
+        let trimmer = ContextTrimmer::new(ApproximateTokenizer::new(1.0), 10).with_separator("|");
+        assert_eq!(trimmer.process(items.clone()).await.unwrap(), "high|middl");
+
+        let trimmer = trimmer.with_truncation(false);
+        assert_eq!(trimmer.process(items).await.unwrap(), "high");
+    }
+}
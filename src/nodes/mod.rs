@@ -4,5 +4,7 @@
 //! variety of contexts. Each node has a defined input and output type that is checked at compile
 //! time to ensure nodes are connected correctly.
 
+pub mod approval;
+pub mod context_trimmer;
 pub mod logger;
 pub mod prompt;
@@ -11,6 +11,7 @@ use std::collections::HashMap;
 
 use anchor_chain_macros::Stateless;
 use async_trait::async_trait;
+use serde::Serialize;
 use tera::{Context, Tera};
 #[cfg(feature = "tracing")]
 use tracing::instrument;
@@ -43,13 +44,24 @@ impl<'a> Prompt<'a> {
     /// let prompt = Prompt::new("Create a {{ language }} program that prints 'Hello, World!'");
     /// ```
     pub fn new(template: &str) -> Self {
+        Self::try_new(template).expect("Error creating template")
+    }
+
+    /// Creates a new `Prompt` processor, returning an error if the template is
+    /// invalid.
+    pub fn try_new(template: &str) -> Result<Self, AnchorChainError> {
         let mut tera = Tera::default();
-        tera.add_raw_template("prompt", template)
-            .expect("Error creating template");
-        Prompt {
+        tera.add_raw_template("prompt", template)?;
+        Ok(Prompt {
             tera,
             _marker: std::marker::PhantomData,
-        }
+        })
+    }
+
+    /// Renders the template with the variables of any serializable value.
+    pub(crate) fn render(&self, variables: &impl Serialize) -> Result<String, AnchorChainError> {
+        let context = Context::from_serialize(variables)?;
+        Ok(self.tera.render("prompt", &context)?)
     }
 }
 
@@ -64,7 +76,6 @@ impl<'a> Node for Prompt<'a> {
     /// Processes the input HashMap and returns the rendered template.
     #[cfg_attr(feature = "tracing", instrument)]
     async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
-        let context = Context::from_serialize(input)?;
-        Ok(self.tera.render("prompt", &context)?.to_string())
+        self.render(&input)
     }
 }
@@ -54,6 +54,8 @@ use std::fmt;
 #[cfg(feature = "tracing")]
 use tracing::{instrument, Instrument};
 
+use crate::context::RunContext;
+use crate::describe::{NodeDescription, NodeKind};
 use crate::error::AnchorChainError;
 use crate::node::Node;
 
@@ -141,6 +143,7 @@ where
     /// using the provided function to produce the final output.
     #[cfg_attr(feature = "tracing", instrument)]
     async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+        RunContext::check_current()?;
         let futures = self.nodes.iter().map(|node| {
             let input_clone = input.clone();
             async move { node.process(input_clone).await }
@@ -161,6 +164,13 @@ where
 
         combined_results.await
     }
+
+    /// Describes the node with each of its branches.
+    fn describe(&self) -> NodeDescription {
+        NodeDescription::of::<Self>()
+            .with_kind(NodeKind::Parallel)
+            .with_children(self.nodes.iter().map(|node| node.describe()).collect())
+    }
 }
 
 impl<I, O, C> fmt::Debug for ParallelNode<I, O, C>
@@ -1,71 +1,717 @@
+//! Shared state for stateful nodes, isolated per session.
+//!
+//! A `StateManager` keeps a separate map for each session so one chain can serve
+//! many users or conversations without their state mixing. The session of a run is
+//! taken from the `RunContext`, usually set with `Chain::process_with_session`; runs
+//! without a session use `DEFAULT_SESSION`.
+//!
+//! State is kept in memory and can be persisted to a `StateStore` with
+//! `StateManager::persist_to` so it survives restarts. Entries can be given a
+//! time-to-live after which they are evicted, and changes can be observed with
+//! `StateManager::subscribe`.
+
 use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
 use std::hash::Hash;
-use std::sync::Arc;
-use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::{RwLock, RwLockMappedWriteGuard, RwLockReadGuard, RwLockWriteGuard};
+use tokio::time::Instant;
+
+use crate::context::RunContext;
+use crate::error::AnchorChainError;
+use crate::state_store::StateStore;
+
+/// The session used by runs that don't set one.
+pub const DEFAULT_SESSION: &str = "default";
+
+/// Runs the future with `session_id` as the current session.
+///
+/// The rest of the current `RunContext` is kept.
+pub async fn with_session<F: Future>(session_id: impl Into<String>, future: F) -> F::Output {
+    RunContext::current()
+        .with_session(session_id)
+        .scope(future)
+        .await
+}
+
+/// Returns the session of the current run, or `DEFAULT_SESSION` if none is set.
+pub fn current_session() -> String {
+    RunContext::with_current(|context| context.session_id().to_string())
+        .unwrap_or_else(|| DEFAULT_SESSION.to_string())
+}
+
+type Sessions<K, V> = HashMap<String, HashMap<K, V>>;
+type Expiries<K> = HashMap<String, HashMap<K, Instant>>;
+
+/// The number of unreceived events kept for each subscription.
+const EVENT_CAPACITY: usize = 256;
+
+/// How often the background task evicts expired entries.
+const EVICTION_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A change to the state of a session.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StateEvent<K, V> {
+    /// The session that changed.
+    pub session: String,
+    /// The change made to the session.
+    pub change: StateChange<K, V>,
+}
+
+/// A change made to the state of a session.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StateChange<K, V> {
+    /// The entry was inserted or modified and now has the value.
+    Set { key: K, value: V },
+    /// The entry was removed.
+    Removed { key: K },
+    /// The entry was removed because its time-to-live elapsed.
+    Expired { key: K },
+    /// All entries of the session were removed.
+    Cleared,
+    /// The state of the session was replaced by an import or fork.
+    Replaced,
+}
+
+impl<K, V> StateChange<K, V> {
+    /// Returns the key of the changed entry, or `None` for changes to the whole session.
+    pub fn key(&self) -> Option<&K> {
+        match self {
+            StateChange::Set { key, .. }
+            | StateChange::Removed { key }
+            | StateChange::Expired { key } => Some(key),
+            StateChange::Cleared | StateChange::Replaced => None,
+        }
+    }
+}
+
+/// A stream of changes to a `StateManager`.
+///
+/// Changes to a whole session, such as clearing it, are received by every
+/// subscription to that session. If the subscriber falls more than 256 events
+/// behind, the oldest events are skipped.
+pub struct StateSubscription<K, V> {
+    receiver: broadcast::Receiver<StateEvent<K, V>>,
+    filter: Box<dyn Fn(&K) -> bool + Send + Sync>,
+    session: Option<String>,
+}
+
+impl<K: Clone, V: Clone> StateSubscription<K, V> {
+    /// Only receives changes to the session.
+    pub fn for_session(mut self, session_id: impl Into<String>) -> Self {
+        self.session = Some(session_id.into());
+        self
+    }
+
+    /// Waits for the next matching change, returning `None` once the
+    /// `StateManager` has been dropped.
+    pub async fn recv(&mut self) -> Option<StateEvent<K, V>> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) => {
+                    let session_matches = match &self.session {
+                        Some(session) => *session == event.session,
+                        None => true,
+                    };
+                    let key_matches = match event.change.key() {
+                        Some(key) => (self.filter)(key),
+                        None => true,
+                    };
+                    if session_matches && key_matches {
+                        return Some(event);
+                    }
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for StateSubscription<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StateSubscription")
+            .field("session", &self.session)
+            .finish()
+    }
+}
+
+/// The store a `StateManager` is persisted to along with the function encoding
+/// the state of a session, which captures the serde bounds of the keys and values.
+#[derive(Debug)]
+struct Persistence<K, V> {
+    store: Arc<dyn StateStore>,
+    encode: fn(&HashMap<K, V>) -> Result<String, AnchorChainError>,
+    /// The version given to the next snapshot, increasing in the order snapshots
+    /// are taken.
+    next_version: AtomicU64,
+    /// The version of the last snapshot written for each session. Locked while
+    /// writing so writes to a session are serialized.
+    saved: Mutex<HashMap<String, Arc<tokio::sync::Mutex<u64>>>>,
+}
+
+/// The state of a session encoded while holding the lock, written to the store
+/// once the lock is released. A `None` state deletes the session.
+struct Snapshot {
+    session: String,
+    version: u64,
+    state: Option<String>,
+}
 
+fn encode_state<K: Serialize, V: Serialize>(
+    state: &HashMap<K, V>,
+) -> Result<String, AnchorChainError> {
+    Ok(serde_json::to_string(&state.iter().collect::<Vec<_>>())?)
+}
+
+fn decode_state<K, V>(state: &str) -> Result<HashMap<K, V>, AnchorChainError>
+where
+    K: Eq + Hash + DeserializeOwned,
+    V: DeserializeOwned,
+{
+    Ok(serde_json::from_str::<Vec<(K, V)>>(state)?
+        .into_iter()
+        .collect())
+}
+
+/// Key-value state shared between the stateful nodes of a chain.
+///
+/// Reads and writes apply to the current session. Clones share the same state.
+/// Once persisted with `persist_to`, every change made through the methods of the
+/// `StateManager` is written to the store before the method returns. Changes are
+/// applied in memory and sent to subscribers before being written, so if writing
+/// fails the method returns an error but the change is kept; the store catches
+/// up with the next change to the session or a call to `save`.
+///
+/// Entries inserted with a time-to-live are never read once expired. They are
+/// removed when the state of their session is next read, or by a background task
+/// within a second. Time-to-lives aren't persisted, so entries restored from a
+/// store don't expire.
 #[derive(Debug, Clone)]
 pub struct StateManager<K, V> {
-    inner: Arc<RwLock<HashMap<K, V>>>,
+    inner: Arc<RwLock<Sessions<K, V>>>,
+    /// When entries with a time-to-live expire. Only modified while holding the
+    /// write lock of `inner`.
+    expiries: Arc<Mutex<Expiries<K>>>,
+    persistence: Arc<OnceLock<Persistence<K, V>>>,
+    /// Only held by `StateManager`s so subscriptions close once they are dropped.
+    events: Arc<broadcast::Sender<StateEvent<K, V>>>,
+    eviction: Arc<OnceLock<()>>,
+    /// Never written. Locked by `read` for sessions without state.
+    empty: Arc<RwLock<HashMap<K, V>>>,
 }
 
 impl<K: Eq + Hash + Clone, V: Clone> StateManager<K, V> {
     pub fn new() -> Self {
         Self {
             inner: Arc::new(RwLock::new(HashMap::new())),
+            expiries: Arc::new(Mutex::new(HashMap::new())),
+            persistence: Arc::new(OnceLock::new()),
+            events: Arc::new(broadcast::channel(EVENT_CAPACITY).0),
+            eviction: Arc::new(OnceLock::new()),
+            empty: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Returns a subscription to every change.
+    pub fn subscribe(&self) -> StateSubscription<K, V> {
+        self.subscribe_matching(|_| true)
+    }
+
+    /// Returns a subscription to changes to the entry with the key.
+    pub fn subscribe_key(&self, key: K) -> StateSubscription<K, V>
+    where
+        K: Send + Sync + 'static,
+    {
+        self.subscribe_matching(move |changed| *changed == key)
+    }
+
+    /// Returns a subscription to changes to entries whose key starts with the prefix.
+    pub fn subscribe_prefix(&self, prefix: impl Into<String>) -> StateSubscription<K, V>
+    where
+        K: AsRef<str>,
+    {
+        let prefix = prefix.into();
+        self.subscribe_matching(move |key| key.as_ref().starts_with(&prefix))
+    }
+
+    fn subscribe_matching(
+        &self,
+        filter: impl Fn(&K) -> bool + Send + Sync + 'static,
+    ) -> StateSubscription<K, V> {
+        StateSubscription {
+            receiver: self.events.subscribe(),
+            filter: Box::new(filter),
+            session: None,
+        }
+    }
+
+    /// Notifies subscribers of the change. Called while holding the write lock so
+    /// events are received in the order changes were made.
+    fn notify(&self, session: &str, change: StateChange<K, V>) {
+        // Sending only fails when there are no subscribers.
+        let _ = self.events.send(StateEvent {
+            session: session.to_string(),
+            change,
+        });
+    }
+
+    fn expiries(&self) -> std::sync::MutexGuard<'_, Expiries<K>> {
+        self.expiries.lock().expect("Expiry lock poisoned")
+    }
+
+    /// Returns true if the entry has a time-to-live that has elapsed.
+    fn is_expired(&self, session: &str, key: &K) -> bool {
+        self.expiries()
+            .get(session)
+            .and_then(|expiries| expiries.get(key))
+            .is_some_and(|expiry| *expiry <= Instant::now())
+    }
+
+    /// Sets when the entry expires, or removes its expiry if `None`.
+    fn set_expiry(&self, session: &str, key: &K, expiry: Option<Instant>) {
+        let mut expiries = self.expiries();
+        match expiry {
+            Some(expiry) => {
+                expiries
+                    .entry(session.to_string())
+                    .or_default()
+                    .insert(key.clone(), expiry);
+            }
+            None => {
+                if let Some(session_expiries) = expiries.get_mut(session) {
+                    session_expiries.remove(key);
+                }
+            }
+        }
+    }
+
+    /// Loads the sessions saved in the store and persists all further changes to it.
+    ///
+    /// Stored sessions replace in-memory sessions with the same ID. Returns an error
+    /// if the state is already persisted or the store can't be read.
+    pub async fn persist_to(&self, store: impl StateStore + 'static) -> Result<(), AnchorChainError>
+    where
+        K: Serialize + DeserializeOwned,
+        V: Serialize + DeserializeOwned,
+    {
+        let mut sessions = self.inner.write().await;
+        if self.persistence.get().is_some() {
+            return Err(AnchorChainError::StateStoreError(
+                "state is already persisted".to_string(),
+            ));
+        }
+        for (session, state) in store.load().await? {
+            sessions.insert(session, decode_state(&state)?);
         }
+        self.persistence
+            .set(Persistence {
+                store: Arc::new(store),
+                encode: encode_state::<K, V>,
+                next_version: AtomicU64::new(1),
+                saved: Mutex::new(HashMap::new()),
+            })
+            .map_err(|_| {
+                AnchorChainError::StateStoreError("state is already persisted".to_string())
+            })
+    }
+
+    /// Encodes the state of the session to be written once the lock is released,
+    /// returning `None` if the state isn't persisted.
+    fn snapshot(
+        &self,
+        sessions: &Sessions<K, V>,
+        session: &str,
+    ) -> Result<Option<Snapshot>, AnchorChainError> {
+        let Some(persistence) = self.persistence.get() else {
+            return Ok(None);
+        };
+        let state = match sessions.get(session) {
+            Some(state) => Some((persistence.encode)(state)?),
+            None => None,
+        };
+        Ok(Some(Snapshot {
+            session: session.to_string(),
+            version: persistence.next_version.fetch_add(1, Ordering::SeqCst),
+            state,
+        }))
+    }
+
+    /// Writes the snapshot to the store, deleting the session if it has no state.
+    ///
+    /// Writes to a session are serialized, and a snapshot older than the last one
+    /// written is skipped so the store never goes back to an earlier state.
+    async fn write_snapshot(&self, snapshot: Option<Snapshot>) -> Result<(), AnchorChainError> {
+        let (Some(snapshot), Some(persistence)) = (snapshot, self.persistence.get()) else {
+            return Ok(());
+        };
+        let saved = persistence
+            .saved
+            .lock()
+            .expect("Save lock poisoned")
+            .entry(snapshot.session.clone())
+            .or_default()
+            .clone();
+        let mut saved = saved.lock().await;
+        if *saved >= snapshot.version {
+            return Ok(());
+        }
+        match snapshot.state {
+            Some(state) => persistence.store.save(&snapshot.session, state).await?,
+            None => persistence.store.delete(&snapshot.session).await?,
+        }
+        *saved = snapshot.version;
+        Ok(())
+    }
+
+    /// Writes the state of the current session to the store.
+    ///
+    /// Changes made through the guard returned by `write` are only persisted by the
+    /// next change to the session or by calling `save`.
+    pub async fn save(&self) -> Result<(), AnchorChainError> {
+        let snapshot = self.snapshot(&*self.inner.read().await, &current_session())?;
+        self.write_snapshot(snapshot).await
+    }
+
+    /// Applies the change to the state of the current session, notifies subscribers
+    /// and persists it.
+    async fn update<T>(
+        &self,
+        change: impl FnOnce(&mut HashMap<K, V>, &str) -> (T, Option<StateChange<K, V>>),
+    ) -> Result<T, AnchorChainError> {
+        let session = current_session();
+        let (result, snapshot) = {
+            let mut sessions = self.inner.write().await;
+            let (result, event) = change(sessions.entry(session.clone()).or_default(), &session);
+            if let Some(event) = event {
+                self.notify(&session, event);
+            }
+            (result, self.snapshot(&sessions, &session)?)
+        };
+        self.write_snapshot(snapshot).await?;
+        Ok(result)
     }
 
     pub async fn get(&self, key: &K) -> Option<V> {
-        self.inner.read().await.get(key).cloned()
+        let session = current_session();
+        let sessions = self.inner.read().await;
+        if self.is_expired(&session, key) {
+            return None;
+        }
+        sessions
+            .get(&session)
+            .and_then(|state| state.get(key))
+            .cloned()
+    }
+
+    pub async fn insert(&self, key: K, value: V) -> Result<(), AnchorChainError> {
+        self.update(|state, session| {
+            self.set_expiry(session, &key, None);
+            state.insert(key.clone(), value.clone());
+            ((), Some(StateChange::Set { key, value }))
+        })
+        .await
     }
 
-    pub async fn insert(&self, key: K, value: V) {
-        self.inner.write().await.insert(key, value);
+    /// Inserts the entry, removing it once `ttl` has elapsed.
+    pub async fn insert_with_ttl(
+        &self,
+        key: K,
+        value: V,
+        ttl: Duration,
+    ) -> Result<(), AnchorChainError>
+    where
+        K: Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    {
+        self.start_eviction();
+        self.update(|state, session| {
+            self.set_expiry(session, &key, Some(Instant::now() + ttl));
+            state.insert(key.clone(), value.clone());
+            ((), Some(StateChange::Set { key, value }))
+        })
+        .await
+    }
+
+    /// Sets the time-to-live of an existing entry, returning false if there is no
+    /// entry with the key.
+    pub async fn set_ttl(&self, key: &K, ttl: Duration) -> bool
+    where
+        K: Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    {
+        self.start_eviction();
+        let session = current_session();
+        let sessions = self.inner.write().await;
+        let exists = sessions
+            .get(&session)
+            .is_some_and(|state| state.contains_key(key));
+        if exists {
+            self.set_expiry(&session, key, Some(Instant::now() + ttl));
+        }
+        exists
+    }
+
+    pub async fn remove(&self, key: &K) -> Result<Option<V>, AnchorChainError> {
+        self.update(|state, session| {
+            self.set_expiry(session, key, None);
+            let removed = state.remove(key);
+            let event = removed
+                .is_some()
+                .then(|| StateChange::Removed { key: key.clone() });
+            (removed, event)
+        })
+        .await
+    }
+
+    /// Removes all expired entries, returning the number removed.
+    ///
+    /// This is called periodically by a background task once an entry has been
+    /// given a time-to-live.
+    pub async fn evict_expired(&self) -> Result<usize, AnchorChainError> {
+        self.evict(None).await
+    }
+
+    /// Removes the expired entries of the session, or of every session if `None`,
+    /// returning the number removed.
+    async fn evict(&self, only: Option<&str>) -> Result<usize, AnchorChainError> {
+        let now = Instant::now();
+        let mut sessions = self.inner.write().await;
+        let expired = {
+            let mut expiries = self.expiries();
+            let mut expired = Vec::new();
+            for (session, session_expiries) in expiries.iter_mut() {
+                if only.is_some_and(|only| only != session) {
+                    continue;
+                }
+                session_expiries.retain(|key, expiry| {
+                    if *expiry <= now {
+                        expired.push((session.clone(), key.clone()));
+                        false
+                    } else {
+                        true
+                    }
+                });
+            }
+            expiries.retain(|_, session_expiries| !session_expiries.is_empty());
+            expired
+        };
+
+        let mut changed = Vec::new();
+        for (session, key) in &expired {
+            if let Some(state) = sessions.get_mut(session) {
+                if state.remove(key).is_some() {
+                    self.notify(session, StateChange::Expired { key: key.clone() });
+                    if !changed.contains(session) {
+                        changed.push(session.clone());
+                    }
+                }
+            }
+        }
+        let snapshots = changed
+            .iter()
+            .map(|session| self.snapshot(&sessions, session))
+            .collect::<Result<Vec<_>, _>>()?;
+        drop(sessions);
+        for snapshot in snapshots {
+            self.write_snapshot(snapshot).await?;
+        }
+        Ok(expired.len())
     }
 
-    pub async fn remove(&self, key: &K) -> Option<V> {
-        self.inner.write().await.remove(key)
+    /// Starts the background task evicting expired entries if it isn't running.
+    ///
+    /// The task stops once every clone of the `StateManager` has been dropped.
+    fn start_eviction(&self)
+    where
+        K: Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    {
+        self.eviction.get_or_init(|| {
+            let inner = Arc::downgrade(&self.inner);
+            let expiries = self.expiries.clone();
+            let persistence = self.persistence.clone();
+            let events = Arc::downgrade(&self.events);
+            let eviction = self.eviction.clone();
+            let empty = self.empty.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(EVICTION_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    let (Some(inner), Some(events)) = (inner.upgrade(), events.upgrade()) else {
+                        break;
+                    };
+                    let state = StateManager {
+                        inner,
+                        expiries: expiries.clone(),
+                        persistence: persistence.clone(),
+                        events,
+                        eviction: eviction.clone(),
+                        empty: empty.clone(),
+                    };
+                    #[allow(unused_variables)]
+                    if let Err(error) = state.evict_expired().await {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!("Error persisting evicted state: {error}");
+                    }
+                }
+            });
+        });
     }
 
     pub async fn len(&self) -> usize {
-        self.inner.read().await.len()
+        self.read().await.len()
     }
 
     pub async fn is_empty(&self) -> bool {
-        self.inner.read().await.is_empty()
+        self.read().await.is_empty()
     }
 
-    pub async fn clear(&self) {
-        self.inner.write().await.clear()
+    pub async fn clear(&self) -> Result<(), AnchorChainError> {
+        self.update(|state, session| {
+            self.expiries().remove(session);
+            state.clear();
+            ((), Some(StateChange::Cleared))
+        })
+        .await
     }
 
     pub async fn contains_key(&self, key: &K) -> bool {
-        self.inner.read().await.contains_key(key)
+        let session = current_session();
+        self.read().await.contains_key(key) && !self.is_expired(&session, key)
     }
 
     pub async fn keys(&self) -> Vec<K> {
-        self.inner.read().await.keys().cloned().collect()
+        self.read().await.keys().cloned().collect()
     }
 
     pub async fn values(&self) -> Vec<V> {
-        self.inner.read().await.values().cloned().collect()
+        self.read().await.values().cloned().collect()
+    }
+
+    /// Locks the state of the current session for reading. A session without
+    /// state reads as empty.
+    ///
+    /// Expired entries of the session are evicted first, so they aren't read even
+    /// if the background task hasn't removed them yet.
+    pub async fn read(&self) -> RwLockReadGuard<'_, HashMap<K, V>> {
+        let session = current_session();
+        let has_expired = self.expiries().get(&session).is_some_and(|expiries| {
+            let now = Instant::now();
+            expiries.values().any(|expiry| *expiry <= now)
+        });
+        #[allow(unused_variables)]
+        if has_expired {
+            if let Err(error) = self.evict(Some(&session)).await {
+                #[cfg(feature = "tracing")]
+                tracing::warn!("Error persisting evicted state: {error}");
+            }
+        }
+        match RwLockReadGuard::try_map(self.inner.read().await, |sessions| sessions.get(&session)) {
+            Ok(state) => state,
+            Err(sessions) => {
+                drop(sessions);
+                self.empty.read().await
+            }
+        }
     }
 
-    pub async fn read(&self) -> RwLockReadGuard<HashMap<K, V>> {
-        self.inner.read().await
+    /// Locks the state of the current session for writing, creating the session
+    /// if it doesn't exist.
+    pub async fn write(&self) -> RwLockMappedWriteGuard<'_, HashMap<K, V>> {
+        let session = current_session();
+        RwLockWriteGuard::map(self.inner.write().await, |sessions| {
+            sessions.entry(session).or_default()
+        })
     }
 
-    pub async fn write(&self) -> RwLockWriteGuard<HashMap<K, V>> {
-        self.inner.write().await
+    /// Returns the IDs of all sessions with state.
+    pub async fn sessions(&self) -> Vec<String> {
+        self.inner.read().await.keys().cloned().collect()
+    }
+
+    /// Copies the state of the `from` session into the `to` session, replacing
+    /// any existing state. Returns false if the `from` session doesn't exist.
+    pub async fn fork_session(
+        &self,
+        from: &str,
+        to: impl Into<String>,
+    ) -> Result<bool, AnchorChainError> {
+        let mut sessions = self.inner.write().await;
+        let Some(state) = sessions.get(from).cloned() else {
+            return Ok(false);
+        };
+        let to = to.into();
+        sessions.insert(to.clone(), state);
+        {
+            let mut expiries = self.expiries();
+            match expiries.get(from).cloned() {
+                Some(from_expiries) => expiries.insert(to.clone(), from_expiries),
+                None => expiries.remove(&to),
+            };
+        }
+        self.notify(&to, StateChange::Replaced);
+        let snapshot = self.snapshot(&sessions, &to)?;
+        drop(sessions);
+        self.write_snapshot(snapshot).await?;
+        Ok(true)
+    }
+
+    /// Returns a copy of the state of the session.
+    pub async fn export_session(&self, session_id: &str) -> Option<HashMap<K, V>> {
+        self.inner.read().await.get(session_id).cloned()
+    }
+
+    /// Sets the state of the session, replacing any existing state.
+    pub async fn import_session(
+        &self,
+        session_id: impl Into<String>,
+        state: HashMap<K, V>,
+    ) -> Result<(), AnchorChainError> {
+        let session_id = session_id.into();
+        let mut sessions = self.inner.write().await;
+        sessions.insert(session_id.clone(), state);
+        self.expiries().remove(&session_id);
+        self.notify(&session_id, StateChange::Replaced);
+        let snapshot = self.snapshot(&sessions, &session_id)?;
+        drop(sessions);
+        self.write_snapshot(snapshot).await
+    }
+
+    /// Deletes the session and its state, returning the deleted state.
+    pub async fn delete_session(
+        &self,
+        session_id: &str,
+    ) -> Result<Option<HashMap<K, V>>, AnchorChainError> {
+        let mut sessions = self.inner.write().await;
+        let state = sessions.remove(session_id);
+        self.expiries().remove(session_id);
+        if state.is_some() {
+            self.notify(session_id, StateChange::Cleared);
+        }
+        let snapshot = self.snapshot(&sessions, session_id)?;
+        drop(sessions);
+        self.write_snapshot(snapshot).await?;
+        Ok(state)
     }
 }
 
 impl<K: Eq + Hash + Clone, V: Clone> StateManager<K, Vec<V>> {
-    pub async fn push(&self, key: K, value: V) {
-        let mut map = self.inner.write().await;
-        map.entry(key)
-            .and_modify(|vec| vec.push(value.clone()))
-            .or_insert(vec![value]);
+    pub async fn push(&self, key: K, value: V) -> Result<(), AnchorChainError> {
+        self.update(|state, _| {
+            let values = state.entry(key.clone()).or_default();
+            values.push(value);
+            let value = values.clone();
+            ((), Some(StateChange::Set { key, value }))
+        })
+        .await
     }
 }
 
@@ -74,3 +720,209 @@ impl<K: Eq + Hash + Clone, V: Clone> Default for StateManager<K, V> {
         StateManager::<K, V>::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state_store::JsonFileStore;
+
+    #[tokio::test]
+    async fn test_sessions_are_isolated() {
+        let state = StateManager::<String, Vec<String>>::new();
+        let history = "history".to_string();
+        with_session("alice", state.push(history.clone(), "hi".to_string()))
+            .await
+            .unwrap();
+        with_session("bob", state.push(history.clone(), "hello".to_string()))
+            .await
+            .unwrap();
+        assert!(state.get(&history).await.is_none());
+
+        assert!(state.fork_session("alice", "alice-2").await.unwrap());
+        with_session("alice-2", state.push(history.clone(), "again".to_string()))
+            .await
+            .unwrap();
+
+        let alice = state.export_session("alice").await.unwrap();
+        assert_eq!(alice["history"], vec!["hi"]);
+        let fork = with_session("alice-2", state.get(&history)).await;
+        assert_eq!(fork.unwrap(), vec!["hi", "again"]);
+
+        assert!(state.delete_session("bob").await.unwrap().is_some());
+        assert!(state.is_empty().await);
+        let mut sessions = state.sessions().await;
+        sessions.sort();
+        assert_eq!(sessions, vec!["alice", "alice-2"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_subscriptions_and_ttl() {
+        let state = StateManager::<String, String>::new();
+        let mut all = state.subscribe().for_session(DEFAULT_SESSION);
+        let mut user = state.subscribe_prefix("user.");
+
+        state
+            .insert("user.name".to_string(), "Ada".to_string())
+            .await
+            .unwrap();
+        state
+            .insert_with_ttl(
+                "token".to_string(),
+                "secret".to_string(),
+                Duration::from_secs(5),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            user.recv().await.unwrap().change,
+            StateChange::Set {
+                key: "user.name".to_string(),
+                value: "Ada".to_string()
+            }
+        );
+        all.recv().await.unwrap();
+        assert_eq!(
+            all.recv().await.unwrap().change.key(),
+            Some(&"token".to_string())
+        );
+
+        tokio::time::sleep(Duration::from_secs(6)).await;
+        assert_eq!(state.get(&"token".to_string()).await, None);
+        assert_eq!(
+            all.recv().await.unwrap().change,
+            StateChange::Expired {
+                key: "token".to_string()
+            }
+        );
+        assert_eq!(state.keys().await, vec!["user.name".to_string()]);
+    }
+
+    /// Store whose saves wait until released.
+    #[derive(Debug, Default)]
+    struct BlockingStore {
+        started: tokio::sync::Notify,
+        release: tokio::sync::Notify,
+    }
+
+    #[async_trait::async_trait]
+    impl StateStore for Arc<BlockingStore> {
+        async fn load(&self) -> Result<HashMap<String, String>, AnchorChainError> {
+            Ok(HashMap::new())
+        }
+
+        async fn save(&self, _session: &str, _state: String) -> Result<(), AnchorChainError> {
+            self.started.notify_one();
+            self.release.notified().await;
+            Ok(())
+        }
+
+        async fn delete(&self, _session: &str) -> Result<(), AnchorChainError> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_saves_do_not_block_other_sessions() {
+        let store = Arc::new(BlockingStore::default());
+        let state = StateManager::<String, String>::new();
+        state.persist_to(store.clone()).await.unwrap();
+        let key = "name".to_string();
+
+        let insert = tokio::spawn({
+            let state = state.clone();
+            let key = key.clone();
+            with_session("alice", async move {
+                state.insert(key, "Ada".to_string()).await
+            })
+        });
+        store.started.notified().await;
+        assert_eq!(with_session("bob", state.get(&key)).await, None);
+        assert_eq!(
+            with_session("alice", state.get(&key)).await.as_deref(),
+            Some("Ada")
+        );
+
+        store.release.notify_one();
+        insert.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_expired_entries_are_not_read_before_eviction() {
+        let state = StateManager::<String, String>::new();
+        let mut events = state.subscribe();
+        state
+            .insert("name".to_string(), "Ada".to_string())
+            .await
+            .unwrap();
+        state
+            .insert_with_ttl(
+                "token".to_string(),
+                "secret".to_string(),
+                Duration::from_millis(10),
+            )
+            .await
+            .unwrap();
+        events.recv().await.unwrap();
+        events.recv().await.unwrap();
+
+        // Blocks the thread so the background task can't evict the entry first.
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(state.keys().await, vec!["name".to_string()]);
+        assert_eq!(state.values().await, vec!["Ada".to_string()]);
+        assert_eq!(state.len().await, 1);
+        assert_eq!(
+            events.recv().await.unwrap().change,
+            StateChange::Expired {
+                key: "token".to_string()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscriptions_close_when_manager_is_dropped() {
+        let state = StateManager::<String, String>::new();
+        let mut events = state.subscribe();
+        state
+            .insert_with_ttl(
+                "token".to_string(),
+                "secret".to_string(),
+                Duration::from_secs(60),
+            )
+            .await
+            .unwrap();
+        events.recv().await.unwrap();
+
+        drop(state);
+        let closed = tokio::time::timeout(Duration::from_millis(100), events.recv()).await;
+        assert_eq!(closed, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_persisted_state_survives_restart() {
+        let dir = std::env::temp_dir().join(format!("anchor-chain-state-{}", std::process::id()));
+        let history = "history".to_string();
+
+        let state = StateManager::<String, Vec<String>>::new();
+        state
+            .persist_to(JsonFileStore::new(&dir).await.unwrap())
+            .await
+            .unwrap();
+        with_session("alice", state.push(history.clone(), "hi".to_string()))
+            .await
+            .unwrap();
+        with_session("bob", state.push(history.clone(), "hello".to_string()))
+            .await
+            .unwrap();
+        state.delete_session("bob").await.unwrap();
+
+        let restored = StateManager::<String, Vec<String>>::new();
+        restored
+            .persist_to(JsonFileStore::new(&dir).await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(restored.sessions().await, vec!["alice"]);
+        let alice = with_session("alice", restored.get(&history)).await;
+        assert_eq!(alice.unwrap(), vec!["hi"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
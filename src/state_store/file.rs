@@ -0,0 +1,100 @@
+//! JSON file state store.
+
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::error::AnchorChainError;
+use crate::state_store::StateStore;
+
+#[derive(Serialize, Deserialize)]
+struct SessionFile {
+    session: String,
+    state: Value,
+}
+
+/// A store keeping the state of each session in a JSON file in a directory.
+///
+/// Files are replaced atomically so a crash while saving leaves the previous state
+/// intact.
+#[derive(Clone, Debug)]
+pub struct JsonFileStore {
+    dir: PathBuf,
+}
+
+impl JsonFileStore {
+    /// Creates a store keeping sessions in `dir`, which is created if it doesn't exist.
+    pub async fn new(dir: impl AsRef<Path>) -> Result<Self, AnchorChainError> {
+        let dir = dir.as_ref().to_path_buf();
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .map_err(|e| io_error(&dir, e))?;
+        Ok(JsonFileStore { dir })
+    }
+
+    /// Returns the path of the file storing the session.
+    fn path(&self, session: &str) -> PathBuf {
+        let hash = Sha256::digest(session.as_bytes());
+        self.dir.join(format!("{}.json", hex::encode(hash)))
+    }
+}
+
+fn io_error(path: &Path, error: std::io::Error) -> AnchorChainError {
+    AnchorChainError::StateStoreError(format!("{}: {error}", path.display()))
+}
+
+#[async_trait]
+impl StateStore for JsonFileStore {
+    async fn load(&self) -> Result<HashMap<String, String>, AnchorChainError> {
+        let mut sessions = HashMap::new();
+        let mut entries = tokio::fs::read_dir(&self.dir)
+            .await
+            .map_err(|e| io_error(&self.dir, e))?;
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| io_error(&self.dir, e))?
+        {
+            let path = entry.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("json") {
+                continue;
+            }
+            let contents = tokio::fs::read_to_string(&path)
+                .await
+                .map_err(|e| io_error(&path, e))?;
+            let file = serde_json::from_str::<SessionFile>(&contents).map_err(|e| {
+                AnchorChainError::StateStoreError(format!("{}: {e}", path.display()))
+            })?;
+            sessions.insert(file.session, file.state.to_string());
+        }
+        Ok(sessions)
+    }
+
+    async fn save(&self, session: &str, state: String) -> Result<(), AnchorChainError> {
+        let path = self.path(session);
+        let file = SessionFile {
+            session: session.to_string(),
+            state: serde_json::from_str(&state)?,
+        };
+        let temp = path.with_extension("json.tmp");
+        tokio::fs::write(&temp, serde_json::to_string(&file)?)
+            .await
+            .map_err(|e| io_error(&temp, e))?;
+        tokio::fs::rename(&temp, &path)
+            .await
+            .map_err(|e| io_error(&path, e))
+    }
+
+    async fn delete(&self, session: &str) -> Result<(), AnchorChainError> {
+        let path = self.path(session);
+        match tokio::fs::remove_file(&path).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(io_error(&path, e)),
+            _ => Ok(()),
+        }
+    }
+}
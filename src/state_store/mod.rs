@@ -0,0 +1,37 @@
+//! Persistent storage for `StateManager` sessions.
+//!
+//! A `StateStore` saves the serialized state of each session so conversation
+//! history and agent state survive restarts. `JsonFileStore` keeps each session in
+//! a JSON file and `SqliteStore` keeps sessions in a SQLite database when the
+//! `sqlite` feature is enabled. Keys and values are serialized with serde, so state
+//! can only be persisted when they implement `Serialize` and `Deserialize`.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use async_trait::async_trait;
+
+use crate::error::AnchorChainError;
+
+pub mod file;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+pub use file::JsonFileStore;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+
+/// Storage for the serialized state of `StateManager` sessions.
+///
+/// Each session is stored as a single JSON string that is replaced on every save.
+#[async_trait]
+pub trait StateStore: fmt::Debug + Send + Sync {
+    /// Returns the state of every stored session by session ID.
+    async fn load(&self) -> Result<HashMap<String, String>, AnchorChainError>;
+
+    /// Stores the state of the session, replacing any existing state.
+    async fn save(&self, session: &str, state: String) -> Result<(), AnchorChainError>;
+
+    /// Removes the session.
+    async fn delete(&self, session: &str) -> Result<(), AnchorChainError>;
+}
@@ -0,0 +1,135 @@
+//! SQLite state store.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use rusqlite::{params, Connection};
+
+use crate::error::AnchorChainError;
+use crate::state_store::StateStore;
+
+/// A store keeping the state of each session in a row of a SQLite table.
+///
+/// Clones share the same connection. Queries run on a blocking thread.
+#[derive(Clone, Debug)]
+pub struct SqliteStore {
+    connection: Arc<Mutex<Connection>>,
+    table: String,
+}
+
+impl SqliteStore {
+    /// Opens or creates the database at `path`, storing sessions in the
+    /// `anchor_chain_state` table.
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self, AnchorChainError> {
+        let path = path.as_ref().to_path_buf();
+        let connection = tokio::task::spawn_blocking(move || Connection::open(path))
+            .await
+            .map_err(|e| AnchorChainError::StateStoreError(e.to_string()))?
+            .map_err(sqlite_error)?;
+        Self::with_connection(connection, "anchor_chain_state").await
+    }
+
+    /// Uses an open connection, storing sessions in `table`, which is created if it
+    /// doesn't exist.
+    pub async fn with_connection(
+        connection: Connection,
+        table: &str,
+    ) -> Result<Self, AnchorChainError> {
+        if !table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(AnchorChainError::InvalidInputError(format!(
+                "invalid table name: {table}"
+            )));
+        }
+        let store = SqliteStore {
+            connection: Arc::new(Mutex::new(connection)),
+            table: table.to_string(),
+        };
+        let sql = format!(
+            "CREATE TABLE IF NOT EXISTS {} (session TEXT PRIMARY KEY, state TEXT NOT NULL)",
+            store.table
+        );
+        store
+            .run(move |connection| connection.execute(&sql, []).map(|_| ()))
+            .await?;
+        Ok(store)
+    }
+
+    /// Runs the query on a blocking thread.
+    async fn run<T: Send + 'static>(
+        &self,
+        query: impl FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+    ) -> Result<T, AnchorChainError> {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || {
+            let connection = connection.lock().expect("SQLite connection lock poisoned");
+            query(&connection)
+        })
+        .await
+        .map_err(|e| AnchorChainError::StateStoreError(e.to_string()))?
+        .map_err(sqlite_error)
+    }
+}
+
+fn sqlite_error(error: rusqlite::Error) -> AnchorChainError {
+    AnchorChainError::StateStoreError(error.to_string())
+}
+
+#[async_trait]
+impl StateStore for SqliteStore {
+    async fn load(&self) -> Result<HashMap<String, String>, AnchorChainError> {
+        let sql = format!("SELECT session, state FROM {}", self.table);
+        self.run(move |connection| {
+            connection
+                .prepare(&sql)?
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect()
+        })
+        .await
+    }
+
+    async fn save(&self, session: &str, state: String) -> Result<(), AnchorChainError> {
+        let sql = format!(
+            "INSERT INTO {} (session, state) VALUES (?1, ?2) \
+             ON CONFLICT(session) DO UPDATE SET state = excluded.state",
+            self.table
+        );
+        let session = session.to_string();
+        self.run(move |connection| {
+            connection
+                .execute(&sql, params![session, state])
+                .map(|_| ())
+        })
+        .await
+    }
+
+    async fn delete(&self, session: &str) -> Result<(), AnchorChainError> {
+        let sql = format!("DELETE FROM {} WHERE session = ?1", self.table);
+        let session = session.to_string();
+        self.run(move |connection| connection.execute(&sql, params![session]).map(|_| ()))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sqlite_store_round_trip() {
+        let store = SqliteStore::with_connection(Connection::open_in_memory().unwrap(), "state")
+            .await
+            .unwrap();
+        store.save("alice", "[1]".to_string()).await.unwrap();
+        store.save("alice", "[2]".to_string()).await.unwrap();
+        store.save("bob", "[3]".to_string()).await.unwrap();
+        store.delete("bob").await.unwrap();
+
+        let sessions = store.load().await.unwrap();
+        assert_eq!(
+            sessions,
+            HashMap::from([("alice".to_string(), "[2]".to_string())])
+        );
+    }
+}
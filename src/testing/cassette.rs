@@ -0,0 +1,404 @@
+//! Record and replay node interactions using on-disk cassettes.
+//!
+//! A `Cassette` stores the requests and responses of wrapped nodes in a JSON file.
+//! In record mode the wrapped nodes are called and every interaction is written to
+//! the cassette. In replay mode the recorded responses are returned without calling
+//! the wrapped nodes, allowing entire chains including model and vector store calls
+//! to be tested deterministically without network access.
+
+use std::fmt;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::Mutex;
+#[cfg(feature = "tracing")]
+use tracing::instrument;
+
+use crate::describe::NodeDescription;
+use crate::error::AnchorChainError;
+use crate::models::embedding_model::EmbeddingModel;
+use crate::node::{Node, Stateless};
+
+/// Environment variable used by `CassetteMode::from_env`.
+pub const CASSETTE_MODE_ENV: &str = "ANCHOR_CHAIN_CASSETTE";
+
+/// Whether a cassette records new interactions or replays existing ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// Call the wrapped nodes and record their interactions, replacing the
+    /// existing cassette.
+    Record,
+    /// Return recorded responses without calling the wrapped nodes.
+    Replay,
+}
+
+impl CassetteMode {
+    /// Returns `Record` if `ANCHOR_CHAIN_CASSETTE` is set to `record`, otherwise `Replay`.
+    pub fn from_env() -> Self {
+        match std::env::var(CASSETTE_MODE_ENV) {
+            Ok(mode) if mode.eq_ignore_ascii_case("record") => CassetteMode::Record,
+            _ => CassetteMode::Replay,
+        }
+    }
+}
+
+/// A single recorded request and response.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Interaction {
+    node: String,
+    request: Value,
+    response: Value,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CassetteFile {
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Debug, Default)]
+struct CassetteState {
+    interactions: Vec<Interaction>,
+    /// The number of times each interaction has been replayed.
+    replayed: Vec<usize>,
+}
+
+/// A JSON file of recorded node interactions.
+///
+/// Interactions are keyed by the name of the wrapping node and the JSON form of
+/// the request, so the same request made by different nodes is recorded
+/// separately. Repeated identical requests are replayed in the order they were
+/// recorded, and replaying fails once they are exhausted unless
+/// `with_repeat_last` is set. Clones share the same cassette.
+///
+/// # Example
+/// ```rust,no_run
+/// use anchor_chain::testing::cassette::{Cassette, CassetteMode};
+/// use anchor_chain::{ChainBuilder, OpenAIModel, Prompt};
+/// use std::collections::HashMap;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let cassette = Cassette::open("tests/cassettes/hello.json", CassetteMode::from_env())
+///         .await
+///         .expect("Error opening cassette");
+///
+///     let chain = ChainBuilder::new()
+///         .link(Prompt::new("{{ input }}"))
+///         .link(cassette.wrap(
+///             "gpt",
+///             OpenAIModel::new_gpt3_5_turbo("You are a helpful assistant").await,
+///         ))
+///         .build();
+///
+///     let output = chain
+///         .process(HashMap::from([("input", "Say hello")]))
+///         .await
+///         .expect("Error processing chain");
+///     println!("{}", output);
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Cassette {
+    path: PathBuf,
+    mode: CassetteMode,
+    repeat_last: bool,
+    state: Arc<Mutex<CassetteState>>,
+}
+
+impl Cassette {
+    /// Opens the cassette at `path` in the given mode.
+    ///
+    /// In replay mode the cassette must already exist. In record mode any existing
+    /// interactions are discarded.
+    pub async fn open(
+        path: impl AsRef<Path>,
+        mode: CassetteMode,
+    ) -> Result<Self, AnchorChainError> {
+        let path = path.as_ref().to_path_buf();
+        let interactions = match mode {
+            CassetteMode::Record => Vec::new(),
+            CassetteMode::Replay => {
+                let contents = tokio::fs::read_to_string(&path).await.map_err(|e| {
+                    AnchorChainError::CassetteError(format!(
+                        "unable to read {}: {e}; record it by setting {CASSETTE_MODE_ENV}=record",
+                        path.display()
+                    ))
+                })?;
+                serde_json::from_str::<CassetteFile>(&contents)?.interactions
+            }
+        };
+        let replayed = vec![0; interactions.len()];
+        Ok(Cassette {
+            path,
+            mode,
+            repeat_last: false,
+            state: Arc::new(Mutex::new(CassetteState {
+                interactions,
+                replayed,
+            })),
+        })
+    }
+
+    /// Sets whether the last recorded response for a request is replayed again
+    /// once every recorded response has been used, instead of failing.
+    ///
+    /// Applies to nodes wrapped after it is set.
+    pub fn with_repeat_last(mut self, repeat_last: bool) -> Self {
+        self.repeat_last = repeat_last;
+        self
+    }
+
+    /// Returns the mode of the cassette.
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    /// Wraps a node so its interactions are recorded to or replayed from this cassette.
+    ///
+    /// The name identifies the node in the cassette and must be unique within it.
+    pub fn wrap<N>(&self, name: impl Into<String>, node: N) -> Recorded<N> {
+        Recorded {
+            name: name.into(),
+            node,
+            cassette: self.clone(),
+        }
+    }
+
+    /// Records the result of `call` or replays the response recorded for the request.
+    async fn interact<Req, Res, F, Fut>(
+        &self,
+        node: &str,
+        request: &Req,
+        call: F,
+    ) -> Result<Res, AnchorChainError>
+    where
+        Req: Serialize + ?Sized,
+        Res: Serialize + DeserializeOwned,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Res, AnchorChainError>>,
+    {
+        let request = serde_json::to_value(request)?;
+        match self.mode {
+            CassetteMode::Record => {
+                let response = call().await?;
+                self.record(node, request, serde_json::to_value(&response)?)
+                    .await?;
+                Ok(response)
+            }
+            CassetteMode::Replay => Ok(serde_json::from_value(self.replay(node, &request).await?)?),
+        }
+    }
+
+    /// Adds an interaction and writes the cassette to disk.
+    async fn record(
+        &self,
+        node: &str,
+        request: Value,
+        response: Value,
+    ) -> Result<(), AnchorChainError> {
+        let mut state = self.state.lock().await;
+        state.interactions.push(Interaction {
+            node: node.to_string(),
+            request,
+            response,
+        });
+        state.replayed.push(0);
+
+        let file = CassetteFile {
+            interactions: state.interactions.clone(),
+        };
+        let contents = serde_json::to_string_pretty(&file)?;
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| self.io_error(e))?;
+        }
+        tokio::fs::write(&self.path, contents)
+            .await
+            .map_err(|e| self.io_error(e))
+    }
+
+    /// Returns the next recorded response for the request.
+    async fn replay(&self, node: &str, request: &Value) -> Result<Value, AnchorChainError> {
+        let mut state = self.state.lock().await;
+        let matches = state
+            .interactions
+            .iter()
+            .enumerate()
+            .filter(|(_, interaction)| interaction.node == node && &interaction.request == request)
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+
+        let unreplayed = matches.iter().find(|index| state.replayed[**index] == 0);
+        let index = match (unreplayed, matches.last()) {
+            (Some(index), _) => *index,
+            (None, Some(index)) if self.repeat_last => *index,
+            (None, Some(_)) => {
+                return Err(AnchorChainError::CassetteError(format!(
+                    "all {} interactions recorded in {} for node {node:?} with request \
+                     {request} have been replayed; re-record it by setting \
+                     {CASSETTE_MODE_ENV}=record",
+                    matches.len(),
+                    self.path.display()
+                )))
+            }
+            (None, None) => {
+                return Err(AnchorChainError::CassetteError(format!(
+                    "no interaction recorded in {} for node {node:?} with request {request}; \
+                     re-record it by setting {CASSETTE_MODE_ENV}=record",
+                    self.path.display()
+                )))
+            }
+        };
+        state.replayed[index] += 1;
+        Ok(state.interactions[index].response.clone())
+    }
+
+    fn io_error(&self, error: std::io::Error) -> AnchorChainError {
+        AnchorChainError::CassetteError(format!("unable to write {}: {error}", self.path.display()))
+    }
+}
+
+impl fmt::Debug for Cassette {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cassette")
+            .field("path", &self.path)
+            .field("mode", &self.mode)
+            .field("repeat_last", &self.repeat_last)
+            .finish()
+    }
+}
+
+/// Node that records or replays the interactions of the wrapped node.
+///
+/// Created with `Cassette::wrap`. The node's input must be serializable and its
+/// output must be serializable and deserializable. When the wrapped node is an
+/// `EmbeddingModel` its `embed` calls are recorded as well.
+#[derive(Debug)]
+pub struct Recorded<N> {
+    name: String,
+    node: N,
+    cassette: Cassette,
+}
+
+#[async_trait]
+impl<N> Node for Recorded<N>
+where
+    N: Node + Send + Sync,
+    N::Input: Serialize + Send,
+    N::Output: Serialize + DeserializeOwned + Send,
+{
+    type Input = N::Input;
+    type Output = N::Output;
+
+    /// Returns the recorded response for the input or records the wrapped node's output.
+    #[cfg_attr(feature = "tracing", instrument(skip(self, input), fields(node = self.name.as_str())))]
+    async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+        let request = serde_json::to_value(&input)?;
+        self.cassette
+            .interact(&self.name, &request, || self.node.process(input))
+            .await
+    }
+
+    /// Describes the node as a wrapper around its inner node.
+    fn describe(&self) -> NodeDescription {
+        NodeDescription::of::<Self>().wrapping(self.node.describe())
+    }
+}
+
+impl<N> Stateless for Recorded<N>
+where
+    N: Node + Stateless + Send + Sync,
+    N::Input: Serialize + Send,
+    N::Output: Serialize + DeserializeOwned + Send,
+{
+}
+
+#[async_trait]
+impl<N> EmbeddingModel for Recorded<N>
+where
+    N: EmbeddingModel + Send + Sync,
+{
+    async fn embed(&self, input: String) -> Result<Vec<f32>, AnchorChainError> {
+        let name = format!("{}::embed", self.name);
+        self.cassette
+            .interact(&name, &input, || self.node.embed(input.clone()))
+            .await
+    }
+
+    fn dimensions(&self) -> usize {
+        self.node.dimensions()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MockModel, MockResponse};
+    use crate::{ChainBuilder, Prompt};
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let path =
+            std::env::temp_dir().join(format!("anchor-chain-cassette-{}.json", std::process::id()));
+
+        let cassette = Cassette::open(&path, CassetteMode::Record).await.unwrap();
+        let model = MockModel::new("mock")
+            .then(MockResponse::text("first"))
+            .then(MockResponse::text("second"));
+        let chain = ChainBuilder::new()
+            .link(Prompt::new("{{ input }}"))
+            .link(cassette.wrap("model", model))
+            .build();
+        for _ in 0..2 {
+            chain
+                .process(HashMap::from([("input", "hello")]))
+                .await
+                .unwrap();
+        }
+
+        let cassette = Cassette::open(&path, CassetteMode::Replay).await.unwrap();
+        let model = MockModel::new("mock");
+        let chain = ChainBuilder::new()
+            .link(Prompt::new("{{ input }}"))
+            .link(cassette.wrap("model", model.clone()))
+            .build();
+        let mut outputs = Vec::new();
+        for _ in 0..2 {
+            outputs.push(
+                chain
+                    .process(HashMap::from([("input", "hello")]))
+                    .await
+                    .unwrap(),
+            );
+        }
+        assert_eq!(outputs, vec!["first", "second"]);
+        assert!(model.requests().is_empty());
+        assert!(matches!(
+            chain.process(HashMap::from([("input", "hello")])).await,
+            Err(AnchorChainError::CassetteError(_))
+        ));
+        assert!(matches!(
+            chain.process(HashMap::from([("input", "goodbye")])).await,
+            Err(AnchorChainError::CassetteError(_))
+        ));
+
+        let cassette = Cassette::open(&path, CassetteMode::Replay)
+            .await
+            .unwrap()
+            .with_repeat_last(true);
+        let repeating = cassette.wrap("model", MockModel::new("mock"));
+        let prompt = "hello".to_string();
+        for expected in ["first", "second", "second"] {
+            assert_eq!(repeating.process(prompt.clone()).await.unwrap(), expected);
+        }
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,446 @@
+//! A scriptable model node for offline tests.
+//!
+//! `MockModel` returns responses from a script instead of calling a provider. Responses
+//! can be matched to requests by pattern or returned in order, and can contain text,
+//! tool-use turns or errors. Every request received is recorded for later assertions.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::sync::RwLock;
+#[cfg(feature = "tracing")]
+use tracing::instrument;
+
+use crate::error::AnchorChainError;
+use crate::node::{Node, Stateless};
+use crate::usage::{record_usage, TokenUsage};
+use crate::ToolRegistry;
+
+/// A tool call requested by a `MockResponse`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockToolCall {
+    /// The name of the tool to call.
+    pub name: String,
+    /// The input passed to the tool.
+    pub input: Value,
+}
+
+/// A scripted response returned by `MockModel`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MockResponse {
+    /// A text response.
+    Text(String),
+    /// A turn requesting one or more tool calls with optional accompanying text.
+    ToolUse {
+        text: Option<String>,
+        calls: Vec<MockToolCall>,
+    },
+    /// A failed model call returned as `AnchorChainError::ModelError`.
+    Error(String),
+}
+
+impl MockResponse {
+    /// Creates a text response.
+    pub fn text(text: impl Into<String>) -> Self {
+        MockResponse::Text(text.into())
+    }
+
+    /// Creates a response calling a single tool.
+    pub fn tool_use(name: impl Into<String>, input: Value) -> Self {
+        MockResponse::ToolUse {
+            text: None,
+            calls: vec![MockToolCall {
+                name: name.into(),
+                input,
+            }],
+        }
+    }
+
+    /// Creates a response that fails with the given message.
+    pub fn error(message: impl Into<String>) -> Self {
+        MockResponse::Error(message.into())
+    }
+}
+
+/// A request received by `MockModel`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MockRequest {
+    /// A prompt sent to the model.
+    Prompt(String),
+    /// The results of tool calls sent back to the model during an agent run, as
+    /// `(tool name, result)` pairs. Failed tool calls are returned as strings.
+    ToolResults(Vec<(String, Value)>),
+}
+
+impl MockRequest {
+    /// The text used to match the request against patterns.
+    ///
+    /// Tool results are matched against their JSON representation.
+    fn text(&self) -> String {
+        match self {
+            MockRequest::Prompt(prompt) => prompt.clone(),
+            MockRequest::ToolResults(results) => {
+                Value::Array(results.iter().map(|(_, result)| result.clone()).collect()).to_string()
+            }
+        }
+    }
+}
+
+type Predicate = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// A rule returning a response for requests matching a predicate.
+#[derive(Clone)]
+struct Rule {
+    description: String,
+    predicate: Predicate,
+    response: MockResponse,
+}
+
+#[derive(Default)]
+struct MockState {
+    rules: Vec<Rule>,
+    script: VecDeque<MockResponse>,
+    fallback: Option<MockResponse>,
+    requests: Vec<MockRequest>,
+}
+
+/// A model node returning scripted responses.
+///
+/// For each request the first rule whose pattern matches is used, then the next
+/// response in the script, then the fallback response. A request without a
+/// response fails with `AnchorChainError::ModelError`. Clones share the same
+/// script and recorded requests so a clone can be kept for assertions after the
+/// model is moved into a chain.
+///
+/// # Example
+/// ```rust
+/// use anchor_chain::testing::{MockModel, MockRequest, MockResponse};
+/// use anchor_chain::ChainBuilder;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let model = MockModel::new("mock")
+///         .when_contains("weather", MockResponse::text("It is sunny"))
+///         .then(MockResponse::text("Hello!"));
+///
+///     let chain = ChainBuilder::new().link(model.clone()).build();
+///
+///     assert_eq!(chain.process("Hi".to_string()).await.unwrap(), "Hello!");
+///     assert_eq!(
+///         chain.process("What is the weather?".to_string()).await.unwrap(),
+///         "It is sunny"
+///     );
+///     assert_eq!(model.requests()[0], MockRequest::Prompt("Hi".to_string()));
+/// }
+/// ```
+#[derive(Clone)]
+pub struct MockModel {
+    name: String,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockModel {
+    /// Creates a new `MockModel` without any responses.
+    ///
+    /// The name is used as the model ID when recording usage.
+    pub fn new(name: impl Into<String>) -> Self {
+        MockModel {
+            name: name.into(),
+            state: Arc::new(Mutex::new(MockState::default())),
+        }
+    }
+
+    /// Adds a response to the end of the script.
+    pub fn then(self, response: MockResponse) -> Self {
+        self.lock().script.push_back(response);
+        self
+    }
+
+    /// Returns the response for every request matching the predicate.
+    pub fn when<F>(
+        self,
+        description: impl Into<String>,
+        predicate: F,
+        response: MockResponse,
+    ) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.lock().rules.push(Rule {
+            description: description.into(),
+            predicate: Arc::new(predicate),
+            response,
+        });
+        self
+    }
+
+    /// Returns the response for every request containing `pattern`.
+    pub fn when_contains(self, pattern: impl Into<String>, response: MockResponse) -> Self {
+        let pattern = pattern.into();
+        let description = format!("contains {pattern:?}");
+        self.when(
+            description,
+            move |request| request.contains(&pattern),
+            response,
+        )
+    }
+
+    /// Returns the response for every request equal to `request`.
+    pub fn when_equals(self, request: impl Into<String>, response: MockResponse) -> Self {
+        let request = request.into();
+        let description = format!("equals {request:?}");
+        self.when(description, move |input| input == request, response)
+    }
+
+    /// Returns the response when no rule matches and the script is empty.
+    pub fn otherwise(self, response: MockResponse) -> Self {
+        self.lock().fallback = Some(response);
+        self
+    }
+
+    /// Returns every request received in order.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.lock().requests.clone()
+    }
+
+    /// Returns the prompts received in order, excluding tool results.
+    pub fn prompts(&self) -> Vec<String> {
+        self.requests()
+            .into_iter()
+            .filter_map(|request| match request {
+                MockRequest::Prompt(prompt) => Some(prompt),
+                MockRequest::ToolResults(_) => None,
+            })
+            .collect()
+    }
+
+    /// Returns the number of scripted responses that haven't been used.
+    pub fn remaining(&self) -> usize {
+        self.lock().script.len()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state.lock().expect("MockModel lock poisoned")
+    }
+
+    /// Records the request and returns the response for it.
+    fn respond(&self, request: MockRequest) -> Result<MockResponse, AnchorChainError> {
+        let text = request.text();
+        let mut state = self.lock();
+        state.requests.push(request);
+
+        let response = state
+            .rules
+            .iter()
+            .find(|rule| (rule.predicate)(&text))
+            .map(|rule| rule.response.clone())
+            .or_else(|| state.script.pop_front())
+            .or_else(|| state.fallback.clone())
+            .ok_or_else(|| {
+                AnchorChainError::ModelError(format!("MockModel has no response for {text:?}"))
+            })?;
+        drop(state);
+
+        let completion = match &response {
+            MockResponse::Text(text) => word_count(text),
+            MockResponse::ToolUse { text, calls } => {
+                text.as_deref().map_or(0, word_count) + calls.len() as u64
+            }
+            MockResponse::Error(message) => {
+                return Err(AnchorChainError::ModelError(message.clone()))
+            }
+        };
+        record_usage(
+            "mock",
+            &self.name,
+            TokenUsage::new(word_count(&text), completion),
+            Duration::ZERO,
+        );
+        Ok(response)
+    }
+
+    /// Runs an agent loop with scripted tool-use turns.
+    ///
+    /// Mirrors `BedrockConverse::run_agent`: tool calls requested by a response are
+    /// executed with the registry and their results sent back to the model until
+    /// a response without tool calls is returned or `max_iterations` is reached.
+    /// The text of every response is joined and returned.
+    pub async fn run_agent<'b>(
+        &self,
+        input: String,
+        max_iterations: usize,
+        tool_registry: &'b RwLock<ToolRegistry<'b>>,
+    ) -> Result<String, AnchorChainError> {
+        let mut output = Vec::new();
+        let mut response = self.respond(MockRequest::Prompt(input))?;
+
+        for _ in 0..max_iterations {
+            let calls = match response {
+                MockResponse::Text(text) => {
+                    output.push(text);
+                    break;
+                }
+                MockResponse::ToolUse { text, calls } => {
+                    output.extend(text);
+                    calls
+                }
+                MockResponse::Error(_) => unreachable!("Errors are returned by respond"),
+            };
+
+            let registry = tool_registry.read().await;
+            let results = calls
+                .into_iter()
+                .map(|call| {
+                    let result = registry
+                        .execute_tool(&call.name, call.input)
+                        .unwrap_or_else(Value::String);
+                    (call.name, result)
+                })
+                .collect();
+            drop(registry);
+            response = self.respond(MockRequest::ToolResults(results))?;
+        }
+        Ok(output.join("\n\n"))
+    }
+}
+
+/// Approximates the token count of a string by counting words.
+fn word_count(text: &str) -> u64 {
+    text.split_whitespace().count() as u64
+}
+
+#[async_trait]
+impl Node for MockModel {
+    type Input = String;
+    type Output = String;
+
+    /// Returns the scripted text response for the input.
+    ///
+    /// Returns an error if the response is a tool-use turn.
+    #[cfg_attr(feature = "tracing", instrument(skip(self), fields(model = self.name.as_str())))]
+    async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+        match self.respond(MockRequest::Prompt(input))? {
+            MockResponse::Text(text) => Ok(text),
+            MockResponse::ToolUse { .. } => Err(AnchorChainError::ModelError(
+                "MockModel returned a tool-use response outside of run_agent".to_string(),
+            )),
+            MockResponse::Error(_) => unreachable!("Errors are returned by respond"),
+        }
+    }
+}
+
+impl Stateless for MockModel {}
+
+impl fmt::Debug for MockModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.lock();
+        f.debug_struct("MockModel")
+            .field("name", &self.name)
+            .field(
+                "rules",
+                &state
+                    .rules
+                    .iter()
+                    .map(|rule| &rule.description)
+                    .collect::<Vec<_>>(),
+            )
+            .field("script", &state.script)
+            .field("requests", &state.requests.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::tool_registry::ToolEntry;
+    use crate::usage::UsageLedger;
+    use crate::{ChainBuilder, Prompt};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn test_chain_records_prompts() {
+        let model = MockModel::new("mock")
+            .when_equals("Say hi", MockResponse::text("hi"))
+            .then(MockResponse::text("first"))
+            .then(MockResponse::error("throttled"));
+        let chain = ChainBuilder::new()
+            .link(Prompt::new("Say {{ input }}"))
+            .link(model.clone())
+            .build();
+
+        let (output, ledger) = chain
+            .process_with_usage(HashMap::from([("input", "hello")]))
+            .await
+            .unwrap();
+        assert_eq!(output, "first");
+        assert_eq!(ledger.total(), TokenUsage::new(2, 1));
+        assert_eq!(
+            chain
+                .process(HashMap::from([("input", "hi")]))
+                .await
+                .unwrap(),
+            "hi"
+        );
+        assert!(matches!(
+            chain.process(HashMap::from([("input", "bye")])).await,
+            Err(AnchorChainError::ModelError(message)) if message == "throttled"
+        ));
+        assert!(chain
+            .process(HashMap::from([("input", "bye")]))
+            .await
+            .is_err());
+        assert_eq!(
+            model.prompts(),
+            vec!["Say hello", "Say hi", "Say bye", "Say bye"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_agent_executes_tools() {
+        let mut registry = ToolRegistry::new();
+        registry.register_tool(ToolEntry::new(
+            "add",
+            "Adds two numbers",
+            |params: Value| params["a"].as_i64().unwrap() + params["b"].as_i64().unwrap(),
+            json!({}),
+        ));
+        let registry = RwLock::new(registry);
+        let model = MockModel::new("mock")
+            .then(MockResponse::ToolUse {
+                text: Some("Adding the numbers".to_string()),
+                calls: vec![
+                    MockToolCall {
+                        name: "add".to_string(),
+                        input: json!({"a": 1, "b": 2}),
+                    },
+                    MockToolCall {
+                        name: "missing".to_string(),
+                        input: json!({}),
+                    },
+                ],
+            })
+            .when_contains("3", MockResponse::text("The answer is 3"));
+
+        let ledger = UsageLedger::new();
+        let output = ledger
+            .scope(model.run_agent("What is 1 + 2?".to_string(), 5, &registry))
+            .await
+            .unwrap();
+
+        assert_eq!(output, "Adding the numbers\n\nThe answer is 3");
+        assert_eq!(ledger.calls(), 2);
+        assert_eq!(
+            model.requests()[1],
+            MockRequest::ToolResults(vec![
+                ("add".to_string(), json!(3)),
+                ("missing".to_string(), json!("Tool missing not found")),
+            ])
+        );
+    }
+}
@@ -0,0 +1,13 @@
+//! Utilities for testing chains without network access.
+//!
+//! Enabled with the `testing` feature. `MockModel` can stand in for any text model
+//! node, returning scripted responses and recording the requests it receives so
+//! tests can assert on exact prompts and agent behaviour. `Cassette` records the
+//! interactions of real nodes to disk and replays them in later test runs.
+
+pub mod cassette;
+pub mod mock_model;
+
+pub use cassette::{Cassette, CassetteMode};
+
+pub use mock_model::{MockModel, MockRequest, MockResponse, MockToolCall};
@@ -0,0 +1,224 @@
+//! Token counting and context window sizes for models.
+//!
+//! The `Tokenizer` trait counts and truncates text in tokens so prompts can be
+//! kept within a model's context window. `TiktokenTokenizer` uses the exact BPE
+//! encodings of OpenAI models when the `openai` feature is enabled, and
+//! `ApproximateTokenizer` estimates counts from the length of the text for models
+//! whose tokenizer isn't available. `context_window` returns the context size of
+//! common models.
+
+use std::fmt;
+use std::sync::Arc;
+
+/// Counts and truncates text in model tokens.
+pub trait Tokenizer: fmt::Debug + Send + Sync {
+    /// Returns the number of tokens in the text.
+    fn count_tokens(&self, text: &str) -> usize;
+
+    /// Returns the longest prefix of the text with at most `max_tokens` tokens.
+    fn truncate(&self, text: &str, max_tokens: usize) -> String;
+}
+
+/// Estimates token counts from the number of characters in the text.
+///
+/// English text averages about four characters per token for most modern
+/// tokenizers. Estimates are rounded up so budgets err on the side of fitting.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ApproximateTokenizer {
+    chars_per_token: f32,
+}
+
+impl ApproximateTokenizer {
+    /// Creates a tokenizer estimating one token per `chars_per_token` characters.
+    pub fn new(chars_per_token: f32) -> Self {
+        ApproximateTokenizer {
+            chars_per_token: chars_per_token.max(f32::EPSILON),
+        }
+    }
+}
+
+impl Default for ApproximateTokenizer {
+    /// Creates a tokenizer estimating one token per four characters.
+    fn default() -> Self {
+        ApproximateTokenizer::new(4.0)
+    }
+}
+
+impl Tokenizer for ApproximateTokenizer {
+    fn count_tokens(&self, text: &str) -> usize {
+        (text.chars().count() as f32 / self.chars_per_token).ceil() as usize
+    }
+
+    fn truncate(&self, text: &str, max_tokens: usize) -> String {
+        let max_chars = (max_tokens as f32 * self.chars_per_token).floor() as usize;
+        text.chars().take(max_chars).collect()
+    }
+}
+
+/// Counts tokens using the BPE encoding of an OpenAI model.
+#[cfg(feature = "openai")]
+#[derive(Clone, Copy)]
+pub struct TiktokenTokenizer {
+    encoding: tiktoken_rs::tokenizer::Tokenizer,
+    bpe: &'static tiktoken_rs::CoreBPE,
+}
+
+#[cfg(feature = "openai")]
+impl TiktokenTokenizer {
+    /// Returns the tokenizer used by the OpenAI model, or `None` if the model
+    /// isn't recognized.
+    pub fn for_model(model: &str) -> Option<Self> {
+        use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer as Encoding};
+
+        let encoding = get_tokenizer(model)?;
+        let bpe = match encoding {
+            Encoding::O200kBase => tiktoken_rs::o200k_base_singleton(),
+            Encoding::Cl100kBase => tiktoken_rs::cl100k_base_singleton(),
+            Encoding::P50kBase => tiktoken_rs::p50k_base_singleton(),
+            Encoding::P50kEdit => tiktoken_rs::p50k_edit_singleton(),
+            Encoding::R50kBase | Encoding::Gpt2 => tiktoken_rs::r50k_base_singleton(),
+        };
+        Some(TiktokenTokenizer { encoding, bpe })
+    }
+
+    /// Returns the `cl100k_base` tokenizer used by GPT-3.5 and GPT-4.
+    pub fn cl100k_base() -> Self {
+        TiktokenTokenizer {
+            encoding: tiktoken_rs::tokenizer::Tokenizer::Cl100kBase,
+            bpe: tiktoken_rs::cl100k_base_singleton(),
+        }
+    }
+
+    /// Returns the `o200k_base` tokenizer used by GPT-4o.
+    pub fn o200k_base() -> Self {
+        TiktokenTokenizer {
+            encoding: tiktoken_rs::tokenizer::Tokenizer::O200kBase,
+            bpe: tiktoken_rs::o200k_base_singleton(),
+        }
+    }
+}
+
+#[cfg(feature = "openai")]
+impl Tokenizer for TiktokenTokenizer {
+    fn count_tokens(&self, text: &str) -> usize {
+        self.bpe.encode_ordinary(text).len()
+    }
+
+    fn truncate(&self, text: &str, max_tokens: usize) -> String {
+        let mut tokens = self.bpe.encode_ordinary(text);
+        if tokens.len() <= max_tokens {
+            return text.to_string();
+        }
+        // Cutting between tokens can split a multi-byte character, so drop tokens
+        // until the prefix decodes.
+        tokens.truncate(max_tokens);
+        while !tokens.is_empty() {
+            if let Ok(prefix) = self.bpe.decode(tokens.clone()) {
+                return prefix;
+            }
+            tokens.pop();
+        }
+        String::new()
+    }
+}
+
+#[cfg(feature = "openai")]
+impl fmt::Debug for TiktokenTokenizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TiktokenTokenizer")
+            .field("encoding", &self.encoding)
+            .finish()
+    }
+}
+
+/// Context window sizes in tokens by model ID prefix, most specific first.
+const CONTEXT_WINDOWS: &[(&str, usize)] = &[
+    // OpenAI
+    ("gpt-4o", 128_000),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4-0125", 128_000),
+    ("gpt-4-1106", 128_000),
+    ("gpt-4-32k", 32_768),
+    ("gpt-4", 8_192),
+    ("gpt-3.5-turbo-instruct", 4_096),
+    ("gpt-3.5-turbo", 16_385),
+    ("text-embedding-3", 8_191),
+    ("text-embedding-ada-002", 8_191),
+    // Bedrock
+    ("anthropic.claude-3", 200_000),
+    ("anthropic.claude-v2", 100_000),
+    ("anthropic.claude-instant", 100_000),
+    ("meta.llama3-1", 128_000),
+    ("meta.llama3", 8_192),
+    ("mistral.mistral-large", 32_000),
+    ("mistral.mixtral", 32_000),
+    ("mistral.mistral", 32_000),
+    ("cohere.command-r", 128_000),
+    ("cohere.command", 4_000),
+    ("amazon.titan-text-premier", 32_000),
+    ("amazon.titan-text", 8_000),
+    ("amazon.titan-embed-text-v2", 8_192),
+    ("amazon.titan-embed-text", 8_000),
+    // Ollama
+    ("llama3.1", 128_000),
+    ("llama3", 8_192),
+    ("mistral", 32_000),
+];
+
+/// Returns the context window of the model in tokens, or `None` if it isn't known.
+///
+/// Bedrock cross-region inference profile IDs such as `us.anthropic.claude-3-haiku`
+/// are matched by their model ID.
+pub fn context_window(model: &str) -> Option<usize> {
+    let model = ["us.", "eu.", "apac."]
+        .iter()
+        .find_map(|region| model.strip_prefix(region))
+        .unwrap_or(model);
+    CONTEXT_WINDOWS
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, window)| *window)
+}
+
+/// Returns the most accurate tokenizer available for the model.
+///
+/// OpenAI models use their BPE encoding when the `openai` feature is enabled;
+/// other models use an `ApproximateTokenizer`.
+pub fn tokenizer_for_model(model: &str) -> Arc<dyn Tokenizer> {
+    #[cfg(feature = "openai")]
+    if let Some(tokenizer) = TiktokenTokenizer::for_model(model) {
+        return Arc::new(tokenizer);
+    }
+    #[cfg(not(feature = "openai"))]
+    let _ = model;
+    Arc::new(ApproximateTokenizer::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_window_and_approximate_tokenizer() {
+        assert_eq!(context_window("gpt-4o-mini"), Some(128_000));
+        assert_eq!(context_window("gpt-4-0613"), Some(8_192));
+        assert_eq!(
+            context_window("us.anthropic.claude-3-5-sonnet-20240620-v1:0"),
+            Some(200_000)
+        );
+        assert_eq!(context_window("unknown"), None);
+
+        let tokenizer = ApproximateTokenizer::default();
+        assert_eq!(tokenizer.count_tokens("abcdefghi"), 3);
+        assert_eq!(tokenizer.truncate("abcdefghi", 2), "abcdefgh");
+    }
+
+    #[cfg(feature = "openai")]
+    #[test]
+    fn test_tiktoken_tokenizer() {
+        let tokenizer = TiktokenTokenizer::for_model("gpt-3.5-turbo").unwrap();
+        assert_eq!(tokenizer.count_tokens("hello world"), 2);
+        assert_eq!(tokenizer.truncate("hello world", 1), "hello");
+        assert_eq!(tokenizer.truncate("hello world", 5), "hello world");
+    }
+}
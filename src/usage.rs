@@ -0,0 +1,338 @@
+//! Token usage and cost accounting for model calls.
+//!
+//! Model nodes record the prompt and completion tokens and the latency of every
+//! request into the `UsageLedger` of the current run. A ledger is attached to a run
+//! with `Chain::process_with_usage`, `Chain::process_with_ledger` or the
+//! `RunContext` of the run; calls made outside of a run with a ledger are not
+//! recorded. A `PriceTable` can be used to
+//! compute the cost of the recorded usage per provider and model.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::ops::{Add, AddAssign};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::context::RunContext;
+
+/// The number of tokens consumed by one or more model calls.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    /// The number of tokens sent to the model.
+    pub prompt_tokens: u64,
+    /// The number of tokens generated by the model.
+    pub completion_tokens: u64,
+}
+
+impl TokenUsage {
+    /// Creates a new `TokenUsage` with the given prompt and completion tokens.
+    pub fn new(prompt_tokens: u64, completion_tokens: u64) -> Self {
+        TokenUsage {
+            prompt_tokens,
+            completion_tokens,
+        }
+    }
+
+    /// The total number of prompt and completion tokens.
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+impl Add for TokenUsage {
+    type Output = TokenUsage;
+
+    fn add(self, other: TokenUsage) -> TokenUsage {
+        TokenUsage::new(
+            self.prompt_tokens + other.prompt_tokens,
+            self.completion_tokens + other.completion_tokens,
+        )
+    }
+}
+
+impl AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: TokenUsage) {
+        *self = *self + other;
+    }
+}
+
+/// The usage of a single model call.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UsageRecord {
+    /// The provider the model is hosted on such as `openai`, `bedrock` or `ollama`.
+    pub provider: String,
+    /// The model ID used for the request.
+    pub model: String,
+    /// The tokens consumed by the request.
+    pub usage: TokenUsage,
+    /// The time taken for the model to respond.
+    pub latency: Duration,
+}
+
+impl UsageRecord {
+    /// Creates a new `UsageRecord`.
+    pub fn new(
+        provider: impl Into<String>,
+        model: impl Into<String>,
+        usage: TokenUsage,
+        latency: Duration,
+    ) -> Self {
+        UsageRecord {
+            provider: provider.into(),
+            model: model.into(),
+            usage,
+            latency,
+        }
+    }
+}
+
+/// Records the usage of every model call made during a run.
+///
+/// Cloning a `UsageLedger` returns a handle to the same records, allowing a ledger
+/// to be shared between runs to track usage across them.
+#[derive(Clone, Debug, Default)]
+pub struct UsageLedger {
+    records: Arc<Mutex<Vec<UsageRecord>>>,
+}
+
+impl UsageLedger {
+    /// Creates a new empty `UsageLedger`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a record to the ledger.
+    pub fn record(&self, record: UsageRecord) {
+        self.records
+            .lock()
+            .expect("Usage ledger lock poisoned")
+            .push(record);
+    }
+
+    /// Returns a copy of all records in the order they were recorded.
+    pub fn records(&self) -> Vec<UsageRecord> {
+        self.records
+            .lock()
+            .expect("Usage ledger lock poisoned")
+            .clone()
+    }
+
+    /// Returns the number of model calls recorded.
+    pub fn calls(&self) -> usize {
+        self.records
+            .lock()
+            .expect("Usage ledger lock poisoned")
+            .len()
+    }
+
+    /// Returns the total tokens consumed by all recorded calls.
+    pub fn total(&self) -> TokenUsage {
+        self.records()
+            .iter()
+            .fold(TokenUsage::default(), |total, record| total + record.usage)
+    }
+
+    /// Returns the combined latency of all recorded calls.
+    ///
+    /// Calls made in parallel are counted individually so this may be larger than
+    /// the wall clock time of the run.
+    pub fn total_latency(&self) -> Duration {
+        self.records().iter().map(|record| record.latency).sum()
+    }
+
+    /// Returns the tokens consumed keyed by `(provider, model)`.
+    pub fn usage_by_model(&self) -> HashMap<(String, String), TokenUsage> {
+        let mut usage = HashMap::new();
+        for record in self.records() {
+            *usage
+                .entry((record.provider, record.model))
+                .or_insert_with(TokenUsage::default) += record.usage;
+        }
+        usage
+    }
+
+    /// Returns the cost of the recorded calls keyed by `(provider, model)`.
+    ///
+    /// Models missing from the price table are not included.
+    pub fn cost_by_model(&self, prices: &PriceTable) -> HashMap<(String, String), f64> {
+        self.usage_by_model()
+            .into_iter()
+            .filter_map(|((provider, model), usage)| {
+                let cost = prices.price(&provider, &model)?.cost(&usage);
+                Some(((provider, model), cost))
+            })
+            .collect()
+    }
+
+    /// Returns the total cost of the recorded calls.
+    ///
+    /// Models missing from the price table are not included.
+    pub fn total_cost(&self, prices: &PriceTable) -> f64 {
+        self.cost_by_model(prices).values().sum()
+    }
+
+    /// Runs the future with this ledger recording the usage of any model calls.
+    ///
+    /// The rest of the current `RunContext` is kept.
+    pub async fn scope<F: Future>(&self, future: F) -> F::Output {
+        RunContext::current()
+            .with_ledger(self.clone())
+            .scope(future)
+            .await
+    }
+
+    /// Returns the ledger of the current run if one is set.
+    pub fn current() -> Option<UsageLedger> {
+        RunContext::with_current(|context| context.ledger().cloned()).flatten()
+    }
+}
+
+/// Records a model call in the ledger of the current run.
+///
+/// This is a no-op if the call isn't made within a run with a ledger. Model nodes
+/// call this after each request; custom nodes can call it to report their own usage.
+pub fn record_usage(
+    provider: impl Into<String>,
+    model: impl Into<String>,
+    usage: TokenUsage,
+    latency: Duration,
+) {
+    if let Some(ledger) = UsageLedger::current() {
+        ledger.record(UsageRecord::new(provider, model, usage, latency));
+    }
+}
+
+/// The price of a model in US dollars per million tokens.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ModelPrice {
+    /// The price per million prompt tokens.
+    pub prompt_per_million: f64,
+    /// The price per million completion tokens.
+    pub completion_per_million: f64,
+}
+
+impl ModelPrice {
+    /// Creates a new `ModelPrice` from prices per million tokens.
+    pub fn new(prompt_per_million: f64, completion_per_million: f64) -> Self {
+        ModelPrice {
+            prompt_per_million,
+            completion_per_million,
+        }
+    }
+
+    /// Returns the cost of the given usage.
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        (usage.prompt_tokens as f64 * self.prompt_per_million
+            + usage.completion_tokens as f64 * self.completion_per_million)
+            / 1_000_000.0
+    }
+}
+
+/// Prices of models keyed by provider and model ID.
+///
+/// # Example
+/// ```rust
+/// use anchor_chain::usage::{ModelPrice, PriceTable};
+///
+/// let prices = PriceTable::new()
+///     .with_price("openai", "gpt-4-turbo-preview", ModelPrice::new(10.0, 30.0))
+///     .with_price("bedrock", "anthropic.claude-3-haiku-20240307-v1:0", ModelPrice::new(0.25, 1.25));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct PriceTable {
+    prices: HashMap<(String, String), ModelPrice>,
+}
+
+impl PriceTable {
+    /// Creates a new empty `PriceTable`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds or replaces the price of a model.
+    pub fn with_price(
+        mut self,
+        provider: impl Into<String>,
+        model: impl Into<String>,
+        price: ModelPrice,
+    ) -> Self {
+        self.prices.insert((provider.into(), model.into()), price);
+        self
+    }
+
+    /// Returns the price of a model if one is set.
+    pub fn price(&self, provider: &str, model: &str) -> Option<ModelPrice> {
+        self.prices
+            .get(&(provider.to_string(), model.to_string()))
+            .copied()
+    }
+
+    /// Returns the cost of a single record if the model has a price.
+    pub fn cost(&self, record: &UsageRecord) -> Option<f64> {
+        self.price(&record.provider, &record.model)
+            .map(|price| price.cost(&record.usage))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_usage_is_recorded_in_scoped_ledger() {
+        record_usage("openai", "gpt", TokenUsage::new(1, 1), Duration::ZERO);
+
+        let ledger = UsageLedger::new();
+        ledger
+            .scope(async {
+                record_usage(
+                    "openai",
+                    "gpt",
+                    TokenUsage::new(10, 5),
+                    Duration::from_millis(20),
+                );
+                record_usage(
+                    "bedrock",
+                    "claude",
+                    TokenUsage::new(3, 7),
+                    Duration::from_millis(30),
+                );
+                record_usage(
+                    "openai",
+                    "gpt",
+                    TokenUsage::new(2, 1),
+                    Duration::from_millis(10),
+                );
+            })
+            .await;
+
+        assert_eq!(ledger.calls(), 3);
+        assert_eq!(ledger.total(), TokenUsage::new(15, 13));
+        assert_eq!(ledger.total_latency(), Duration::from_millis(60));
+        assert_eq!(
+            ledger.usage_by_model()[&("openai".to_string(), "gpt".to_string())],
+            TokenUsage::new(12, 6)
+        );
+    }
+
+    #[test]
+    fn test_cost_by_model() {
+        let ledger = UsageLedger::new();
+        ledger.record(UsageRecord::new(
+            "openai",
+            "gpt",
+            TokenUsage::new(1_000_000, 500_000),
+            Duration::ZERO,
+        ));
+        ledger.record(UsageRecord::new(
+            "ollama",
+            "llama3",
+            TokenUsage::new(100, 100),
+            Duration::ZERO,
+        ));
+        let prices = PriceTable::new().with_price("openai", "gpt", ModelPrice::new(2.0, 4.0));
+
+        assert_eq!(ledger.total_cost(&prices), 4.0);
+        assert_eq!(ledger.cost_by_model(&prices).len(), 1);
+    }
+}
@@ -3,6 +3,7 @@
 //! These nodes are used to preform various operations on vector databases such as indexing and
 //! retrieving documents.
 pub mod document;
+pub mod vector_store;
 
 #[cfg(feature = "opensearch")]
 pub mod opensearch_client_builder;
@@ -10,3 +11,5 @@ pub mod opensearch_client_builder;
 pub mod opensearch_indexer;
 #[cfg(feature = "opensearch")]
 pub mod opensearch_retriever;
+#[cfg(feature = "opensearch")]
+pub mod opensearch_vector_store;
@@ -169,7 +169,10 @@ impl<M: EmbeddingModel + fmt::Debug + Send + Sync> OpenSearchRetrieverBuilder<M>
     }
 
     /// Builds an OpenSearchRetriever from the provided configuration.
-    pub async fn build(self, base_url: &str) -> Result<OpenSearchRetriever<M>, AnchorChainError> {
+    pub async fn build(
+        self,
+        base_url: &str,
+    ) -> Result<OpenSearchRetriever<'_, M>, AnchorChainError> {
         let embedding_model = self
             .embedding_model
             .ok_or(AnchorChainError::InvalidInputError(
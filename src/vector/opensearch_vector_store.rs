@@ -0,0 +1,211 @@
+//! A `VectorStore` backed by an OpenSearch k-NN index.
+//!
+//! The index is created on the first upsert using the dimensions of the stored
+//! embeddings. Similarity is computed from the embeddings returned with each hit so
+//! scores are comparable with other `VectorStore` implementations.
+//!
+//! Filtered searches score every matching document exactly rather than filtering
+//! the approximate nearest neighbours, so matches aren't crowded out by other
+//! documents. String metadata fields are indexed as keywords for filtering.
+
+use async_trait::async_trait;
+use opensearch::http::request::JsonBody;
+use opensearch::indices::{IndicesCreateParts, IndicesExistsParts};
+use opensearch::{BulkParts, OpenSearch, SearchParts};
+use serde_json::{json, Value};
+use tokio::sync::OnceCell;
+#[cfg(feature = "tracing")]
+use tracing::instrument;
+
+use crate::error::AnchorChainError;
+use crate::vector::document::Document;
+use crate::vector::vector_store::{
+    cosine_similarity, require_embedding, MetadataFilter, VectorStore,
+};
+
+/// A vector store using an OpenSearch k-NN index.
+#[derive(Debug)]
+pub struct OpenSearchVectorStore {
+    client: OpenSearch,
+    index: String,
+    vector_field: String,
+    index_ready: OnceCell<()>,
+}
+
+impl OpenSearchVectorStore {
+    /// Creates a new store using the given index and vector field.
+    pub fn new(client: OpenSearch, index: &str, vector_field: &str) -> Self {
+        OpenSearchVectorStore {
+            client,
+            index: index.to_string(),
+            vector_field: vector_field.to_string(),
+            index_ready: OnceCell::new(),
+        }
+    }
+
+    /// Creates the k-NN index if it doesn't already exist.
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    async fn ensure_index(&self, dimensions: usize) -> Result<(), AnchorChainError> {
+        self.index_ready
+            .get_or_try_init(|| async {
+                let exists = self
+                    .client
+                    .indices()
+                    .exists(IndicesExistsParts::Index(&[&self.index]))
+                    .send()
+                    .await?;
+                if exists.status_code().is_success() {
+                    return Ok(());
+                }
+
+                let response = self
+                    .client
+                    .indices()
+                    .create(IndicesCreateParts::Index(&self.index))
+                    .body(json!({
+                        "settings": {
+                            "index.knn": true
+                        },
+                        "mappings": {
+                            "dynamic_templates": [{
+                                "metadata_strings": {
+                                    "path_match": "metadata.*",
+                                    "match_mapping_type": "string",
+                                    "mapping": {
+                                        "type": "keyword",
+                                        "ignore_above": 8191
+                                    }
+                                }
+                            }],
+                            "properties": {
+                                &self.vector_field: {
+                                    "type": "knn_vector",
+                                    "dimension": dimensions,
+                                    "method": {
+                                        "name": "hnsw",
+                                        "space_type": "cosinesimil",
+                                        "engine": "nmslib",
+                                    }
+                                }
+                            }
+                        }
+                    }))
+                    .send()
+                    .await?;
+                if response.status_code().is_success() {
+                    Ok(())
+                } else {
+                    Err(AnchorChainError::OpenSearchInternalError(
+                        response.text().await?,
+                    ))
+                }
+            })
+            .await
+            .map(|_| ())
+    }
+}
+
+#[async_trait]
+impl VectorStore for OpenSearchVectorStore {
+    #[cfg_attr(feature = "tracing", instrument(skip(self, documents)))]
+    async fn upsert(&self, documents: Vec<Document>) -> Result<(), AnchorChainError> {
+        let Some(first) = documents.first() else {
+            return Ok(());
+        };
+        self.ensure_index(require_embedding(first)?.len()).await?;
+
+        let mut operations: Vec<JsonBody<Value>> = Vec::with_capacity(documents.len() * 2);
+        for mut document in documents {
+            require_embedding(&document)?;
+            document.embedding_name = Some(self.vector_field.clone());
+            operations.push(json!({"index": {"_index": self.index, "_id": document.id}}).into());
+            operations.push(serde_json::to_value(&document)?.into());
+        }
+
+        let response = self
+            .client
+            .bulk(BulkParts::Index(&self.index))
+            .body(operations)
+            .send()
+            .await?;
+        if response.status_code().is_success() {
+            Ok(())
+        } else {
+            Err(AnchorChainError::OpenSearchInternalError(
+                response.text().await?,
+            ))
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", instrument(skip(self, embedding)))]
+    async fn search(
+        &self,
+        embedding: &[f32],
+        top_k: usize,
+        filter: Option<&MetadataFilter>,
+    ) -> Result<Vec<(Document, f32)>, AnchorChainError> {
+        let query = match filter {
+            Some(filter) => json!({
+                "script_score": {
+                    "query": {
+                        "bool": {
+                            "filter": filter
+                                .fields()
+                                .iter()
+                                .map(|(name, value)| json!({"term": {format!("metadata.{name}"): value}}))
+                                .collect::<Vec<_>>()
+                        }
+                    },
+                    "script": {
+                        "source": "knn_score",
+                        "lang": "knn",
+                        "params": {
+                            "field": self.vector_field,
+                            "query_value": embedding,
+                            "space_type": "cosinesimil",
+                        }
+                    }
+                }
+            }),
+            None => json!({
+                "knn": {
+                    &self.vector_field: {
+                        "vector": embedding,
+                        "k": top_k,
+                    }
+                }
+            }),
+        };
+        let response = self
+            .client
+            .search(SearchParts::Index(&[&self.index]))
+            .size(top_k as i64)
+            .body(json!({ "query": query }))
+            .send()
+            .await?;
+        // A missing index has no documents yet.
+        if response.status_code().as_u16() == 404 {
+            return Ok(Vec::new());
+        }
+        if !response.status_code().is_success() {
+            return Err(AnchorChainError::OpenSearchInternalError(
+                response.text().await?,
+            ));
+        }
+
+        let response = response.json::<Value>().await?;
+        let mut results = response["hits"]["hits"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|hit| serde_json::from_value::<Document>(hit["_source"].clone()).ok())
+            .filter_map(|document| {
+                let similarity = cosine_similarity(embedding, document.embedding.as_deref()?);
+                Some((document, similarity))
+            })
+            .collect::<Vec<_>>();
+        results.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        Ok(results)
+    }
+}
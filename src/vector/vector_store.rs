@@ -0,0 +1,178 @@
+//! A common interface for storing and searching document embeddings.
+//!
+//! `VectorStore` is implemented by `InMemoryVectorStore`, which is useful for tests
+//! and small datasets, and by `OpenSearchVectorStore` when the `opensearch` feature
+//! is enabled.
+
+use std::fmt;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::sync::RwLock;
+
+use crate::error::AnchorChainError;
+use crate::vector::document::Document;
+
+/// A store of documents that can be searched by embedding similarity.
+#[async_trait]
+pub trait VectorStore: fmt::Debug + Send + Sync {
+    /// Adds the documents to the store, replacing any documents with the same ID.
+    ///
+    /// Every document must have an embedding.
+    async fn upsert(&self, documents: Vec<Document>) -> Result<(), AnchorChainError>;
+
+    /// Returns up to `top_k` documents most similar to the embedding along with
+    /// their cosine similarity, ordered from most to least similar.
+    ///
+    /// If a filter is given, only documents matching it are searched.
+    async fn search(
+        &self,
+        embedding: &[f32],
+        top_k: usize,
+        filter: Option<&MetadataFilter>,
+    ) -> Result<Vec<(Document, f32)>, AnchorChainError>;
+}
+
+/// Restricts a search to documents whose metadata fields have the given values.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MetadataFilter {
+    fields: Vec<(String, Value)>,
+}
+
+impl MetadataFilter {
+    /// Creates a filter matching every document.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only matches documents whose metadata field `name` equals `value`.
+    pub fn with_field(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.fields.push((name.into(), value.into()));
+        self
+    }
+
+    /// Returns the required metadata fields and their values.
+    pub fn fields(&self) -> &[(String, Value)] {
+        &self.fields
+    }
+
+    /// Returns true if the document's metadata has every required field value.
+    pub fn matches(&self, document: &Document) -> bool {
+        self.fields.iter().all(|(name, value)| {
+            document
+                .metadata
+                .as_ref()
+                .and_then(|metadata| metadata.get(name))
+                == Some(value)
+        })
+    }
+}
+
+/// Returns the cosine similarity of two vectors, or 0 if either has no magnitude.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot = a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>();
+    let norm_a = a.iter().map(|v| v * v).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+/// Returns an error if the document doesn't have an embedding.
+pub(crate) fn require_embedding(document: &Document) -> Result<&[f32], AnchorChainError> {
+    document.embedding.as_deref().ok_or_else(|| {
+        AnchorChainError::InvalidInputError(format!("document {} has no embedding", document.id))
+    })
+}
+
+/// A vector store that keeps documents in memory and searches them exhaustively.
+///
+/// Clones share the same documents.
+#[derive(Clone, Debug, Default)]
+pub struct InMemoryVectorStore {
+    documents: Arc<RwLock<Vec<Document>>>,
+}
+
+impl InMemoryVectorStore {
+    /// Creates a new empty `InMemoryVectorStore`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of documents in the store.
+    pub async fn len(&self) -> usize {
+        self.documents.read().await.len()
+    }
+
+    /// Returns true if the store has no documents.
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+}
+
+#[async_trait]
+impl VectorStore for InMemoryVectorStore {
+    async fn upsert(&self, documents: Vec<Document>) -> Result<(), AnchorChainError> {
+        for document in &documents {
+            require_embedding(document)?;
+        }
+        let mut stored = self.documents.write().await;
+        for document in documents {
+            match stored.iter_mut().find(|stored| stored.id == document.id) {
+                Some(existing) => *existing = document,
+                None => stored.push(document),
+            }
+        }
+        Ok(())
+    }
+
+    async fn search(
+        &self,
+        embedding: &[f32],
+        top_k: usize,
+        filter: Option<&MetadataFilter>,
+    ) -> Result<Vec<(Document, f32)>, AnchorChainError> {
+        let stored = self.documents.read().await;
+        let mut results = stored
+            .iter()
+            .filter(|document| match filter {
+                Some(filter) => filter.matches(document),
+                None => true,
+            })
+            .filter_map(|document| {
+                let similarity = cosine_similarity(embedding, document.embedding.as_deref()?);
+                Some((document.clone(), similarity))
+            })
+            .collect::<Vec<_>>();
+        results.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        results.truncate(top_k);
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_in_memory_search_orders_by_similarity() {
+        let store = InMemoryVectorStore::new();
+        let mut near = Document::new("near".to_string());
+        near.embedding = Some(vec![1.0, 0.1]);
+        let mut far = Document::new("far".to_string());
+        far.embedding = Some(vec![0.0, 1.0]);
+        store.upsert(vec![far, near]).await.unwrap();
+
+        let results = store.search(&[1.0, 0.0], 1, None).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0.text, "near");
+        assert!(results[0].1 > 0.99);
+        assert!(store
+            .upsert(vec![Document::new("none".to_string())])
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,636 @@
+//! Workflows of named nodes connected by data dependencies.
+//!
+//! Chains pass the output of each node to the next, and `ParallelNode` runs nodes
+//! side by side on the same input. A `Workflow` instead runs a directed acyclic
+//! graph of named nodes, each declaring the nodes whose outputs it needs. A node
+//! starts as soon as all of its dependencies have finished, so independent nodes
+//! run concurrently.
+//!
+//! A node without dependencies takes the input of the workflow, and a node with a
+//! single dependency takes that dependency's output. A node taking `Outputs` can
+//! depend on any number of nodes and reads their outputs by name. Unknown
+//! dependencies, cycles and mismatched types are reported when the workflow is
+//! built.
+//!
+//! # Example
+//! ```rust
+//! use anchor_chain::{AnchorChainError, NoOpNode, Node, Stateless};
+//! use anchor_chain::workflow::{Outputs, WorkflowBuilder};
+//! use async_trait::async_trait;
+//!
+//! #[derive(Debug)]
+//! struct Compare;
+//!
+//! impl Stateless for Compare {}
+//!
+//! #[async_trait]
+//! impl Node for Compare {
+//!     type Input = Outputs;
+//!     type Output = String;
+//!
+//!     async fn process(&self, input: Outputs) -> Result<String, AnchorChainError> {
+//!         let draft = input.get::<String>("draft")?;
+//!         let review = input.get::<String>("review")?;
+//!         Ok(format!("{draft} / {review}"))
+//!     }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let workflow = WorkflowBuilder::<String>::new()
+//!         .node("question", &[], NoOpNode::<String>::new())
+//!         .node("draft", &["question"], NoOpNode::<String>::new())
+//!         .node("review", &["question"], NoOpNode::<String>::new())
+//!         .node("compare", &["draft", "review"], Compare)
+//!         .build::<String>()
+//!         .expect("Workflow should be valid");
+//!
+//!     let output = workflow.process("hi".to_string()).await.unwrap();
+//!     assert_eq!(output, "hi / hi");
+//! }
+//! ```
+
+use std::any::{type_name, Any, TypeId};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::stream::{FuturesUnordered, StreamExt};
+
+use crate::chain_state::ChainState;
+use crate::context::RunContext;
+use crate::describe::{NodeDescription, NodeKind};
+use crate::dynamic_chain::PayloadType;
+use crate::error::AnchorChainError;
+use crate::link::WithState;
+use crate::node::{Node, Stateful, Stateless};
+
+/// A value produced by a node of a workflow.
+#[derive(Clone)]
+struct Value {
+    value: Arc<dyn Any + Send + Sync>,
+    value_type: PayloadType,
+}
+
+impl Value {
+    fn new<T: Any + Send + Sync>(value: T) -> Self {
+        Value {
+            value: Arc::new(value),
+            value_type: PayloadType::of::<T>(),
+        }
+    }
+
+    fn get<T: Any + Clone>(&self) -> Result<T, AnchorChainError> {
+        self.value
+            .downcast_ref::<T>()
+            .cloned()
+            .ok_or_else(|| AnchorChainError::TypeMismatch {
+                expected: type_name::<T>().to_string(),
+                found: self.value_type.to_string(),
+            })
+    }
+}
+
+/// The input of a workflow and the outputs of its nodes, read by name.
+///
+/// Nodes taking `Outputs` are passed the workflow input and the outputs of their
+/// dependencies. `Workflow::run` returns the outputs of every node.
+#[derive(Clone)]
+pub struct Outputs {
+    input: Value,
+    values: HashMap<String, Value>,
+}
+
+impl Outputs {
+    /// Returns the input of the workflow.
+    pub fn input<T: Any + Clone>(&self) -> Result<T, AnchorChainError> {
+        self.input.get()
+    }
+
+    /// Returns the output of the named node, or an error if there is no output
+    /// with that name or it isn't of type `T`.
+    pub fn get<T: Any + Clone>(&self, name: &str) -> Result<T, AnchorChainError> {
+        self.values
+            .get(name)
+            .ok_or_else(|| AnchorChainError::WorkflowError(format!("no output from {name:?}")))?
+            .get()
+    }
+
+    /// Returns the names of the nodes with outputs.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().map(String::as_str)
+    }
+}
+
+impl fmt::Debug for Outputs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = self.names().collect::<Vec<_>>();
+        names.sort_unstable();
+        f.debug_struct("Outputs")
+            .field("input", &self.input.value_type.name())
+            .field("values", &names)
+            .finish()
+    }
+}
+
+/// A node of a workflow with type-erased input and output.
+#[async_trait]
+trait Step: fmt::Debug + Send + Sync {
+    fn input_type(&self) -> PayloadType;
+
+    fn output_type(&self) -> PayloadType;
+
+    fn describe(&self) -> NodeDescription;
+
+    /// Runs the node with the outputs of its dependencies, taking its input from
+    /// `dependency` if it has a single one.
+    async fn run(
+        &self,
+        outputs: Outputs,
+        dependency: Option<&str>,
+    ) -> Result<Value, AnchorChainError>;
+}
+
+#[derive(Debug)]
+struct Erased<N>(N);
+
+#[async_trait]
+impl<N> Step for Erased<N>
+where
+    N: Node + Send + Sync,
+    N::Input: Any + Clone + Send,
+    N::Output: Any + Send + Sync,
+{
+    fn input_type(&self) -> PayloadType {
+        PayloadType::of::<N::Input>()
+    }
+
+    fn output_type(&self) -> PayloadType {
+        PayloadType::of::<N::Output>()
+    }
+
+    fn describe(&self) -> NodeDescription {
+        self.0.describe()
+    }
+
+    async fn run(
+        &self,
+        outputs: Outputs,
+        dependency: Option<&str>,
+    ) -> Result<Value, AnchorChainError> {
+        let input = if TypeId::of::<N::Input>() == TypeId::of::<Outputs>() {
+            let outputs: Box<dyn Any + Send> = Box::new(outputs);
+            *outputs
+                .downcast::<N::Input>()
+                .expect("Input type was checked to be Outputs")
+        } else {
+            match dependency {
+                Some(name) => outputs.get(name)?,
+                None => outputs.input()?,
+            }
+        };
+        Ok(Value::new(self.0.process(input).await?))
+    }
+}
+
+#[derive(Debug)]
+struct WorkflowNode {
+    name: String,
+    dependencies: Vec<String>,
+    step: Box<dyn Step>,
+}
+
+impl WorkflowNode {
+    fn takes_outputs(&self) -> bool {
+        self.step.input_type() == PayloadType::of::<Outputs>()
+    }
+}
+
+/// A graph of named nodes run concurrently in dependency order.
+///
+/// Workflows are built with `WorkflowBuilder` and can be linked into chains as
+/// nodes taking `I` and returning the output `O` of the workflow's final node.
+pub struct Workflow<I, O> {
+    nodes: Vec<WorkflowNode>,
+    /// Indexes of the dependencies of each node.
+    dependencies: Vec<Vec<usize>>,
+    /// Indexes of the nodes depending on each node.
+    dependents: Vec<Vec<usize>>,
+    output: usize,
+    _types: PhantomData<fn(I) -> O>,
+}
+
+impl<I, O> Workflow<I, O>
+where
+    I: Any + Clone + Send + Sync,
+    O: Any + Clone + Send + Sync,
+{
+    /// Runs every node of the workflow, returning all of their outputs.
+    ///
+    /// Each node starts once all of its dependencies have finished. The run stops
+    /// at the first error, or if the `RunContext` of the run is cancelled or its
+    /// deadline passes.
+    pub async fn run(&self, input: I) -> Result<Outputs, AnchorChainError> {
+        let context = RunContext::current();
+        context
+            .interruptible(async {
+                let mut outputs = Outputs {
+                    input: Value::new(input),
+                    values: HashMap::new(),
+                };
+                let mut waiting = self.dependencies.iter().map(Vec::len).collect::<Vec<_>>();
+                let mut running = FuturesUnordered::new();
+                for (index, count) in waiting.iter().enumerate() {
+                    if *count == 0 {
+                        running.push(self.start(index, &outputs));
+                    }
+                }
+                while let Some((index, result)) = running.next().await {
+                    context.check()?;
+                    outputs
+                        .values
+                        .insert(self.nodes[index].name.clone(), result?);
+                    for &dependent in &self.dependents[index] {
+                        waiting[dependent] -= 1;
+                        if waiting[dependent] == 0 {
+                            running.push(self.start(dependent, &outputs));
+                        }
+                    }
+                }
+                Ok(outputs)
+            })
+            .await
+    }
+
+    /// Returns a future running the node with the outputs of its dependencies.
+    fn start(
+        &self,
+        index: usize,
+        outputs: &Outputs,
+    ) -> impl std::future::Future<Output = (usize, Result<Value, AnchorChainError>)> + '_ {
+        let node = &self.nodes[index];
+        let upstream = Outputs {
+            input: outputs.input.clone(),
+            values: node
+                .dependencies
+                .iter()
+                .map(|name| (name.clone(), outputs.values[name].clone()))
+                .collect(),
+        };
+        let dependency = match node.dependencies.as_slice() {
+            [name] => Some(name.as_str()),
+            _ => None,
+        };
+        async move { (index, node.step.run(upstream, dependency).await) }
+    }
+}
+
+impl<I, O> fmt::Debug for Workflow<I, O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Workflow")
+            .field("nodes", &self.nodes)
+            .field("output", &self.nodes[self.output].name)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl<I, O> Node for Workflow<I, O>
+where
+    I: Any + Clone + Send + Sync,
+    O: Any + Clone + Send + Sync,
+{
+    type Input = I;
+    type Output = O;
+
+    /// Runs the workflow, returning the output of its final node.
+    async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+        let outputs = self.run(input).await?;
+        outputs.get(&self.nodes[self.output].name)
+    }
+
+    /// Describes each node of the workflow with its name and dependencies.
+    fn describe(&self) -> NodeDescription {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| {
+                node.step
+                    .describe()
+                    .with_id(node.name.clone())
+                    .with_dependencies(node.dependencies.clone())
+            })
+            .collect();
+        NodeDescription::of::<Self>()
+            .with_name("Workflow")
+            .with_kind(NodeKind::Workflow)
+            .with_children(nodes)
+    }
+}
+
+impl<I, O> Stateless for Workflow<I, O>
+where
+    I: Any + Clone + Send + Sync,
+    O: Any + Clone + Send + Sync,
+{
+}
+
+/// A builder for constructing a `Workflow` taking `I`.
+///
+/// Nodes can be added in any order; dependencies, cycles and types are checked by
+/// `build`.
+pub struct WorkflowBuilder<I> {
+    nodes: Vec<WorkflowNode>,
+    output: Option<String>,
+    state: ChainState,
+    _input: PhantomData<fn(I)>,
+}
+
+impl<I> WorkflowBuilder<I>
+where
+    I: Any + Clone + Send + Sync,
+{
+    /// Creates a new empty `WorkflowBuilder`.
+    pub fn new() -> Self {
+        WorkflowBuilder {
+            nodes: Vec::new(),
+            output: None,
+            state: ChainState::new(),
+            _input: PhantomData,
+        }
+    }
+
+    fn push<N>(mut self, name: impl Into<String>, dependencies: &[&str], node: N) -> Self
+    where
+        N: Node + Send + Sync + 'static,
+        N::Input: Any + Clone + Send,
+        N::Output: Any + Send + Sync,
+    {
+        self.nodes.push(WorkflowNode {
+            name: name.into(),
+            dependencies: dependencies.iter().map(|name| name.to_string()).collect(),
+            step: Box::new(Erased(node)),
+        });
+        self
+    }
+
+    /// Adds a stateless node depending on the named nodes.
+    ///
+    /// A node without dependencies takes the workflow input and a node with one
+    /// dependency takes its output. Nodes with several dependencies must take
+    /// `Outputs`.
+    pub fn node<N>(self, name: impl Into<String>, dependencies: &[&str], node: N) -> Self
+    where
+        N: Node + Stateless + Send + Sync + 'static,
+        N::Input: Any + Clone + Send,
+        N::Output: Any + Send + Sync,
+    {
+        self.push(name, dependencies, node)
+    }
+
+    /// Adds a stateful node depending on the named nodes, which is passed the
+    /// `ChainState` shared by the stateful nodes of the workflow.
+    pub fn node_with_state<N>(self, name: impl Into<String>, dependencies: &[&str], node: N) -> Self
+    where
+        N: Node + Stateful + Send + Sync + 'static,
+        N::Input: Any + Clone + Send,
+        N::Output: Any + Send + Sync,
+    {
+        let node = WithState::new(node, self.state.clone());
+        self.push(name, dependencies, node)
+    }
+
+    /// Sets the node whose output is the output of the workflow.
+    ///
+    /// Defaults to the only node no other node depends on.
+    pub fn output(mut self, name: impl Into<String>) -> Self {
+        self.output = Some(name.into());
+        self
+    }
+
+    /// Returns the `ChainState` shared by the stateful nodes of the workflow.
+    pub fn state(&self) -> ChainState {
+        self.state.clone()
+    }
+
+    /// Builds the workflow with output type `O`.
+    ///
+    /// Returns an error if the workflow is empty, node names are repeated, a
+    /// dependency doesn't exist, the dependencies form a cycle, the input type of a
+    /// node doesn't match what it is passed, or the final node doesn't produce `O`.
+    pub fn build<O>(self) -> Result<Workflow<I, O>, AnchorChainError>
+    where
+        O: Any + Clone + Send + Sync,
+    {
+        let invalid = |message: String| Err(AnchorChainError::WorkflowError(message));
+        if self.nodes.is_empty() {
+            return invalid("workflow has no nodes".to_string());
+        }
+
+        let mut indexes = HashMap::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            if indexes.insert(node.name.as_str(), index).is_some() {
+                return invalid(format!("node {:?} is defined more than once", node.name));
+            }
+        }
+
+        let mut dependencies = Vec::with_capacity(self.nodes.len());
+        let mut dependents = vec![Vec::new(); self.nodes.len()];
+        for (index, node) in self.nodes.iter().enumerate() {
+            let mut node_dependencies = Vec::new();
+            for name in &node.dependencies {
+                let Some(&dependency) = indexes.get(name.as_str()) else {
+                    return invalid(format!(
+                        "node {:?} depends on unknown node {name:?}",
+                        node.name
+                    ));
+                };
+                node_dependencies.push(dependency);
+                dependents[dependency].push(index);
+            }
+            let expected = match node_dependencies.as_slice() {
+                _ if node.takes_outputs() => None,
+                [] => Some(("the workflow input", PayloadType::of::<I>())),
+                [dependency] => Some((
+                    self.nodes[*dependency].name.as_str(),
+                    self.nodes[*dependency].step.output_type(),
+                )),
+                _ => {
+                    return invalid(format!(
+                        "node {:?} has several dependencies but doesn't take Outputs",
+                        node.name
+                    ))
+                }
+            };
+            if let Some((source, found)) = expected {
+                if found != node.step.input_type() {
+                    return invalid(format!(
+                        "node {:?} takes {} but {source:?} produces {found}",
+                        node.name,
+                        node.step.input_type()
+                    ));
+                }
+            }
+            dependencies.push(node_dependencies);
+        }
+
+        let mut waiting = dependencies.iter().map(Vec::len).collect::<Vec<_>>();
+        let mut ready = (0..self.nodes.len())
+            .filter(|&index| waiting[index] == 0)
+            .collect::<VecDeque<_>>();
+        let mut sorted = 0;
+        while let Some(index) = ready.pop_front() {
+            sorted += 1;
+            for &dependent in &dependents[index] {
+                waiting[dependent] -= 1;
+                if waiting[dependent] == 0 {
+                    ready.push_back(dependent);
+                }
+            }
+        }
+        if sorted < self.nodes.len() {
+            let cycle = self
+                .nodes
+                .iter()
+                .zip(&waiting)
+                .filter(|(_, &count)| count > 0)
+                .map(|(node, _)| node.name.as_str())
+                .collect::<Vec<_>>();
+            return invalid(format!(
+                "dependencies form a cycle through {}",
+                cycle.join(", ")
+            ));
+        }
+
+        let output = match &self.output {
+            Some(name) => match indexes.get(name.as_str()) {
+                Some(&index) => index,
+                None => return invalid(format!("output node {name:?} doesn't exist")),
+            },
+            None => {
+                let sinks = (0..self.nodes.len())
+                    .filter(|&index| dependents[index].is_empty())
+                    .collect::<Vec<_>>();
+                match sinks.as_slice() {
+                    [index] => *index,
+                    _ => {
+                        let names = sinks
+                            .iter()
+                            .map(|&index| self.nodes[index].name.as_str())
+                            .collect::<Vec<_>>();
+                        return invalid(format!(
+                            "workflow has several final nodes ({}), choose one with output",
+                            names.join(", ")
+                        ));
+                    }
+                }
+            }
+        };
+        let output_type = self.nodes[output].step.output_type();
+        if output_type != PayloadType::of::<O>() {
+            return Err(AnchorChainError::TypeMismatch {
+                expected: type_name::<O>().to_string(),
+                found: output_type.to_string(),
+            });
+        }
+
+        Ok(Workflow {
+            nodes: self.nodes,
+            dependencies,
+            dependents,
+            output,
+            _types: PhantomData,
+        })
+    }
+}
+
+impl<I> Default for WorkflowBuilder<I>
+where
+    I: Any + Clone + Send + Sync,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I> fmt::Debug for WorkflowBuilder<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WorkflowBuilder")
+            .field("nodes", &self.nodes)
+            .field("output", &self.output)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::*;
+    use crate::node::NoOpNode;
+
+    #[derive(Debug, anchor_chain_macros::Stateless)]
+    struct Delay(u64);
+
+    #[async_trait]
+    impl Node for Delay {
+        type Input = u64;
+        type Output = u64;
+
+        async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+            tokio::time::sleep(Duration::from_secs(self.0)).await;
+            Ok(input + self.0)
+        }
+    }
+
+    #[derive(Debug, anchor_chain_macros::Stateless)]
+    struct Sum;
+
+    #[async_trait]
+    impl Node for Sum {
+        type Input = Outputs;
+        type Output = u64;
+
+        async fn process(&self, input: Self::Input) -> Result<Self::Output, AnchorChainError> {
+            Ok(input.get::<u64>("b")? + input.get::<u64>("c")? + input.input::<u64>()?)
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_workflow_runs_dependencies_concurrently() {
+        let workflow = WorkflowBuilder::<u64>::new()
+            .node("d", &["b", "c"], Sum)
+            .node("a", &[], Delay(1))
+            .node("b", &["a"], Delay(2))
+            .node("c", &["a"], Delay(3))
+            .build::<u64>()
+            .unwrap();
+
+        let start = Instant::now();
+        assert_eq!(workflow.process(10).await.unwrap(), 13 + 14 + 10);
+        assert_eq!(start.elapsed(), Duration::from_secs(4));
+
+        let cyclic = WorkflowBuilder::<u64>::new()
+            .node("a", &["c"], Delay(1))
+            .node("b", &["a"], Delay(1))
+            .node("c", &["b"], Delay(1))
+            .build::<u64>();
+        assert!(matches!(
+            cyclic,
+            Err(AnchorChainError::WorkflowError(message)) if message.contains("cycle through a, b, c")
+        ));
+
+        let mismatched = WorkflowBuilder::<u64>::new()
+            .node("a", &[], Delay(1))
+            .node("b", &["a"], NoOpNode::<String>::new())
+            .build::<String>();
+        assert!(matches!(
+            mismatched,
+            Err(AnchorChainError::WorkflowError(_))
+        ));
+    }
+}